@@ -0,0 +1,12 @@
+-- migrations/<timestamp>_create_refresh_tokens_table.sql
+
+CREATE TABLE refresh_tokens (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    expires_at BIGINT NOT NULL,
+    revoked_at BIGINT,
+    created_at BIGINT NOT NULL DEFAULT EXTRACT(EPOCH FROM now())
+);
+
+CREATE INDEX idx_refresh_tokens_user_id ON refresh_tokens(user_id);
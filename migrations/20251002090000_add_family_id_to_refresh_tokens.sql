@@ -0,0 +1,8 @@
+-- migrations/<timestamp>_add_family_id_to_refresh_tokens.sql
+
+-- Every refresh token issued by rotation shares the family id of the login that started it.
+ALTER TABLE refresh_tokens ADD COLUMN family_id UUID;
+UPDATE refresh_tokens SET family_id = id WHERE family_id IS NULL;
+ALTER TABLE refresh_tokens ALTER COLUMN family_id SET NOT NULL;
+
+CREATE INDEX idx_refresh_tokens_family_id ON refresh_tokens(family_id);
@@ -0,0 +1,14 @@
+-- migrations/<timestamp>_create_login_throttles_table.sql
+
+-- Failed login attempts per account (`scope = 'account'`, keyed by lower-cased email) and per
+-- source IP (`scope = 'ip'`). Rows are reset once `last_failure_at` falls outside the window.
+CREATE TABLE login_throttles (
+    scope VARCHAR(16) NOT NULL,
+    key VARCHAR(255) NOT NULL,
+    failures INTEGER NOT NULL DEFAULT 0,
+    last_failure_at BIGINT NOT NULL,
+    locked_until BIGINT,
+    PRIMARY KEY (scope, key)
+);
+
+CREATE INDEX idx_login_throttles_locked_until ON login_throttles(locked_until);
@@ -0,0 +1,12 @@
+-- migrations/<timestamp>_create_rate_limit_buckets_table.sql
+
+-- Token buckets for the Postgres rate limit store (`RATE_LIMIT_STORE=postgres`).
+-- `updated_at_ms` is in epoch milliseconds since buckets refill continuously.
+CREATE TABLE rate_limit_buckets (
+    key VARCHAR(255) PRIMARY KEY,
+    tokens DOUBLE PRECISION NOT NULL,
+    allowed BOOLEAN NOT NULL,
+    updated_at_ms BIGINT NOT NULL
+);
+
+CREATE INDEX idx_rate_limit_buckets_updated_at_ms ON rate_limit_buckets(updated_at_ms);
@@ -0,0 +1,18 @@
+-- migrations/<timestamp>_add_email_verification.sql
+
+-- NULL until the user follows a verification link. Existing users start unverified.
+ALTER TABLE users ADD COLUMN email_verified_at BIGINT;
+
+-- Verification links sent to users. Only the SHA-256 of the signed token's `jti` is stored;
+-- `email` pins the token to the address it was sent to.
+CREATE TABLE email_verification_tokens (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    email VARCHAR(255) NOT NULL,
+    expires_at BIGINT NOT NULL,
+    used_at BIGINT,
+    created_at BIGINT NOT NULL DEFAULT EXTRACT(EPOCH FROM now())
+);
+
+CREATE INDEX idx_email_verification_tokens_user_id ON email_verification_tokens(user_id);
@@ -0,0 +1,36 @@
+-- migrations/<timestamp>_create_mfa_tables.sql
+
+-- TOTP authenticators. The secret is encrypted with MFA_ENCRYPTION_KEY (AES-256-GCM,
+-- 12-byte nonce followed by the ciphertext). `enabled_at` stays NULL until the user confirms
+-- enrollment with a first code; `last_used_step` keeps a code from being accepted twice.
+CREATE TABLE user_totp (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    secret BYTEA NOT NULL,
+    enabled_at BIGINT,
+    last_used_step BIGINT,
+    created_at BIGINT NOT NULL DEFAULT EXTRACT(EPOCH FROM now())
+);
+
+-- Single-use recovery codes, stored as SHA-256 hashes.
+CREATE TABLE mfa_recovery_codes (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    code_hash VARCHAR(64) NOT NULL,
+    used_at BIGINT,
+    created_at BIGINT NOT NULL DEFAULT EXTRACT(EPOCH FROM now())
+);
+
+CREATE INDEX idx_mfa_recovery_codes_user_id ON mfa_recovery_codes(user_id);
+
+-- Pending second steps of a login, by SHA-256 of the challenge token's `jti`.
+CREATE TABLE mfa_challenges (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    expires_at BIGINT NOT NULL,
+    used_at BIGINT,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    created_at BIGINT NOT NULL DEFAULT EXTRACT(EPOCH FROM now())
+);
+
+CREATE INDEX idx_mfa_challenges_user_id ON mfa_challenges(user_id);
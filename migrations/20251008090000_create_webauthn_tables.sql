@@ -0,0 +1,29 @@
+-- migrations/<timestamp>_create_webauthn_tables.sql
+
+-- Passkeys registered by users. `public_key` is the COSE_Key from the authenticator;
+-- `sign_count` is the last signature counter seen, used to spot cloned authenticators.
+CREATE TABLE webauthn_credentials (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    credential_id BYTEA NOT NULL UNIQUE,
+    public_key BYTEA NOT NULL,
+    sign_count BIGINT NOT NULL DEFAULT 0,
+    transports TEXT[] NOT NULL DEFAULT '{}',
+    name VARCHAR(100) NOT NULL,
+    created_at BIGINT NOT NULL DEFAULT EXTRACT(EPOCH FROM now()),
+    last_used_at BIGINT
+);
+
+CREATE INDEX idx_webauthn_credentials_user_id ON webauthn_credentials(user_id);
+
+-- Open registration and login ceremonies, by SHA-256 of the challenge sent to the browser.
+-- `user_id` is set for registrations; logins find the user through the credential.
+CREATE TABLE webauthn_challenges (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    challenge_hash VARCHAR(64) NOT NULL UNIQUE,
+    ceremony VARCHAR(16) NOT NULL,
+    user_id UUID REFERENCES users(id) ON DELETE CASCADE,
+    expires_at BIGINT NOT NULL,
+    used_at BIGINT,
+    created_at BIGINT NOT NULL DEFAULT EXTRACT(EPOCH FROM now())
+);
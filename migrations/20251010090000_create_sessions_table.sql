@@ -0,0 +1,20 @@
+-- migrations/<timestamp>_create_sessions_table.sql
+
+-- One row per login, keyed by the refresh token family it started (`refresh_tokens.family_id`,
+-- the `sid` claim). A session is active while its family has an unrevoked, unexpired token.
+CREATE TABLE sessions (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    user_agent VARCHAR(512),
+    ip_address VARCHAR(45),
+    created_at BIGINT NOT NULL DEFAULT EXTRACT(EPOCH FROM now()),
+    last_used_at BIGINT NOT NULL DEFAULT EXTRACT(EPOCH FROM now())
+);
+
+CREATE INDEX idx_sessions_user_id ON sessions(user_id);
+
+-- Logins from before sessions were recorded, without client details.
+INSERT INTO sessions (id, user_id, created_at, last_used_at)
+SELECT family_id, user_id, MIN(created_at), MAX(created_at)
+FROM refresh_tokens
+GROUP BY family_id, user_id;
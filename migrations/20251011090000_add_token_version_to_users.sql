@@ -0,0 +1,5 @@
+-- migrations/<timestamp>_add_token_version_to_users.sql
+
+-- Embedded in access tokens as the `ver` claim. Bumping it rejects every access token issued
+-- before, e.g. on a password change or when the user is logged out everywhere.
+ALTER TABLE users ADD COLUMN token_version INTEGER NOT NULL DEFAULT 0;
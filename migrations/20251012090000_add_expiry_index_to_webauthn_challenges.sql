@@ -0,0 +1,7 @@
+-- migrations/<timestamp>_add_expiry_index_to_webauthn_challenges.sql
+
+-- Expired challenges are swept by `expires_at`. Answered challenges are deleted on the spot,
+-- so they no longer need `used_at`.
+CREATE INDEX idx_webauthn_challenges_expires_at ON webauthn_challenges(expires_at);
+
+ALTER TABLE webauthn_challenges DROP COLUMN used_at;
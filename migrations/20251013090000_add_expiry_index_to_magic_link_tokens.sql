@@ -0,0 +1,4 @@
+-- migrations/<timestamp>_add_expiry_index_to_magic_link_tokens.sql
+
+-- Expired links are swept by `expires_at`.
+CREATE INDEX idx_magic_link_tokens_expires_at ON magic_link_tokens(expires_at);
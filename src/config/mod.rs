@@ -0,0 +1,1288 @@
+use crate::services::keys::KeyRing;
+use crate::services::mailer::Outbox;
+use crate::services::password_hash::PasswordHasher;
+use crate::services::password_policy::PasswordPolicy;
+use base64::Engine;
+use chrono::Duration;
+use jsonwebtoken::Algorithm;
+use lettre::message::Mailbox;
+use serde::Deserialize;
+use std::env;
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use url::Url;
+
+// Application configuration, loaded once at startup.
+// Sources in order of precedence: environment variables, `<VAR>_FILE` (the variable's value
+// read from a file, for secrets mounted by Docker/Kubernetes), then the optional TOML file
+// named by `APP_CONFIG_FILE` (default `config.toml` when present).
+#[derive(Debug, Clone)]
+pub struct AppConfig {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub jwt: JwtConfig,
+    pub password: PasswordPolicyConfig,
+    pub argon2: Argon2Config,
+    pub login_throttle: LoginThrottleConfig,
+    pub rate_limit: RateLimitConfig,
+    pub email_verification: EmailVerificationConfig,
+    pub password_reset: PasswordResetConfig,
+    pub magic_link: MagicLinkConfig,
+    pub mail: MailConfig,
+    pub mfa: MfaConfig,
+    pub webauthn: WebauthnConfig,
+}
+
+/// What the server builds from the configuration before it starts, loaded by
+/// `AppConfig::load_with_resources` so their problems are reported with the configuration's.
+pub struct Resources {
+    pub keyring: KeyRing,
+    pub password_policy: PasswordPolicy,
+    pub password_hasher: PasswordHasher,
+    pub outbox: Outbox,
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub bind_address: SocketAddr,
+    // Take the client IP from `X-Forwarded-For` / `X-Real-IP`; only behind a trusted proxy
+    pub trust_proxy_headers: bool,
+    // Base URL of the web app, used to build the links sent by email
+    pub public_url: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct DatabaseConfig {
+    pub url: String,
+    pub max_connections: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct JwtConfig {
+    pub algorithm: Algorithm,
+    // HMAC algorithms only
+    pub secret: Option<String>,
+    pub previous_secrets: Vec<String>,
+    // Asymmetric algorithms: a single key pair or a keyring directory
+    pub private_key_path: Option<PathBuf>,
+    pub public_key_path: Option<PathBuf>,
+    pub keys_dir: Option<PathBuf>,
+    pub key_id: Option<String>,
+    pub access_token_expiry: Duration,
+    pub refresh_token_expiry: Duration,
+    pub issuer: String,
+    pub audience: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct PasswordPolicyConfig {
+    pub min_length: usize,
+    pub max_length: usize,
+    pub require_lowercase: bool,
+    pub require_uppercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+    // Minimum estimated strength, 0 (guessable) to 4 (very strong)
+    pub min_strength: u8,
+    // Reject passwords containing the username or the email's local part
+    pub disallow_user_info: bool,
+    // Local breached-password corpus: a directory of HIBP range files or a file of SHA-1 hashes
+    pub breached_passwords_path: Option<PathBuf>,
+    // Reject passwords seen at least this many times in the corpus
+    pub breached_min_count: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Argon2Config {
+    pub algorithm: argon2::Algorithm,
+    // Memory (KiB), iterations and lanes; hashes made with other values are upgraded on login
+    pub params: argon2::Params,
+    // Secret mixed into every hash and kept out of the database
+    pub pepper: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LoginThrottleConfig {
+    pub enabled: bool,
+    // Failures per account before it is locked out
+    pub max_failures: u32,
+    // Failures per source IP, across all accounts, before the IP is locked out
+    pub ip_max_failures: u32,
+    // Delay after the first failed attempt on an account, doubled with every further failure
+    pub backoff_base: Duration,
+    // Failures older than this are forgotten
+    pub failure_window: Duration,
+    pub lockout_duration: Duration,
+    // Cache active lockouts in memory to turn repeat attempts away without a query
+    pub in_memory: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    // Postgres shares the buckets between instances; memory is per process
+    pub store: RateLimitStoreKind,
+    // `/api/auth/*`
+    pub auth: RateLimitRule,
+    // Authenticated API routes (`/api/user/*`)
+    pub api: RateLimitRule,
+    // `X-API-Key` values that get their own bucket under `api_key`; others count by IP
+    pub api_keys: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct EmailVerificationConfig {
+    // Refuse to log in users whose email is not verified, and issue no tokens on register
+    pub required: bool,
+    pub token_expiry: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct PasswordResetConfig {
+    pub token_expiry: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct MagicLinkConfig {
+    // Off by default: a mailbox alone is then enough to log in
+    pub enabled: bool,
+    pub token_expiry: Duration,
+    // Only the client that asked for the link may redeem it
+    pub same_device: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct MfaConfig {
+    // Shown next to the account in authenticator apps
+    pub issuer: String,
+    // AES-256-GCM key for TOTP secrets at rest; enrollment is refused without it
+    pub encryption_key: Option<[u8; 32]>,
+    pub challenge_expiry: Duration,
+    // Wrong codes allowed per login challenge before the password must be entered again
+    pub max_attempts: i32,
+    pub recovery_codes: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct WebauthnConfig {
+    // Domain passkeys are bound to: the host of every origin, or a parent domain of it
+    pub rp_id: String,
+    // Shown by browsers and authenticators
+    pub rp_name: String,
+    // Pages allowed to run ceremonies, as `scheme://host[:port]`
+    pub origins: Vec<String>,
+    pub challenge_expiry: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct MailConfig {
+    pub transport: MailTransportKind,
+    pub from: Mailbox,
+    // `<locale>/<template>.{subject.txt,txt,html}`
+    pub templates_dir: PathBuf,
+    // Used when none of the recipient's languages has a template
+    pub default_locale: String,
+    // File transport only: where `.eml` files are written
+    pub file_dir: PathBuf,
+    // SMTP transport only
+    pub smtp: Option<SmtpConfig>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MailTransportKind {
+    Log,
+    File,
+    Smtp,
+}
+
+#[derive(Debug, Clone)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub tls: SmtpTls,
+}
+
+/// `Tls` connects over TLS (usually port 465); `StartTls` upgrades a plain connection
+/// (usually 587); `None` is for local relays and test sinks only.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpTls {
+    None,
+    StartTls,
+    Tls,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitStoreKind {
+    Memory,
+    Postgres,
+}
+
+/// Token bucket of `capacity` requests, refilled evenly over `period`.
+#[derive(Debug, Clone)]
+pub struct RateLimitRule {
+    pub capacity: u32,
+    pub period: Duration,
+    pub key: RateLimitKey,
+}
+
+/// What a bucket belongs to. `User` and `ApiKey` fall back to the client IP when the request
+/// carries no valid access token or no `X-API-Key` listed in `RATE_LIMIT_API_KEYS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitKey {
+    Ip,
+    User,
+    ApiKey,
+}
+
+impl FromStr for RateLimitStoreKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "memory" => Ok(Self::Memory),
+            "postgres" => Ok(Self::Postgres),
+            _ => Err(()),
+        }
+    }
+}
+
+impl FromStr for MailTransportKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "log" => Ok(Self::Log),
+            "file" => Ok(Self::File),
+            "smtp" => Ok(Self::Smtp),
+            _ => Err(()),
+        }
+    }
+}
+
+impl FromStr for SmtpTls {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "starttls" => Ok(Self::StartTls),
+            "tls" => Ok(Self::Tls),
+            _ => Err(()),
+        }
+    }
+}
+
+impl FromStr for RateLimitKey {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ip" => Ok(Self::Ip),
+            "user" => Ok(Self::User),
+            "api_key" => Ok(Self::ApiKey),
+            _ => Err(()),
+        }
+    }
+}
+
+// Longest password accepted by the request DTOs.
+pub const PASSWORD_MAX_LENGTH_LIMIT: usize = 1024;
+
+/// Every problem found while loading the configuration, so they can be fixed in one go.
+#[derive(Debug)]
+pub struct ConfigError(pub Vec<String>);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Invalid configuration:")?;
+        for error in &self.0 {
+            writeln!(f, "  - {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+// Shape of the optional TOML file. Every field may be overridden by its environment variable.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileConfig {
+    server: FileServerConfig,
+    database: FileDatabaseConfig,
+    jwt: FileJwtConfig,
+    password: FilePasswordPolicyConfig,
+    argon2: FileArgon2Config,
+    login_throttle: FileLoginThrottleConfig,
+    rate_limit: FileRateLimitConfig,
+    email_verification: FileEmailVerificationConfig,
+    password_reset: FilePasswordResetConfig,
+    magic_link: FileMagicLinkConfig,
+    mail: FileMailConfig,
+    mfa: FileMfaConfig,
+    webauthn: FileWebauthnConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileServerConfig {
+    bind_address: Option<String>,
+    trust_proxy_headers: Option<bool>,
+    public_url: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileDatabaseConfig {
+    url: Option<String>,
+    max_connections: Option<u32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileJwtConfig {
+    algorithm: Option<String>,
+    secret: Option<String>,
+    previous_secrets: Option<Vec<String>>,
+    private_key_path: Option<String>,
+    public_key_path: Option<String>,
+    keys_dir: Option<String>,
+    key_id: Option<String>,
+    access_token_expiry: Option<String>,
+    refresh_token_expiry: Option<String>,
+    issuer: Option<String>,
+    audience: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FilePasswordPolicyConfig {
+    min_length: Option<usize>,
+    max_length: Option<usize>,
+    require_lowercase: Option<bool>,
+    require_uppercase: Option<bool>,
+    require_digit: Option<bool>,
+    require_symbol: Option<bool>,
+    min_strength: Option<u8>,
+    disallow_user_info: Option<bool>,
+    breached_passwords_path: Option<String>,
+    breached_min_count: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileArgon2Config {
+    algorithm: Option<String>,
+    memory_cost: Option<u32>,
+    time_cost: Option<u32>,
+    parallelism: Option<u32>,
+    pepper: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileLoginThrottleConfig {
+    enabled: Option<bool>,
+    max_failures: Option<u32>,
+    ip_max_failures: Option<u32>,
+    backoff_base: Option<String>,
+    failure_window: Option<String>,
+    lockout_duration: Option<String>,
+    in_memory: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileRateLimitConfig {
+    enabled: Option<bool>,
+    store: Option<String>,
+    auth: FileRateLimitRule,
+    api: FileRateLimitRule,
+    api_keys: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileRateLimitRule {
+    limit: Option<String>,
+    key: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileEmailVerificationConfig {
+    required: Option<bool>,
+    token_expiry: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FilePasswordResetConfig {
+    token_expiry: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileMagicLinkConfig {
+    enabled: Option<bool>,
+    token_expiry: Option<String>,
+    same_device: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileMfaConfig {
+    issuer: Option<String>,
+    encryption_key: Option<String>,
+    challenge_expiry: Option<String>,
+    max_attempts: Option<i32>,
+    recovery_codes: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileWebauthnConfig {
+    rp_id: Option<String>,
+    rp_name: Option<String>,
+    origins: Option<Vec<String>>,
+    challenge_expiry: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileMailConfig {
+    transport: Option<String>,
+    from: Option<String>,
+    templates_dir: Option<String>,
+    default_locale: Option<String>,
+    file_dir: Option<String>,
+    smtp: FileSmtpConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileSmtpConfig {
+    host: Option<String>,
+    port: Option<u16>,
+    username: Option<String>,
+    password: Option<String>,
+    tls: Option<String>,
+}
+
+impl AppConfig {
+    /// The configuration alone, for CLI commands that do not start the server.
+    pub fn load() -> Result<Self, ConfigError> {
+        Self::load_sections(false).map(|(config, _)| config)
+    }
+
+    /// The configuration plus the JWT keys, password policy and hasher and mail outbox built
+    /// from it. Every problem with any of them is reported in one `ConfigError`.
+    pub fn load_with_resources() -> Result<(Self, Resources), ConfigError> {
+        let (config, resources) = Self::load_sections(true)?;
+        Ok((
+            config,
+            resources.expect("resources are built whenever the configuration is valid"),
+        ))
+    }
+
+    fn load_sections(with_resources: bool) -> Result<(Self, Option<Resources>), ConfigError> {
+        let mut loader = Loader::default();
+        let file = loader.read_file();
+
+        let bind_address = loader
+            .value("BIND_ADDRESS", file.server.bind_address)
+            .unwrap_or_else(|| "127.0.0.1:3000".to_string());
+        let bind_address = loader.parse("BIND_ADDRESS", &bind_address);
+        let trust_proxy_headers = loader.parsed_or(
+            "TRUST_PROXY_HEADERS",
+            file.server.trust_proxy_headers,
+            false,
+        );
+        let public_url = loader
+            .value("PUBLIC_URL", file.server.public_url)
+            .unwrap_or_else(|| "http://127.0.0.1:3000".to_string());
+        if !public_url.starts_with("http://") && !public_url.starts_with("https://") {
+            loader.error(format!("PUBLIC_URL must be an http(s) URL: {}", public_url));
+        }
+        let public_url = public_url.trim_end_matches('/').to_string();
+
+        let database_url = loader.required("DATABASE_URL", file.database.url);
+        let max_connections = loader
+            .value(
+                "DATABASE_MAX_CONNECTIONS",
+                file.database.max_connections.map(|n| n.to_string()),
+            )
+            .and_then(|n| loader.parse::<u32>("DATABASE_MAX_CONNECTIONS", &n))
+            .unwrap_or(5);
+        if max_connections == 0 {
+            loader.error("DATABASE_MAX_CONNECTIONS must be greater than 0".to_string());
+        }
+
+        let jwt_errors = loader.errors.len();
+        let algorithm = loader
+            .value("JWT_ALGORITHM", file.jwt.algorithm)
+            .unwrap_or_else(|| "HS256".to_string());
+        let algorithm = loader.parse::<Algorithm>("JWT_ALGORITHM", &algorithm);
+        let secret = loader.value("JWT_SECRET", file.jwt.secret);
+        let previous_secrets = loader
+            .value(
+                "JWT_PREVIOUS_SECRETS",
+                file.jwt.previous_secrets.map(|s| s.join(",")),
+            )
+            .map(|s| {
+                s.split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let private_key_path = loader
+            .value("JWT_PRIVATE_KEY_PATH", file.jwt.private_key_path)
+            .map(PathBuf::from);
+        let public_key_path = loader
+            .value("JWT_PUBLIC_KEY_PATH", file.jwt.public_key_path)
+            .map(PathBuf::from);
+        let keys_dir = loader
+            .value("JWT_KEYS_DIR", file.jwt.keys_dir)
+            .map(PathBuf::from);
+        let key_id = loader
+            .value("JWT_KEY_ID", file.jwt.key_id)
+            .filter(|k| !k.is_empty());
+
+        if let Some(algorithm) = algorithm {
+            if is_hmac(algorithm) {
+                if secret.is_none() {
+                    loader.error(format!("JWT_SECRET must be set for {:?}", algorithm));
+                }
+            } else if keys_dir.is_none()
+                && (private_key_path.is_none() || public_key_path.is_none())
+            {
+                loader.error(format!(
+                    "JWT_KEYS_DIR or both JWT_PRIVATE_KEY_PATH and JWT_PUBLIC_KEY_PATH must be set for {:?}",
+                    algorithm
+                ));
+            }
+        }
+
+        let access_token_expiry = loader
+            .required("JWT_ACCESS_TOKEN_EXPIRY", file.jwt.access_token_expiry)
+            .and_then(|s| loader.duration("JWT_ACCESS_TOKEN_EXPIRY", &s));
+        let refresh_token_expiry = loader
+            .required("JWT_REFRESH_TOKEN_EXPIRY", file.jwt.refresh_token_expiry)
+            .and_then(|s| loader.duration("JWT_REFRESH_TOKEN_EXPIRY", &s));
+        let issuer = loader
+            .value("JWT_ISSUER", file.jwt.issuer)
+            .unwrap_or_else(|| "rust-axum-pg-boilerplate".to_string());
+        let audience = loader
+            .value("JWT_AUDIENCE", file.jwt.audience)
+            .unwrap_or_else(|| "rust-axum-pg-boilerplate".to_string());
+        let jwt = match (algorithm, access_token_expiry, refresh_token_expiry) {
+            (Some(algorithm), Some(access_token_expiry), Some(refresh_token_expiry)) => {
+                Some(JwtConfig {
+                    algorithm,
+                    secret,
+                    previous_secrets,
+                    private_key_path,
+                    public_key_path,
+                    keys_dir,
+                    key_id,
+                    access_token_expiry,
+                    refresh_token_expiry,
+                    issuer,
+                    audience,
+                })
+            }
+            _ => None,
+        };
+        let jwt_valid = loader.errors.len() == jwt_errors;
+
+        let password_errors = loader.errors.len();
+        let password = loader.password_policy(file.password);
+        let password_valid = loader.errors.len() == password_errors;
+        let argon2 = loader.argon2(file.argon2);
+        let login_throttle = loader.login_throttle(file.login_throttle);
+        let rate_limit = loader.rate_limit(file.rate_limit);
+        let email_verification = loader.email_verification(file.email_verification);
+        let password_reset = loader.password_reset(file.password_reset);
+        let magic_link = loader.magic_link(file.magic_link);
+        let mail_errors = loader.errors.len();
+        let mail = loader.mail(file.mail);
+        let mail_valid = loader.errors.len() == mail_errors;
+        let mfa = loader.mfa(file.mfa);
+        let webauthn = loader.webauthn(file.webauthn, &public_url);
+
+        // Built only from sections without errors of their own, so nothing is reported twice.
+        let resources = if with_resources {
+            let keyring = jwt
+                .as_ref()
+                .filter(|_| jwt_valid)
+                .and_then(|jwt| loader.build("Invalid JWT key configuration", KeyRing::load(jwt)));
+            let password_policy = if password_valid {
+                loader.build(
+                    "Invalid password policy",
+                    PasswordPolicy::from_config(&password),
+                )
+            } else {
+                None
+            };
+            let password_hasher = argon2.as_ref().and_then(|argon2| {
+                loader.build(
+                    "Invalid Argon2 configuration",
+                    PasswordHasher::from_config(argon2),
+                )
+            });
+            let outbox = mail.as_ref().filter(|_| mail_valid).and_then(|mail| {
+                loader.build("Invalid mail configuration", Outbox::from_config(mail))
+            });
+            match (keyring, password_policy, password_hasher, outbox) {
+                (Some(keyring), Some(password_policy), Some(password_hasher), Some(outbox)) => {
+                    Some(Resources {
+                        keyring,
+                        password_policy,
+                        password_hasher,
+                        outbox,
+                    })
+                }
+                _ => None,
+            }
+        } else {
+            None
+        };
+
+        match (
+            bind_address,
+            database_url,
+            jwt,
+            argon2,
+            login_throttle,
+            rate_limit,
+            email_verification,
+            password_reset,
+            magic_link,
+            mail,
+            mfa,
+            webauthn,
+        ) {
+            (
+                Some(bind_address),
+                Some(url),
+                Some(jwt),
+                Some(argon2),
+                Some(login_throttle),
+                Some(rate_limit),
+                Some(email_verification),
+                Some(password_reset),
+                Some(magic_link),
+                Some(mail),
+                Some(mfa),
+                Some(webauthn),
+            ) if loader.errors.is_empty() => Ok((
+                Self {
+                    server: ServerConfig {
+                        bind_address,
+                        trust_proxy_headers,
+                        public_url,
+                    },
+                    database: DatabaseConfig {
+                        url,
+                        max_connections,
+                    },
+                    jwt,
+                    password,
+                    argon2,
+                    login_throttle,
+                    rate_limit,
+                    email_verification,
+                    password_reset,
+                    magic_link,
+                    mail,
+                    mfa,
+                    webauthn,
+                },
+                resources,
+            )),
+            _ => Err(ConfigError(loader.errors)),
+        }
+    }
+}
+
+pub fn is_hmac(algorithm: Algorithm) -> bool {
+    matches!(
+        algorithm,
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+    )
+}
+
+// Collects errors instead of returning on the first one.
+#[derive(Default)]
+struct Loader {
+    errors: Vec<String>,
+}
+
+impl Loader {
+    fn error(&mut self, message: String) {
+        self.errors.push(message);
+    }
+
+    // Keeps what a constructor built from the configuration, or records why it failed.
+    fn build<T>(&mut self, context: &str, result: Result<T, String>) -> Option<T> {
+        result
+            .map_err(|e| self.error(format!("{}: {}", context, e)))
+            .ok()
+    }
+
+    fn read_file(&mut self) -> FileConfig {
+        let (path, explicit) = match env::var("APP_CONFIG_FILE") {
+            Ok(path) => (PathBuf::from(path), true),
+            Err(_) => (PathBuf::from("config.toml"), false),
+        };
+        if !explicit && !Path::new(&path).exists() {
+            return FileConfig::default();
+        }
+
+        let parsed = std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|content| toml::from_str(&content).map_err(|e| e.to_string()));
+        parsed.unwrap_or_else(|e| {
+            self.error(format!(
+                "Failed to load config file {}: {}",
+                path.display(),
+                e
+            ));
+            FileConfig::default()
+        })
+    }
+
+    fn value(&mut self, var: &str, file_value: Option<String>) -> Option<String> {
+        if let Ok(value) = env::var(var) {
+            return Some(value);
+        }
+        let file_var = format!("{}_FILE", var);
+        if let Ok(path) = env::var(&file_var) {
+            return match std::fs::read_to_string(&path) {
+                Ok(value) => Some(value.trim_end_matches(['\r', '\n']).to_string()),
+                Err(e) => {
+                    self.error(format!("{} ({}) could not be read: {}", file_var, path, e));
+                    None
+                }
+            };
+        }
+        file_value
+    }
+
+    fn required(&mut self, var: &str, file_value: Option<String>) -> Option<String> {
+        let value = self.value(var, file_value);
+        if value.is_none() {
+            self.error(format!("{} must be set", var));
+        }
+        value
+    }
+
+    fn parse<T: FromStr>(&mut self, var: &str, value: &str) -> Option<T> {
+        match value.trim().parse() {
+            Ok(parsed) => Some(parsed),
+            Err(_) => {
+                self.error(format!("{} has an invalid value: {}", var, value));
+                None
+            }
+        }
+    }
+
+    // `var` parsed as `T` (env, `_FILE` or the TOML value), falling back to `default`.
+    fn parsed_or<T: FromStr + ToString>(
+        &mut self,
+        var: &str,
+        file_value: Option<T>,
+        default: T,
+    ) -> T {
+        self.value(var, file_value.map(|v| v.to_string()))
+            .and_then(|v| self.parse(var, &v))
+            .unwrap_or(default)
+    }
+
+    fn password_policy(&mut self, file: FilePasswordPolicyConfig) -> PasswordPolicyConfig {
+        let min_length = self.parsed_or("PASSWORD_MIN_LENGTH", file.min_length, 8);
+        let max_length = self.parsed_or("PASSWORD_MAX_LENGTH", file.max_length, 128);
+        let min_strength = self.parsed_or("PASSWORD_MIN_STRENGTH", file.min_strength, 2);
+
+        if min_length == 0 || min_length > max_length {
+            self.error("PASSWORD_MIN_LENGTH must be between 1 and PASSWORD_MAX_LENGTH".to_string());
+        }
+        if max_length > PASSWORD_MAX_LENGTH_LIMIT {
+            self.error(format!(
+                "PASSWORD_MAX_LENGTH must be at most {}",
+                PASSWORD_MAX_LENGTH_LIMIT
+            ));
+        }
+        let breached_passwords_path = self
+            .value("PASSWORD_BREACHED_PATH", file.breached_passwords_path)
+            .filter(|p| !p.is_empty())
+            .map(PathBuf::from);
+        if let Some(path) = &breached_passwords_path {
+            if !path.exists() {
+                self.error(format!(
+                    "PASSWORD_BREACHED_PATH ({}) does not exist",
+                    path.display()
+                ));
+            }
+        }
+        let breached_min_count =
+            self.parsed_or("PASSWORD_BREACHED_MIN_COUNT", file.breached_min_count, 1);
+        if breached_min_count == 0 {
+            self.error("PASSWORD_BREACHED_MIN_COUNT must be greater than 0".to_string());
+        }
+
+        if min_strength > 4 {
+            self.error("PASSWORD_MIN_STRENGTH must be between 0 and 4".to_string());
+        }
+
+        PasswordPolicyConfig {
+            min_length,
+            max_length,
+            require_lowercase: self.parsed_or(
+                "PASSWORD_REQUIRE_LOWERCASE",
+                file.require_lowercase,
+                false,
+            ),
+            require_uppercase: self.parsed_or(
+                "PASSWORD_REQUIRE_UPPERCASE",
+                file.require_uppercase,
+                false,
+            ),
+            require_digit: self.parsed_or("PASSWORD_REQUIRE_DIGIT", file.require_digit, false),
+            require_symbol: self.parsed_or("PASSWORD_REQUIRE_SYMBOL", file.require_symbol, false),
+            min_strength,
+            disallow_user_info: self.parsed_or(
+                "PASSWORD_DISALLOW_USER_INFO",
+                file.disallow_user_info,
+                true,
+            ),
+            breached_passwords_path,
+            breached_min_count,
+        }
+    }
+
+    fn argon2(&mut self, file: FileArgon2Config) -> Option<Argon2Config> {
+        let algorithm = self
+            .value("ARGON2_ALGORITHM", file.algorithm)
+            .unwrap_or_else(|| "argon2id".to_string());
+        let algorithm = self.parse::<argon2::Algorithm>("ARGON2_ALGORITHM", &algorithm);
+        let memory_cost = self.parsed_or(
+            "ARGON2_MEMORY_COST",
+            file.memory_cost,
+            argon2::Params::DEFAULT_M_COST,
+        );
+        let time_cost = self.parsed_or(
+            "ARGON2_TIME_COST",
+            file.time_cost,
+            argon2::Params::DEFAULT_T_COST,
+        );
+        let parallelism = self.parsed_or(
+            "ARGON2_PARALLELISM",
+            file.parallelism,
+            argon2::Params::DEFAULT_P_COST,
+        );
+        let params = argon2::Params::new(memory_cost, time_cost, parallelism, None)
+            .map_err(|e| self.error(format!("Invalid Argon2 parameters: {}", e)))
+            .ok();
+        let pepper = self
+            .value("PASSWORD_PEPPER", file.pepper)
+            .filter(|p| !p.is_empty());
+
+        Some(Argon2Config {
+            algorithm: algorithm?,
+            params: params?,
+            pepper,
+        })
+    }
+
+    fn login_throttle(&mut self, file: FileLoginThrottleConfig) -> Option<LoginThrottleConfig> {
+        let max_failures = self.parsed_or("LOGIN_MAX_FAILURES", file.max_failures, 5);
+        let ip_max_failures = self.parsed_or("LOGIN_IP_MAX_FAILURES", file.ip_max_failures, 50);
+        if max_failures == 0 || ip_max_failures == 0 {
+            self.error(
+                "LOGIN_MAX_FAILURES and LOGIN_IP_MAX_FAILURES must be greater than 0".to_string(),
+            );
+        }
+        let backoff_base = self
+            .value("LOGIN_BACKOFF_BASE", file.backoff_base)
+            .unwrap_or_else(|| "1s".to_string());
+        let failure_window = self
+            .value("LOGIN_FAILURE_WINDOW", file.failure_window)
+            .unwrap_or_else(|| "15m".to_string());
+        let lockout_duration = self
+            .value("LOGIN_LOCKOUT_DURATION", file.lockout_duration)
+            .unwrap_or_else(|| "15m".to_string());
+        let backoff_base = self.duration("LOGIN_BACKOFF_BASE", &backoff_base);
+        let failure_window = self.duration("LOGIN_FAILURE_WINDOW", &failure_window);
+        let lockout_duration = self.duration("LOGIN_LOCKOUT_DURATION", &lockout_duration);
+
+        Some(LoginThrottleConfig {
+            enabled: self.parsed_or("LOGIN_THROTTLE_ENABLED", file.enabled, true),
+            max_failures,
+            ip_max_failures,
+            backoff_base: backoff_base?,
+            failure_window: failure_window?,
+            lockout_duration: lockout_duration?,
+            in_memory: self.parsed_or("LOGIN_THROTTLE_IN_MEMORY", file.in_memory, false),
+        })
+    }
+
+    fn rate_limit(&mut self, file: FileRateLimitConfig) -> Option<RateLimitConfig> {
+        let store = self
+            .value("RATE_LIMIT_STORE", file.store)
+            .unwrap_or_else(|| "memory".to_string());
+        let store = self.parse::<RateLimitStoreKind>("RATE_LIMIT_STORE", &store);
+        let auth = self.rate_limit_rule("RATE_LIMIT_AUTH", file.auth, "20/1m", "ip");
+        let api = self.rate_limit_rule("RATE_LIMIT_API", file.api, "120/1m", "user");
+        let api_keys: Vec<String> = self
+            .value("RATE_LIMIT_API_KEYS", file.api_keys.map(|k| k.join(",")))
+            .map(|s| {
+                s.split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let keyed_by_api_key = [&auth, &api]
+            .into_iter()
+            .any(|rule| rule.as_ref().is_some_and(|r| r.key == RateLimitKey::ApiKey));
+        if keyed_by_api_key && api_keys.is_empty() {
+            self.error(
+                "RATE_LIMIT_API_KEYS must be set when a route group is keyed by api_key"
+                    .to_string(),
+            );
+        }
+
+        Some(RateLimitConfig {
+            enabled: self.parsed_or("RATE_LIMIT_ENABLED", file.enabled, true),
+            store: store?,
+            auth: auth?,
+            api: api?,
+            api_keys,
+        })
+    }
+
+    // `<var>` is `<capacity>/<period>`, e.g. `20/1m`; `<var>_KEY` is `ip`, `user` or `api_key`.
+    fn rate_limit_rule(
+        &mut self,
+        var: &str,
+        file: FileRateLimitRule,
+        default_limit: &str,
+        default_key: &str,
+    ) -> Option<RateLimitRule> {
+        let key_var = format!("{}_KEY", var);
+        let key = self
+            .value(&key_var, file.key)
+            .unwrap_or_else(|| default_key.to_string());
+        let key = self.parse::<RateLimitKey>(&key_var, &key);
+
+        let limit = self
+            .value(var, file.limit)
+            .unwrap_or_else(|| default_limit.to_string());
+        let Some((capacity, period)) = limit.split_once('/') else {
+            self.error(format!(
+                "{} must look like <requests>/<period>, e.g. 20/1m",
+                var
+            ));
+            return None;
+        };
+        let capacity = self.parse::<u32>(var, capacity);
+        if capacity == Some(0) {
+            self.error(format!("{} must allow at least one request", var));
+        }
+        let period = self.duration(var, period);
+
+        Some(RateLimitRule {
+            capacity: capacity.filter(|c| *c > 0)?,
+            period: period?,
+            key: key?,
+        })
+    }
+
+    fn email_verification(
+        &mut self,
+        file: FileEmailVerificationConfig,
+    ) -> Option<EmailVerificationConfig> {
+        let token_expiry = self
+            .value("EMAIL_VERIFICATION_TOKEN_EXPIRY", file.token_expiry)
+            .unwrap_or_else(|| "24h".to_string());
+        let token_expiry = self.duration("EMAIL_VERIFICATION_TOKEN_EXPIRY", &token_expiry);
+
+        Some(EmailVerificationConfig {
+            required: self.parsed_or("EMAIL_VERIFICATION_REQUIRED", file.required, false),
+            token_expiry: token_expiry?,
+        })
+    }
+
+    fn password_reset(&mut self, file: FilePasswordResetConfig) -> Option<PasswordResetConfig> {
+        let token_expiry = self
+            .value("PASSWORD_RESET_TOKEN_EXPIRY", file.token_expiry)
+            .unwrap_or_else(|| "1h".to_string());
+
+        Some(PasswordResetConfig {
+            token_expiry: self.duration("PASSWORD_RESET_TOKEN_EXPIRY", &token_expiry)?,
+        })
+    }
+
+    fn magic_link(&mut self, file: FileMagicLinkConfig) -> Option<MagicLinkConfig> {
+        let token_expiry = self
+            .value("MAGIC_LINK_TOKEN_EXPIRY", file.token_expiry)
+            .unwrap_or_else(|| "15m".to_string());
+
+        Some(MagicLinkConfig {
+            enabled: self.parsed_or("MAGIC_LINK_ENABLED", file.enabled, false),
+            token_expiry: self.duration("MAGIC_LINK_TOKEN_EXPIRY", &token_expiry)?,
+            same_device: self.parsed_or("MAGIC_LINK_SAME_DEVICE", file.same_device, false),
+        })
+    }
+
+    fn mfa(&mut self, file: FileMfaConfig) -> Option<MfaConfig> {
+        let issuer = self
+            .value("MFA_ISSUER", file.issuer)
+            .unwrap_or_else(|| "rust-axum-pg-boilerplate".to_string());
+        // `issuer:account` is how authenticator apps label the entry.
+        if issuer.is_empty() || issuer.contains(':') {
+            self.error("MFA_ISSUER must be non-empty and must not contain ':'".to_string());
+        }
+        let encryption_key = self
+            .value("MFA_ENCRYPTION_KEY", file.encryption_key)
+            .filter(|k| !k.is_empty())
+            .and_then(|k| {
+                let key = base64::engine::general_purpose::STANDARD
+                    .decode(k.trim())
+                    .ok()
+                    .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok());
+                if key.is_none() {
+                    self.error("MFA_ENCRYPTION_KEY must be 32 bytes, base64-encoded".to_string());
+                }
+                key
+            });
+        let challenge_expiry = self
+            .value("MFA_CHALLENGE_EXPIRY", file.challenge_expiry)
+            .unwrap_or_else(|| "5m".to_string());
+        let challenge_expiry = self.duration("MFA_CHALLENGE_EXPIRY", &challenge_expiry);
+        let max_attempts = self.parsed_or("MFA_MAX_ATTEMPTS", file.max_attempts, 5);
+        if max_attempts <= 0 {
+            self.error("MFA_MAX_ATTEMPTS must be greater than 0".to_string());
+        }
+        let recovery_codes = self.parsed_or("MFA_RECOVERY_CODES", file.recovery_codes, 10);
+        if !(1..=100).contains(&recovery_codes) {
+            self.error("MFA_RECOVERY_CODES must be between 1 and 100".to_string());
+        }
+
+        Some(MfaConfig {
+            issuer,
+            encryption_key,
+            challenge_expiry: challenge_expiry?,
+            max_attempts,
+            recovery_codes,
+        })
+    }
+
+    fn webauthn(&mut self, file: FileWebauthnConfig, public_url: &str) -> Option<WebauthnConfig> {
+        let public_url = Url::parse(public_url).ok();
+        let origins: Vec<String> = self
+            .value("WEBAUTHN_ORIGINS", file.origins.map(|o| o.join(",")))
+            .map(|s| {
+                s.split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .or_else(|| {
+                public_url
+                    .as_ref()
+                    .map(|url| vec![url.origin().ascii_serialization()])
+            })
+            .unwrap_or_default();
+        let rp_id = self
+            .value("WEBAUTHN_RP_ID", file.rp_id)
+            .or_else(|| {
+                public_url
+                    .as_ref()
+                    .and_then(Url::host_str)
+                    .map(str::to_string)
+            })
+            .unwrap_or_default();
+        if rp_id.is_empty() {
+            self.error("WEBAUTHN_RP_ID must be set".to_string());
+        }
+        let rp_name = self
+            .value("WEBAUTHN_RP_NAME", file.rp_name)
+            .unwrap_or_else(|| "rust-axum-pg-boilerplate".to_string());
+
+        if origins.is_empty() {
+            self.error("WEBAUTHN_ORIGINS must list at least one origin".to_string());
+        }
+        for origin in &origins {
+            let host = Url::parse(origin)
+                .ok()
+                .filter(|url| matches!(url.scheme(), "http" | "https"))
+                .filter(|url| url.origin().ascii_serialization() == *origin)
+                .and_then(|url| url.host_str().map(str::to_string));
+            match host {
+                None => self.error(format!(
+                    "WEBAUTHN_ORIGINS entries must look like https://host[:port]: {}",
+                    origin
+                )),
+                Some(host) if host != rp_id && !host.ends_with(&format!(".{}", rp_id)) => {
+                    self.error(format!(
+                        "WEBAUTHN_ORIGINS entry {} is not on WEBAUTHN_RP_ID ({}) or a subdomain of it",
+                        origin, rp_id
+                    ))
+                }
+                Some(_) => {}
+            }
+        }
+
+        let challenge_expiry = self
+            .value("WEBAUTHN_CHALLENGE_EXPIRY", file.challenge_expiry)
+            .unwrap_or_else(|| "5m".to_string());
+
+        Some(WebauthnConfig {
+            rp_id,
+            rp_name,
+            origins,
+            challenge_expiry: self.duration("WEBAUTHN_CHALLENGE_EXPIRY", &challenge_expiry)?,
+        })
+    }
+
+    fn mail(&mut self, file: FileMailConfig) -> Option<MailConfig> {
+        let transport = self
+            .value("MAIL_TRANSPORT", file.transport)
+            .unwrap_or_else(|| "log".to_string());
+        let transport = self.parse::<MailTransportKind>("MAIL_TRANSPORT", &transport);
+        let from = self
+            .value("MAIL_FROM", file.from)
+            .unwrap_or_else(|| "no-reply@localhost".to_string());
+        let from = self.parse::<Mailbox>("MAIL_FROM", &from);
+
+        let templates_dir = PathBuf::from(
+            self.value("MAIL_TEMPLATES_DIR", file.templates_dir)
+                .unwrap_or_else(|| "templates/email".to_string()),
+        );
+        if !templates_dir.is_dir() {
+            self.error(format!(
+                "MAIL_TEMPLATES_DIR ({}) is not a directory",
+                templates_dir.display()
+            ));
+        }
+        let default_locale = self
+            .value("MAIL_DEFAULT_LOCALE", file.default_locale)
+            .unwrap_or_else(|| "en".to_string());
+        let file_dir = PathBuf::from(
+            self.value("MAIL_FILE_DIR", file.file_dir)
+                .unwrap_or_else(|| "mail".to_string()),
+        );
+
+        let smtp = match transport {
+            Some(MailTransportKind::Smtp) => Some(self.smtp(file.smtp)?),
+            _ => None,
+        };
+
+        Some(MailConfig {
+            transport: transport?,
+            from: from?,
+            templates_dir,
+            default_locale,
+            file_dir,
+            smtp,
+        })
+    }
+
+    fn smtp(&mut self, file: FileSmtpConfig) -> Option<SmtpConfig> {
+        let host = self.required("SMTP_HOST", file.host);
+        let tls = self
+            .value("SMTP_TLS", file.tls)
+            .unwrap_or_else(|| "starttls".to_string());
+        let tls = self.parse::<SmtpTls>("SMTP_TLS", &tls);
+        let default_port = match tls {
+            Some(SmtpTls::Tls) => 465,
+            Some(SmtpTls::StartTls) => 587,
+            _ => 25,
+        };
+        let port = self.parsed_or("SMTP_PORT", file.port, default_port);
+        let username = self
+            .value("SMTP_USERNAME", file.username)
+            .filter(|u| !u.is_empty());
+        let password = self.value("SMTP_PASSWORD", file.password);
+        if username.is_some() && password.is_none() {
+            self.error("SMTP_PASSWORD must be set when SMTP_USERNAME is".to_string());
+        }
+
+        Some(SmtpConfig {
+            host: host?,
+            port,
+            username,
+            password,
+            tls: tls?,
+        })
+    }
+
+    fn duration(&mut self, var: &str, value: &str) -> Option<Duration> {
+        match parse_duration(value) {
+            Ok(duration) if duration > Duration::zero() => Some(duration),
+            Ok(_) => {
+                self.error(format!("{} must be greater than zero", var));
+                None
+            }
+            Err(e) => {
+                self.error(format!("{} has an invalid value {:?}: {}", var, value, e));
+                None
+            }
+        }
+    }
+}
+
+// Accepted formats: Xs (seconds), Xm (minutes), Xh (hours), Xd (days)
+fn parse_duration(s: &str) -> Result<Duration, &'static str> {
+    let s = s.trim().to_lowercase();
+    if let Some(stripped) = s.strip_suffix('s') {
+        let seconds: i64 = stripped.parse().map_err(|_| "Invalid seconds")?;
+        Duration::try_seconds(seconds).ok_or("is out of range")
+    } else if let Some(stripped) = s.strip_suffix('m') {
+        let minutes: i64 = stripped.parse().map_err(|_| "Invalid minutes")?;
+        Duration::try_minutes(minutes).ok_or("is out of range")
+    } else if let Some(stripped) = s.strip_suffix('h') {
+        let hours: i64 = stripped.parse().map_err(|_| "Invalid hours")?;
+        Duration::try_hours(hours).ok_or("is out of range")
+    } else if let Some(stripped) = s.strip_suffix('d') {
+        let days: i64 = stripped.parse().map_err(|_| "Invalid days")?;
+        Duration::try_days(days).ok_or("is out of range")
+    } else {
+        Err("Unknown duration format")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations_in_each_unit() {
+        assert_eq!(parse_duration("90s"), Ok(Duration::seconds(90)));
+        assert_eq!(parse_duration(" 15M "), Ok(Duration::minutes(15)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::hours(2)));
+        assert_eq!(parse_duration("7d"), Ok(Duration::days(7)));
+        assert_eq!(parse_duration("7"), Err("Unknown duration format"));
+        assert_eq!(parse_duration("xd"), Err("Invalid days"));
+    }
+
+    #[test]
+    fn rejects_durations_out_of_range() {
+        assert_eq!(
+            parse_duration("9223372036854775807s"),
+            Err("is out of range")
+        );
+        assert_eq!(parse_duration("999999999999999d"), Err("is out of range"));
+        assert_eq!(parse_duration("-999999999999999d"), Err("is out of range"));
+    }
+}
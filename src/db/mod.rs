@@ -1,14 +1,11 @@
+use crate::config::DatabaseConfig;
 use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
-use std::env;
-
-pub async fn setup_db_pool() -> Result<Pool<Postgres>, sqlx::Error> {
-    let database_url = env::var("DATABASE_URL")
-        .expect("DATABASE_URL must be set");
 
+pub async fn setup_db_pool(config: &DatabaseConfig) -> Result<Pool<Postgres>, sqlx::Error> {
     let pool = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(&database_url)
+        .max_connections(config.max_connections)
+        .connect(&config.url)
         .await?;
 
     Ok(pool)
-}
\ No newline at end of file
+}
@@ -1,14 +1,131 @@
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
+use validator::Validate;
 
-#[derive(Debug, Deserialize, ToSchema)]
+#[derive(Debug, Deserialize, ToSchema, Validate)]
 pub struct LoginRequest {
-    #[schema(example = "alice@example.com")]
+    #[schema(example = "alice@example.com", format = "email", max_length = 255)]
+    #[validate(
+        email(message = "Must be a valid email address"),
+        length(max = 255, message = "Must be at most 255 characters")
+    )]
     pub email: String,
-    #[schema(example = "Passw0rd!")]
+    #[schema(example = "correct-Horse-battery-9", format = Password, min_length = 1, max_length = 1024)]
+    #[validate(length(min = 1, max = 1024, message = "Must be between 1 and 1024 characters"))]
     pub password: String,
 }
 
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub struct VerifyEmailRequest {
+    /// Token from the verification link
+    #[schema(
+        example = "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9...",
+        min_length = 1,
+        max_length = 4096
+    )]
+    #[validate(length(min = 1, max = 4096, message = "Must be between 1 and 4096 characters"))]
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub struct ResendVerificationRequest {
+    #[schema(example = "alice@example.com", format = "email", max_length = 255)]
+    #[validate(
+        email(message = "Must be a valid email address"),
+        length(max = 255, message = "Must be at most 255 characters")
+    )]
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub struct ForgotPasswordRequest {
+    #[schema(example = "alice@example.com", format = "email", max_length = 255)]
+    #[validate(
+        email(message = "Must be a valid email address"),
+        length(max = 255, message = "Must be at most 255 characters")
+    )]
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub struct ResetPasswordRequest {
+    /// Token from the reset link
+    #[schema(
+        example = "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9...",
+        min_length = 1,
+        max_length = 4096
+    )]
+    #[validate(length(min = 1, max = 4096, message = "Must be between 1 and 4096 characters"))]
+    pub token: String,
+    /// The new password; must satisfy the password policy
+    #[schema(example = "correct-Horse-battery-9", format = Password, min_length = 1, max_length = 1024)]
+    #[validate(length(min = 1, max = 1024, message = "Must be between 1 and 1024 characters"))]
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub struct MagicLinkRequest {
+    #[schema(example = "alice@example.com", format = "email", max_length = 255)]
+    #[validate(
+        email(message = "Must be a valid email address"),
+        length(max = 255, message = "Must be at most 255 characters")
+    )]
+    pub email: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MagicLinkResponse {
+    /// Only with `MAGIC_LINK_SAME_DEVICE`: keep it on this device and send it along with the link
+    #[schema(example = "h3Jc0xq4m1Zk5Vt8oW2yNb7pE9sLd6aGfRuQiT0vXcY")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub struct ConsumeMagicLinkRequest {
+    /// Token from the login link
+    #[schema(
+        example = "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9...",
+        min_length = 1,
+        max_length = 4096
+    )]
+    #[validate(length(min = 1, max = 4096, message = "Must be between 1 and 4096 characters"))]
+    pub token: String,
+    /// `device_token` from the link request; required when links are bound to the device
+    #[schema(
+        example = "h3Jc0xq4m1Zk5Vt8oW2yNb7pE9sLd6aGfRuQiT0vXcY",
+        max_length = 128
+    )]
+    #[validate(length(min = 1, max = 128, message = "Must be between 1 and 128 characters"))]
+    pub device_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub struct VerifyMfaRequest {
+    /// `mfa_token` from the login response
+    #[schema(
+        example = "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9...",
+        min_length = 1,
+        max_length = 4096
+    )]
+    #[validate(length(min = 1, max = 4096, message = "Must be between 1 and 4096 characters"))]
+    pub mfa_token: String,
+    /// Six-digit code from the authenticator app, or a recovery code
+    #[schema(example = "123456", min_length = 1, max_length = 32)]
+    #[validate(length(min = 1, max = 32, message = "Must be between 1 and 32 characters"))]
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MfaChallengeResponse {
+    /// Pass to `/api/auth/mfa/verify` together with a code
+    #[schema(example = "<mfa_token>")]
+    pub mfa_token: String,
+    /// Seconds until the challenge expires
+    #[schema(example = 300)]
+    pub expires_in: i64,
+}
+
 #[derive(Debug, Serialize, ToSchema)]
 pub struct TokenResponse {
     /// JWT access token
@@ -1,7 +1,11 @@
-use crate::dtos::auth::TokenResponse;
+use crate::dtos::auth::{MagicLinkResponse, MfaChallengeResponse, TokenResponse};
+use crate::dtos::user::{RecoveryCodesResponse, SessionResponse, TotpEnrollmentResponse};
+use crate::dtos::webauthn::{PasskeyCreationOptions, PasskeyRequestOptions, PasskeyResponse};
 use crate::models::user::UserProfile;
+use crate::utils::errors::ApiError;
 use serde::Serialize;
 use serde_json::json;
+use std::collections::HashMap;
 use utoipa::ToSchema;
 
 #[derive(Debug, Serialize, ToSchema)]
@@ -38,13 +42,30 @@ impl ApiResponse {
         Self::success_with(200, "OK", data)
     }
 
-    pub fn error_with(code: u16, message: impl Into<String>) -> serde_json::Value {
+    pub fn error_with(
+        code: u16,
+        error: &ApiError,
+        message: impl Into<String>,
+    ) -> serde_json::Value {
         json!({
             "status": "error",
             "code": code,
             "message": message.into(),
+            "error_code": error.code,
         })
     }
+
+    /// Same as `error_with`, plus per-field messages under `errors`.
+    pub fn error_with_details(
+        code: u16,
+        error: &ApiError,
+        message: impl Into<String>,
+        errors: serde_json::Value,
+    ) -> serde_json::Value {
+        let mut body = Self::error_with(code, error, message);
+        body["errors"] = errors;
+        body
+    }
 }
 
 // Swagger-visible envelopes for specific payloads
@@ -92,3 +113,157 @@ pub struct ApiResponseUserProfileEnvelope {
     /// User profile payload
     pub data: UserProfile,
 }
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiResponseMfaChallengeEnvelope {
+    /// Status response
+    #[schema(example = "success")]
+    pub status: String,
+    /// HTTP status code
+    #[schema(example = 200)]
+    pub code: u16,
+    /// Message detail
+    #[schema(example = "OK")]
+    pub message: String,
+    /// MFA challenge payload
+    pub data: MfaChallengeResponse,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiResponseMagicLinkEnvelope {
+    /// Status response
+    #[schema(example = "success")]
+    pub status: String,
+    /// HTTP status code
+    #[schema(example = 200)]
+    pub code: u16,
+    /// Message detail
+    #[schema(example = "If the email is registered, a login link has been sent")]
+    pub message: String,
+    /// Device token payload; empty unless links are bound to the device
+    pub data: MagicLinkResponse,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiResponseTotpEnrollmentEnvelope {
+    /// Status response
+    #[schema(example = "success")]
+    pub status: String,
+    /// HTTP status code
+    #[schema(example = 200)]
+    pub code: u16,
+    /// Message detail
+    #[schema(example = "OK")]
+    pub message: String,
+    /// TOTP enrollment payload
+    pub data: TotpEnrollmentResponse,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiResponseRecoveryCodesEnvelope {
+    /// Status response
+    #[schema(example = "success")]
+    pub status: String,
+    /// HTTP status code
+    #[schema(example = 200)]
+    pub code: u16,
+    /// Message detail
+    #[schema(example = "OK")]
+    pub message: String,
+    /// Recovery codes payload
+    pub data: RecoveryCodesResponse,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiResponsePasskeyCreationEnvelope {
+    /// Status response
+    #[schema(example = "success")]
+    pub status: String,
+    /// HTTP status code
+    #[schema(example = 200)]
+    pub code: u16,
+    /// Message detail
+    #[schema(example = "OK")]
+    pub message: String,
+    /// Options for `navigator.credentials.create()`
+    pub data: PasskeyCreationOptions,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiResponsePasskeyRequestEnvelope {
+    /// Status response
+    #[schema(example = "success")]
+    pub status: String,
+    /// HTTP status code
+    #[schema(example = 200)]
+    pub code: u16,
+    /// Message detail
+    #[schema(example = "OK")]
+    pub message: String,
+    /// Options for `navigator.credentials.get()`
+    pub data: PasskeyRequestOptions,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiResponsePasskeyEnvelope {
+    /// Status response
+    #[schema(example = "success")]
+    pub status: String,
+    /// HTTP status code
+    #[schema(example = 200)]
+    pub code: u16,
+    /// Message detail
+    #[schema(example = "Passkey registered")]
+    pub message: String,
+    /// Registered passkey payload
+    pub data: PasskeyResponse,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiResponsePasskeyListEnvelope {
+    /// Status response
+    #[schema(example = "success")]
+    pub status: String,
+    /// HTTP status code
+    #[schema(example = 200)]
+    pub code: u16,
+    /// Message detail
+    #[schema(example = "OK")]
+    pub message: String,
+    /// The user's passkeys
+    pub data: Vec<PasskeyResponse>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiResponseSessionListEnvelope {
+    /// Status response
+    #[schema(example = "success")]
+    pub status: String,
+    /// HTTP status code
+    #[schema(example = 200)]
+    pub code: u16,
+    /// Message detail
+    #[schema(example = "OK")]
+    pub message: String,
+    /// The user's active sessions
+    pub data: Vec<SessionResponse>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiErrorEnvelope {
+    /// Status response
+    #[schema(example = "error")]
+    pub status: String,
+    /// HTTP status code, repeated from the response status
+    #[schema(example = 401)]
+    pub code: u16,
+    /// Human-readable message; may change, do not match on it
+    #[schema(example = "Email or password is incorrect")]
+    pub message: String,
+    /// Stable machine-readable error code, one of the `ApiError` constants in `utils/errors.rs`
+    #[schema(example = "INVALID_CREDENTIALS")]
+    pub error_code: String,
+    /// Optional messages per offending field
+    #[schema(example = json!({"email": ["This email is already registered"]}))]
+    pub errors: Option<HashMap<String, Vec<String>>>,
+}
@@ -1,3 +1,4 @@
 pub mod auth;
 pub mod common;
 pub mod user;
+pub mod webauthn;
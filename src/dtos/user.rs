@@ -0,0 +1,76 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
+
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub struct ChangePasswordRequest {
+    #[schema(example = "correct-Horse-battery-9", format = Password, min_length = 1, max_length = 1024)]
+    #[validate(length(min = 1, max = 1024, message = "Must be between 1 and 1024 characters"))]
+    pub current_password: String,
+    /// The new password; must satisfy the password policy
+    #[schema(example = "staple-Battery-horse-10", format = Password, min_length = 1, max_length = 1024)]
+    #[validate(length(min = 1, max = 1024, message = "Must be between 1 and 1024 characters"))]
+    pub password: String,
+    /// Log out every other session; the one making the request stays logged in
+    #[serde(default)]
+    #[schema(example = true)]
+    pub revoke_other_sessions: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TotpEnrollmentResponse {
+    /// Base32 secret, for entering into an authenticator app by hand
+    #[schema(example = "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP")]
+    pub secret: String,
+    /// Render as a QR code for the authenticator app to scan
+    #[schema(
+        example = "otpauth://totp/rust-axum-pg-boilerplate:alice%40example.com?secret=JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP&issuer=rust-axum-pg-boilerplate"
+    )]
+    pub otpauth_uri: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RecoveryCodesResponse {
+    /// Single-use codes that stand in for a TOTP code; shown only this once
+    #[schema(example = json!(["k7pqm-3xw9a", "h2ndv-r8tce"]))]
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub struct MfaCodeRequest {
+    /// Six-digit code from the authenticator app, or a recovery code
+    #[schema(example = "123456", min_length = 1, max_length = 32)]
+    #[validate(length(min = 1, max = 32, message = "Must be between 1 and 32 characters"))]
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub struct DisableMfaRequest {
+    #[schema(example = "correct-Horse-battery-9", format = Password, min_length = 1, max_length = 1024)]
+    #[validate(length(min = 1, max = 1024, message = "Must be between 1 and 1024 characters"))]
+    pub password: String,
+    /// Six-digit code from the authenticator app, or a recovery code
+    #[schema(example = "123456", min_length = 1, max_length = 32)]
+    #[validate(length(min = 1, max = 32, message = "Must be between 1 and 32 characters"))]
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SessionResponse {
+    /// Session id; the `sid` claim of its tokens
+    #[schema(example = "7c9e6679-7425-40de-944b-e07fc1f90ae7")]
+    pub id: uuid::Uuid,
+    /// `User-Agent` of the client that logged in; `null` for sessions from before tracking
+    #[schema(example = "Mozilla/5.0 (Macintosh; Intel Mac OS X 14_5) AppleWebKit/605.1.15")]
+    pub user_agent: Option<String>,
+    /// Address the login came from
+    #[schema(example = "203.0.113.7")]
+    pub ip_address: Option<String>,
+    #[schema(example = 1696166400)]
+    pub created_at: i64,
+    /// Last login or token refresh
+    #[schema(example = 1696170000)]
+    pub last_used_at: i64,
+    /// Whether this is the session of the access token making the request
+    pub current: bool,
+}
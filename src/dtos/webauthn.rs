@@ -0,0 +1,165 @@
+// WebAuthn wire formats, as produced by `PublicKeyCredential.toJSON()` and read by
+// `PublicKeyCredential.parseCreationOptionsFromJSON()` / `parseRequestOptionsFromJSON()`.
+// Binary fields are base64url without padding.
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyCreationOptions {
+    pub rp: RelyingParty,
+    pub user: PasskeyUser,
+    #[schema(example = "q3kX9n1mJt0v8sQ2yYw4bA7cE5dF6gH8iJ0kL1mN2oP")]
+    pub challenge: String,
+    pub pub_key_cred_params: Vec<CredentialParameters>,
+    /// Milliseconds
+    #[schema(example = 300000)]
+    pub timeout: u64,
+    /// Passkeys the user already has, so the authenticator does not register one twice
+    pub exclude_credentials: Vec<CredentialDescriptor>,
+    pub authenticator_selection: AuthenticatorSelection,
+    #[schema(example = "none")]
+    pub attestation: &'static str,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyRequestOptions {
+    #[schema(example = "q3kX9n1mJt0v8sQ2yYw4bA7cE5dF6gH8iJ0kL1mN2oP")]
+    pub challenge: String,
+    /// Milliseconds
+    #[schema(example = 300000)]
+    pub timeout: u64,
+    #[schema(example = "example.com")]
+    pub rp_id: String,
+    /// Always empty: the authenticator offers the passkeys it holds for the relying party
+    pub allow_credentials: Vec<CredentialDescriptor>,
+    #[schema(example = "required")]
+    pub user_verification: &'static str,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RelyingParty {
+    #[schema(example = "example.com")]
+    pub id: String,
+    #[schema(example = "Example App")]
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyUser {
+    /// The user's id (16 bytes); returned as `userHandle` on login
+    #[schema(example = "VQ6EAOKbQdSnFkRmVUQAAA")]
+    pub id: String,
+    #[schema(example = "alice@example.com")]
+    pub name: String,
+    #[schema(example = "alice")]
+    pub display_name: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CredentialParameters {
+    #[serde(rename = "type")]
+    #[schema(example = "public-key")]
+    pub credential_type: &'static str,
+    /// COSE algorithm: -7 (ES256), -8 (EdDSA) or -257 (RS256)
+    #[schema(example = -7)]
+    pub alg: i64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CredentialDescriptor {
+    #[serde(rename = "type")]
+    #[schema(example = "public-key")]
+    pub credential_type: &'static str,
+    #[schema(example = "AQIDBAUGBwgJCgsMDQ4PEA")]
+    pub id: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[schema(example = json!(["internal", "hybrid"]))]
+    pub transports: Vec<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticatorSelection {
+    #[schema(example = "required")]
+    pub resident_key: &'static str,
+    pub require_resident_key: bool,
+    #[schema(example = "required")]
+    pub user_verification: &'static str,
+}
+
+/// A new credential from `navigator.credentials.create()`
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RegistrationCredential {
+    #[schema(example = "AQIDBAUGBwgJCgsMDQ4PEA")]
+    pub raw_id: String,
+    #[serde(rename = "type")]
+    #[schema(example = "public-key")]
+    pub credential_type: String,
+    pub response: AttestationResponse,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AttestationResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    pub attestation_object: String,
+    #[serde(default)]
+    #[schema(example = json!(["internal", "hybrid"]))]
+    pub transports: Vec<String>,
+}
+
+/// An assertion from `navigator.credentials.get()`
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticationCredential {
+    #[schema(example = "AQIDBAUGBwgJCgsMDQ4PEA")]
+    pub raw_id: String,
+    #[serde(rename = "type")]
+    #[schema(example = "public-key")]
+    pub credential_type: String,
+    pub response: AssertionResponse,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AssertionResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    pub authenticator_data: String,
+    pub signature: String,
+    pub user_handle: Option<String>,
+}
+
+// The credential itself is checked by the ceremony, which rejects anything malformed.
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub struct FinishPasskeyRegistrationRequest {
+    pub credential: RegistrationCredential,
+    /// Label shown in the passkey list; defaults to "Passkey"
+    #[schema(example = "MacBook Touch ID", min_length = 1, max_length = 100)]
+    #[validate(length(min = 1, max = 100, message = "Must be between 1 and 100 characters"))]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub struct PasskeyLoginRequest {
+    pub credential: AuthenticationCredential,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PasskeyResponse {
+    #[schema(example = "550e8400-e29b-41d4-a716-446655440000")]
+    pub id: uuid::Uuid,
+    #[schema(example = "MacBook Touch ID")]
+    pub name: String,
+    #[schema(example = 1696166400)]
+    pub created_at: i64,
+    /// `null` until the passkey is first used to log in
+    #[schema(example = 1696166400)]
+    pub last_used_at: Option<i64>,
+}
@@ -1,12 +1,26 @@
-use crate::dtos::auth::{LoginRequest, TokenResponse};
-use crate::dtos::common::{ApiResponse, ApiResponseEmptyEnvelope, ApiResponseTokenEnvelope};
+use crate::dtos::auth::{
+    ConsumeMagicLinkRequest, ForgotPasswordRequest, LoginRequest, MagicLinkRequest,
+    MagicLinkResponse, MfaChallengeResponse, ResendVerificationRequest, ResetPasswordRequest,
+    TokenResponse, VerifyEmailRequest, VerifyMfaRequest,
+};
+use crate::dtos::common::ApiResponse;
+use crate::dtos::webauthn::PasskeyLoginRequest;
+use crate::middleware::accept_language::AcceptLanguage;
+use crate::middleware::validation::ValidatedJson;
+use crate::models::session::SessionClient;
 use crate::models::user::NewUser;
-use crate::services::auth::{authenticate_user, register_user};
-use crate::services::token::{generate_access_token, validate_refresh_token};
-use axum::http::{HeaderMap, StatusCode};
-use axum::{Extension, Json};
+use crate::services::auth::{
+    authenticate_passkey, authenticate_user, complete_login, register_user, LoginOutcome,
+};
+use crate::services::token::{
+    generate_token_pair, revoke_refresh_token, rotate_refresh_token, validate_refresh_token,
+};
+use crate::state::AppState;
+use crate::utils::errors::AppError;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::Json;
 use serde_json::Value;
-use sqlx::{Pool, Postgres};
 
 /// Register a new user
 #[utoipa::path(
@@ -15,48 +29,101 @@ use sqlx::{Pool, Postgres};
     tag = "auth",
     request_body = NewUser,
     responses(
-        (status = 200, description = "User registered", body = ApiResponseTokenEnvelope),
-        (status = 400, description = "Bad request")
+        (status = 200, description = "User registered and a verification link sent. When verified emails are required (`EMAIL_VERIFICATION_REQUIRED`), `data` is empty and no tokens are issued", body = ApiResponseTokenEnvelope),
+        (status = 409, description = "Username or email already registered; `errors` names the field", body = ApiErrorEnvelope),
+        (status = 422, description = "Validation failed or the password breaks the password policy; `errors` lists the messages per field", body = ApiErrorEnvelope),
+        (status = 503, description = "Database unavailable", body = ApiErrorEnvelope)
     )
 )]
 pub async fn register_handler(
-    Extension(pool): Extension<Pool<Postgres>>,
-    Json(new_user): Json<NewUser>,
-) -> Result<Json<Value>, StatusCode> {
-    match register_user(&pool, &new_user).await {
-        Ok(token_pair) => Ok(Json(ApiResponse::success_ok(serde_json::json!(
-            TokenResponse {
-                access_token: token_pair.access_token,
-                refresh_token: token_pair.refresh_token,
-            }
-        )))),
-        Err(_) => Err(StatusCode::BAD_REQUEST),
+    State(state): State<AppState>,
+    AcceptLanguage(languages): AcceptLanguage,
+    client: SessionClient,
+    ValidatedJson(new_user): ValidatedJson<NewUser>,
+) -> Result<Json<Value>, AppError> {
+    let user = register_user(
+        &state.db,
+        &state.keyring,
+        &state.password_policy,
+        &state.password_hasher,
+        &state.email_verifier,
+        &new_user,
+        &languages,
+    )
+    .await?;
+
+    if state.email_verifier.required() {
+        return Ok(Json(ApiResponse::success_with(
+            200,
+            "Check your inbox to verify your email address",
+            serde_json::json!({}),
+        )));
     }
+    let token_pair = generate_token_pair(&state.db, &state.keyring, user.id, &client).await?;
+    Ok(Json(ApiResponse::success_ok(serde_json::json!(
+        TokenResponse {
+            access_token: token_pair.access_token,
+            refresh_token: token_pair.refresh_token,
+        }
+    ))))
 }
 
 /// Login and receive access & refresh tokens
+///
+/// Users with two-factor authentication get an `mfa_token` instead (message
+/// `"Two-factor authentication required"`, `data` as in `ApiResponseMfaChallengeEnvelope`);
+/// post it with a code to `/api/auth/mfa/verify` for the tokens.
 #[utoipa::path(
     post,
     path = "/api/auth/login",
     tag = "auth",
     request_body = LoginRequest,
     responses(
-        (status = 200, description = "Login success", body = ApiResponseTokenEnvelope),
-        (status = 401, description = "Unauthorized")
+        (status = 200, description = "Login success, or an MFA challenge when two-factor authentication is enabled", body = ApiResponseTokenEnvelope),
+        (status = 401, description = "Unauthorized", body = ApiErrorEnvelope),
+        (status = 403, description = "Email not verified while verified emails are required (`EMAIL_NOT_VERIFIED`)", body = ApiErrorEnvelope),
+        (status = 422, description = "Validation failed; `errors` lists the messages per field", body = ApiErrorEnvelope),
+        (status = 429, description = "Too many failed attempts for this account or IP (`ACCOUNT_LOCKED`); see `Retry-After`", body = ApiErrorEnvelope,
+            headers(("Retry-After" = u64, description = "Seconds until the next attempt is allowed")))
     )
 )]
 pub async fn login_handler(
-    Extension(pool): Extension<Pool<Postgres>>,
-    Json(payload): Json<LoginRequest>,
-) -> Result<Json<Value>, StatusCode> {
-    match authenticate_user(&pool, &payload.email, &payload.password).await {
-        Ok(token_pair) => Ok(Json(ApiResponse::success_ok(serde_json::json!(
-            TokenResponse {
+    State(state): State<AppState>,
+    client: SessionClient,
+    ValidatedJson(payload): ValidatedJson<LoginRequest>,
+) -> Result<Json<Value>, AppError> {
+    let user = authenticate_user(
+        &state.db,
+        &state.password_hasher,
+        &state.login_throttle,
+        &state.email_verifier,
+        &state.mfa,
+        &payload,
+        client.ip_address,
+    )
+    .await?;
+
+    let outcome = complete_login(&state.db, &state.keyring, &state.mfa, user.id, &client).await?;
+    Ok(Json(login_response(outcome)))
+}
+
+// Tokens, or the MFA challenge that stands in for them.
+fn login_response(outcome: LoginOutcome) -> Value {
+    match outcome {
+        LoginOutcome::Tokens(token_pair) => {
+            ApiResponse::success_ok(serde_json::json!(TokenResponse {
                 access_token: token_pair.access_token,
                 refresh_token: token_pair.refresh_token,
-            }
-        )))),
-        Err(_) => Err(StatusCode::UNAUTHORIZED),
+            }))
+        }
+        LoginOutcome::MfaRequired(challenge) => ApiResponse::success_with(
+            200,
+            "Two-factor authentication required",
+            serde_json::json!(MfaChallengeResponse {
+                mfa_token: challenge.token,
+                expires_in: challenge.expires_in,
+            }),
+        ),
     }
 }
 
@@ -68,51 +135,343 @@ pub async fn login_handler(
     security(("bearerAuth" = [])),
     responses(
         (status = 200, description = "Logout success", body = ApiResponseEmptyEnvelope),
-        (status = 401, description = "Unauthorized")
+        (status = 401, description = "Unauthorized", body = ApiErrorEnvelope)
     )
 )]
-pub async fn logout_handler(headers: HeaderMap) -> Result<Json<Value>, StatusCode> {
+pub async fn logout_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<Value>, AppError> {
     // Expect Bearer <refresh_token>
     let auth_header = headers
         .get("Authorization")
         .and_then(|h| h.to_str().ok())
-        .ok_or(StatusCode::UNAUTHORIZED)?;
+        .ok_or(AppError::Unauthorized)?;
     let token = auth_header
         .strip_prefix("Bearer ")
-        .ok_or(StatusCode::UNAUTHORIZED)?;
-    // Optionally validate token format; for stateless logout we just acknowledge
-    let _ = validate_refresh_token(token).map_err(|_| StatusCode::UNAUTHORIZED)?;
+        .ok_or(AppError::Unauthorized)?;
+    // Only tokens that are still active in the store can be logged out
+    let claims = validate_refresh_token(&state.db, &state.keyring, token).await?;
+    revoke_refresh_token(&state.db, &claims).await?;
     Ok(Json(ApiResponse::success_ok(serde_json::json!({}))))
 }
 
-/// Refresh access token using refresh token from Authorization: Bearer <token>
+/// Rotate tokens using refresh token from Authorization: Bearer <token>
+///
+/// The presented refresh token is revoked and a new access & refresh token pair is returned;
+/// the new refresh token expires when the presented one would have, so refreshing never
+/// extends a session past `JWT_REFRESH_TOKEN_EXPIRY` from login.
+/// Reusing an already rotated refresh token revokes every token issued from the same login.
 #[utoipa::path(
     post,
     path = "/api/auth/refresh",
     tag = "auth",
     security(("bearerAuth" = [])),
     responses(
-        (status = 200, description = "Token refreshed", body = ApiResponseTokenEnvelope),
-        (status = 401, description = "Unauthorized")
+        (status = 200, description = "Tokens rotated", body = ApiResponseTokenEnvelope),
+        (status = 401, description = "Unauthorized", body = ApiErrorEnvelope)
     )
 )]
-pub async fn refresh_token_handler(headers: HeaderMap) -> Result<Json<Value>, StatusCode> {
+pub async fn refresh_token_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<Value>, AppError> {
     // Expect Bearer <refresh_token>
     let auth_header = headers
         .get("Authorization")
         .and_then(|h| h.to_str().ok())
-        .ok_or(StatusCode::UNAUTHORIZED)?;
+        .ok_or(AppError::Unauthorized)?;
     let token = auth_header
         .strip_prefix("Bearer ")
-        .ok_or(StatusCode::UNAUTHORIZED)?;
-    // Validate refresh token and mint a new access token
-    let claims = validate_refresh_token(token).map_err(|_| StatusCode::UNAUTHORIZED)?;
-    let new_access = generate_access_token(claims.sub).map_err(|_| StatusCode::UNAUTHORIZED)?;
+        .ok_or(AppError::Unauthorized)?;
+    // Revoke the presented refresh token and mint a new pair in the same family
+    let token_pair = rotate_refresh_token(&state.db, &state.keyring, token).await?;
+
+    Ok(Json(ApiResponse::success_ok(serde_json::json!(
+        TokenResponse {
+            access_token: token_pair.access_token,
+            refresh_token: token_pair.refresh_token,
+        }
+    ))))
+}
+
+/// Verify an email address with the token from the verification link
+///
+/// Each token can be used once and is tied to the address it was sent to.
+#[utoipa::path(
+    post,
+    path = "/api/auth/verify-email",
+    tag = "auth",
+    request_body = VerifyEmailRequest,
+    responses(
+        (status = 200, description = "Email verified", body = ApiResponseEmptyEnvelope),
+        (status = 400, description = "Token is invalid, expired or already used (`INVALID_OR_EXPIRED_TOKEN`)", body = ApiErrorEnvelope),
+        (status = 422, description = "Validation failed; `errors` lists the messages per field", body = ApiErrorEnvelope)
+    )
+)]
+pub async fn verify_email_handler(
+    State(state): State<AppState>,
+    ValidatedJson(payload): ValidatedJson<VerifyEmailRequest>,
+) -> Result<Json<Value>, AppError> {
+    state
+        .email_verifier
+        .verify(&state.db, &state.keyring, &payload.token)
+        .await?;
+    Ok(Json(ApiResponse::success_ok(serde_json::json!({}))))
+}
+
+/// Send a new verification link
+///
+/// Always succeeds, whether or not the email belongs to an unverified account.
+/// Links sent earlier stop working.
+#[utoipa::path(
+    post,
+    path = "/api/auth/resend-verification",
+    tag = "auth",
+    request_body = ResendVerificationRequest,
+    responses(
+        (status = 200, description = "A link was sent if the email belongs to an unverified account", body = ApiResponseEmptyEnvelope),
+        (status = 422, description = "Validation failed; `errors` lists the messages per field", body = ApiErrorEnvelope)
+    )
+)]
+pub async fn resend_verification_handler(
+    State(state): State<AppState>,
+    AcceptLanguage(languages): AcceptLanguage,
+    ValidatedJson(payload): ValidatedJson<ResendVerificationRequest>,
+) -> Result<Json<Value>, AppError> {
+    state
+        .email_verifier
+        .resend(&state.db, &state.keyring, &payload.email, &languages);
+    Ok(Json(ApiResponse::success_ok(serde_json::json!({}))))
+}
+
+/// Request a password reset link
+///
+/// Always answers the same, whether or not the email is registered.
+#[utoipa::path(
+    post,
+    path = "/api/auth/forgot-password",
+    tag = "auth",
+    request_body = ForgotPasswordRequest,
+    responses(
+        (status = 200, description = "A reset link was sent if the email is registered", body = ApiResponseEmptyEnvelope),
+        (status = 422, description = "Validation failed; `errors` lists the messages per field", body = ApiErrorEnvelope)
+    )
+)]
+pub async fn forgot_password_handler(
+    State(state): State<AppState>,
+    AcceptLanguage(languages): AcceptLanguage,
+    ValidatedJson(payload): ValidatedJson<ForgotPasswordRequest>,
+) -> Result<Json<Value>, AppError> {
+    state
+        .password_resetter
+        .request(&state.db, &state.keyring, &payload.email, &languages);
+    Ok(Json(ApiResponse::success_with(
+        200,
+        "If the email is registered, a reset link has been sent",
+        serde_json::json!({}),
+    )))
+}
+
+/// Set a new password with the token from the reset link
+///
+/// Each token can be used once. On success every refresh token of the user is revoked,
+/// logging them out on all devices.
+#[utoipa::path(
+    post,
+    path = "/api/auth/reset-password",
+    tag = "auth",
+    request_body = ResetPasswordRequest,
+    responses(
+        (status = 200, description = "Password changed", body = ApiResponseEmptyEnvelope),
+        (status = 400, description = "Token is invalid, expired or already used (`INVALID_OR_EXPIRED_TOKEN`)", body = ApiErrorEnvelope),
+        (status = 422, description = "Validation failed or the password breaks the password policy; the token stays usable", body = ApiErrorEnvelope)
+    )
+)]
+pub async fn reset_password_handler(
+    State(state): State<AppState>,
+    ValidatedJson(payload): ValidatedJson<ResetPasswordRequest>,
+) -> Result<Json<Value>, AppError> {
+    state
+        .password_resetter
+        .reset(
+            &state.db,
+            &state.keyring,
+            &state.password_policy,
+            &state.password_hasher,
+            &payload.token,
+            &payload.password,
+        )
+        .await?;
+    Ok(Json(ApiResponse::success_ok(serde_json::json!({}))))
+}
+
+/// Complete a login with a TOTP or recovery code
+///
+/// Each `mfa_token` accepts a limited number of codes (`MFA_MAX_ATTEMPTS`) and succeeds once.
+/// Codes are never accepted twice; recovery codes are spent on use. Wrong codes count as
+/// failed logins of the account, like wrong passwords.
+#[utoipa::path(
+    post,
+    path = "/api/auth/mfa/verify",
+    tag = "auth",
+    request_body = VerifyMfaRequest,
+    responses(
+        (status = 200, description = "Login success", body = ApiResponseTokenEnvelope),
+        (status = 400, description = "Challenge is invalid, expired, already used or out of attempts (`INVALID_OR_EXPIRED_TOKEN`); log in again", body = ApiErrorEnvelope),
+        (status = 401, description = "Code is incorrect or already used (`INVALID_MFA_CODE`)", body = ApiErrorEnvelope),
+        (status = 422, description = "Validation failed; `errors` lists the messages per field", body = ApiErrorEnvelope),
+        (status = 429, description = "Too many failed attempts for this account or IP (`ACCOUNT_LOCKED`); see `Retry-After`", body = ApiErrorEnvelope,
+            headers(("Retry-After" = u64, description = "Seconds until the next attempt is allowed")))
+    )
+)]
+pub async fn verify_mfa_handler(
+    State(state): State<AppState>,
+    client: SessionClient,
+    ValidatedJson(payload): ValidatedJson<VerifyMfaRequest>,
+) -> Result<Json<Value>, AppError> {
+    let user_id = state
+        .mfa
+        .verify_challenge(
+            &state.db,
+            &state.keyring,
+            &state.login_throttle,
+            &payload.mfa_token,
+            &payload.code,
+            client.ip_address,
+        )
+        .await?;
+    let token_pair = generate_token_pair(&state.db, &state.keyring, user_id, &client).await?;
+
+    Ok(Json(ApiResponse::success_ok(serde_json::json!(
+        TokenResponse {
+            access_token: token_pair.access_token,
+            refresh_token: token_pair.refresh_token,
+        }
+    ))))
+}
+
+/// Request a magic login link
+///
+/// Always answers the same, whether or not the email is registered. When links are bound to
+/// the requesting device (`MAGIC_LINK_SAME_DEVICE`), `data.device_token` must be kept and sent
+/// with the link to `/api/auth/magic-link/consume`.
+#[utoipa::path(
+    post,
+    path = "/api/auth/magic-link",
+    tag = "auth",
+    request_body = MagicLinkRequest,
+    responses(
+        (status = 200, description = "A login link was sent if the email is registered", body = ApiResponseMagicLinkEnvelope),
+        (status = 404, description = "Magic-link login is disabled on this server", body = ApiErrorEnvelope),
+        (status = 422, description = "Validation failed; `errors` lists the messages per field", body = ApiErrorEnvelope)
+    )
+)]
+pub async fn request_magic_link_handler(
+    State(state): State<AppState>,
+    AcceptLanguage(languages): AcceptLanguage,
+    ValidatedJson(payload): ValidatedJson<MagicLinkRequest>,
+) -> Result<Json<Value>, AppError> {
+    let device_token =
+        state
+            .magic_links
+            .request(&state.db, &state.keyring, &payload.email, &languages)?;
+    Ok(Json(ApiResponse::success_with(
+        200,
+        "If the email is registered, a login link has been sent",
+        serde_json::json!(MagicLinkResponse { device_token }),
+    )))
+}
+
+/// Log in with the token from a magic link
+///
+/// Each link works once and retires the user's other links; it also verifies the email.
+/// Users with two-factor authentication get an `mfa_token` instead, as from `/api/auth/login`.
+#[utoipa::path(
+    post,
+    path = "/api/auth/magic-link/consume",
+    tag = "auth",
+    request_body = ConsumeMagicLinkRequest,
+    responses(
+        (status = 200, description = "Login success, or an MFA challenge when two-factor authentication is enabled", body = ApiResponseTokenEnvelope),
+        (status = 400, description = "Link is invalid, expired, already used or was requested on another device", body = ApiErrorEnvelope),
+        (status = 404, description = "Magic-link login is disabled on this server", body = ApiErrorEnvelope),
+        (status = 422, description = "Validation failed; `errors` lists the messages per field", body = ApiErrorEnvelope)
+    )
+)]
+pub async fn consume_magic_link_handler(
+    State(state): State<AppState>,
+    client: SessionClient,
+    ValidatedJson(payload): ValidatedJson<ConsumeMagicLinkRequest>,
+) -> Result<Json<Value>, AppError> {
+    let user_id = state
+        .magic_links
+        .consume(
+            &state.db,
+            &state.keyring,
+            &payload.token,
+            payload.device_token.as_deref(),
+        )
+        .await?;
+
+    let outcome = complete_login(&state.db, &state.keyring, &state.mfa, user_id, &client).await?;
+    Ok(Json(login_response(outcome)))
+}
+
+/// Start a passkey login
+///
+/// Returns options for `navigator.credentials.get()`. No email is needed: the authenticator
+/// offers the passkeys it holds for this site.
+#[utoipa::path(
+    post,
+    path = "/api/auth/passkey/start",
+    tag = "auth",
+    responses(
+        (status = 200, description = "Request options with a single-use challenge", body = ApiResponsePasskeyRequestEnvelope)
+    )
+)]
+pub async fn start_passkey_login_handler(
+    State(state): State<AppState>,
+) -> Result<Json<Value>, AppError> {
+    let options = state.passkeys.start_login(&state.db).await?;
+    Ok(Json(ApiResponse::success_ok(serde_json::json!(options))))
+}
+
+/// Finish a passkey login and receive access & refresh tokens
+///
+/// Takes the credential from `navigator.credentials.get()`, serialized with
+/// `PublicKeyCredential.toJSON()`. Two-factor authentication is not asked for afterwards.
+#[utoipa::path(
+    post,
+    path = "/api/auth/passkey/finish",
+    tag = "auth",
+    request_body = PasskeyLoginRequest,
+    responses(
+        (status = 200, description = "Login success", body = ApiResponseTokenEnvelope),
+        (status = 400, description = "Challenge is invalid, expired or already used (`INVALID_OR_EXPIRED_TOKEN`), or the passkey failed verification (`INVALID_PASSKEY`)", body = ApiErrorEnvelope),
+        (status = 403, description = "Email not verified while verified emails are required (`EMAIL_NOT_VERIFIED`)", body = ApiErrorEnvelope),
+        (status = 422, description = "Request body does not match the expected shape", body = ApiErrorEnvelope)
+    )
+)]
+pub async fn finish_passkey_login_handler(
+    State(state): State<AppState>,
+    client: SessionClient,
+    ValidatedJson(payload): ValidatedJson<PasskeyLoginRequest>,
+) -> Result<Json<Value>, AppError> {
+    let token_pair = authenticate_passkey(
+        &state.db,
+        &state.keyring,
+        &state.passkeys,
+        &state.email_verifier,
+        &payload.credential,
+        &client,
+    )
+    .await?;
 
     Ok(Json(ApiResponse::success_ok(serde_json::json!(
         TokenResponse {
-            access_token: new_access,
-            refresh_token: token.to_string(),
+            access_token: token_pair.access_token,
+            refresh_token: token_pair.refresh_token,
         }
     ))))
 }
@@ -1,2 +1,3 @@
 pub mod auth;
-pub mod user;
\ No newline at end of file
+pub mod user;
+pub mod well_known;
@@ -1,11 +1,24 @@
-use crate::dtos::common::{ApiResponse, ApiResponseUserProfileEnvelope};
-use crate::middleware::auth::AuthenticatedUser;
+use crate::dtos::common::ApiResponse;
+use crate::dtos::user::{
+    ChangePasswordRequest, DisableMfaRequest, MfaCodeRequest, RecoveryCodesResponse,
+    SessionResponse, TotpEnrollmentResponse,
+};
+use crate::dtos::webauthn::{FinishPasskeyRegistrationRequest, PasskeyResponse};
+use crate::middleware::auth::{AuthenticatedSession, AuthenticatedUser};
+use crate::middleware::client_ip::ClientIp;
+use crate::middleware::validation::ValidatedJson;
 use crate::models::user::UserProfile;
-use crate::services::user::service_get_profile;
-use axum::http::StatusCode;
-use axum::{Extension, Json};
+use crate::models::webauthn::WebauthnCredential;
+use crate::services::session::{
+    list_sessions, revoke_all_sessions, revoke_other_sessions, revoke_session,
+};
+use crate::services::user::{change_password, service_get_profile, verify_current_password};
+use crate::state::AppState;
+use crate::utils::errors::AppError;
+use axum::extract::{Path, State};
+use axum::Json;
 use serde_json::Value;
-use sqlx::{Pool, Postgres};
+use uuid::Uuid;
 
 #[utoipa::path(
     get,
@@ -14,17 +27,15 @@ use sqlx::{Pool, Postgres};
     security(("bearerAuth" = [])),
     responses(
         (status = 200, description = "User profile", body = ApiResponseUserProfileEnvelope),
-        (status = 404, description = "User not found"),
-        (status = 401, description = "Unauthorized")
+        (status = 404, description = "User not found", body = ApiErrorEnvelope),
+        (status = 401, description = "Unauthorized", body = ApiErrorEnvelope)
     )
 )]
 pub async fn get_profile(
     AuthenticatedUser(user_id): AuthenticatedUser,
-    Extension(pool): Extension<Pool<Postgres>>,
-) -> Result<Json<Value>, StatusCode> {
-    let user = service_get_profile(&pool, user_id)
-        .await
-        .map_err(|_| StatusCode::NOT_FOUND)?;
+    State(state): State<AppState>,
+) -> Result<Json<Value>, AppError> {
+    let user = service_get_profile(&state.db, user_id).await?;
 
     Ok(Json(ApiResponse::success_ok(serde_json::json!(
         UserProfile {
@@ -33,6 +44,455 @@ pub async fn get_profile(
             email: user.email,
             created_at: user.created_at,
             updated_at: user.updated_at,
+            email_verified_at: user.email_verified_at,
         }
     ))))
 }
+
+/// Change the password of the logged-in user
+///
+/// Requires the current password; wrong ones count as failed logins, so repeated guesses
+/// lock the account out as they do at login. Outstanding password reset links stop working,
+/// and so do all access tokens, this one included: the client refreshes to continue. With
+/// `revoke_other_sessions`, every other session is logged out; the current one stays.
+#[utoipa::path(
+    post,
+    path = "/api/user/password",
+    tag = "user",
+    security(("bearerAuth" = [])),
+    request_body = ChangePasswordRequest,
+    responses(
+        (status = 200, description = "Password changed", body = ApiResponseEmptyEnvelope),
+        (status = 401, description = "Unauthorized", body = ApiErrorEnvelope),
+        (status = 422, description = "Validation failed, the current password is incorrect or the new password breaks the password policy", body = ApiErrorEnvelope),
+        (status = 429, description = "Too many failed attempts for this account or IP (`ACCOUNT_LOCKED`); see `Retry-After`", body = ApiErrorEnvelope,
+            headers(("Retry-After" = u64, description = "Seconds until the next attempt is allowed")))
+    )
+)]
+pub async fn change_password_handler(
+    session: AuthenticatedSession,
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    ValidatedJson(payload): ValidatedJson<ChangePasswordRequest>,
+) -> Result<Json<Value>, AppError> {
+    let user = verify_current_password(
+        &state.db,
+        &state.password_hasher,
+        &state.login_throttle,
+        session.user_id,
+        "current_password",
+        &payload.current_password,
+        client_ip,
+    )
+    .await?;
+    change_password(
+        &state.db,
+        &state.token_versions,
+        &state.password_policy,
+        &state.password_hasher,
+        &user,
+        session.session_id,
+        &payload,
+    )
+    .await?;
+    Ok(Json(ApiResponse::success_with(
+        200,
+        "Password changed",
+        serde_json::json!({}),
+    )))
+}
+
+/// List the sessions the user is logged in with
+///
+/// One entry per login that can still refresh its tokens, most recently used first.
+#[utoipa::path(
+    get,
+    path = "/api/user/sessions",
+    tag = "user",
+    security(("bearerAuth" = [])),
+    responses(
+        (status = 200, description = "Active sessions", body = ApiResponseSessionListEnvelope),
+        (status = 401, description = "Unauthorized", body = ApiErrorEnvelope)
+    )
+)]
+pub async fn list_sessions_handler(
+    session: AuthenticatedSession,
+    State(state): State<AppState>,
+) -> Result<Json<Value>, AppError> {
+    let sessions: Vec<SessionResponse> = list_sessions(&state.db, session.user_id)
+        .await?
+        .into_iter()
+        .map(|s| SessionResponse {
+            current: Some(s.id) == session.session_id,
+            id: s.id,
+            user_agent: s.user_agent,
+            ip_address: s.ip_address,
+            created_at: s.created_at,
+            last_used_at: s.last_used_at,
+        })
+        .collect();
+
+    Ok(Json(ApiResponse::success_ok(serde_json::json!(sessions))))
+}
+
+/// Log a session out
+///
+/// Revokes its refresh tokens; access tokens already issued to it stay valid until they
+/// expire. Revoking the current session logs this client out.
+#[utoipa::path(
+    delete,
+    path = "/api/user/sessions/{id}",
+    tag = "user",
+    security(("bearerAuth" = [])),
+    params(("id" = Uuid, Path, description = "Session id")),
+    responses(
+        (status = 200, description = "Session logged out", body = ApiResponseEmptyEnvelope),
+        (status = 401, description = "Unauthorized", body = ApiErrorEnvelope),
+        (status = 404, description = "The user has no such active session", body = ApiErrorEnvelope)
+    )
+)]
+pub async fn revoke_session_handler(
+    AuthenticatedUser(user_id): AuthenticatedUser,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Value>, AppError> {
+    revoke_session(&state.db, user_id, id).await?;
+    Ok(Json(ApiResponse::success_with(
+        200,
+        "Session logged out",
+        serde_json::json!({}),
+    )))
+}
+
+/// Log out everywhere else
+///
+/// Revokes every session except the current one (all of them for access tokens issued
+/// before sessions were tracked).
+#[utoipa::path(
+    post,
+    path = "/api/user/sessions/revoke-others",
+    tag = "user",
+    security(("bearerAuth" = [])),
+    responses(
+        (status = 200, description = "Other sessions logged out", body = ApiResponseEmptyEnvelope),
+        (status = 401, description = "Unauthorized", body = ApiErrorEnvelope)
+    )
+)]
+pub async fn revoke_other_sessions_handler(
+    session: AuthenticatedSession,
+    State(state): State<AppState>,
+) -> Result<Json<Value>, AppError> {
+    revoke_other_sessions(&state.db, session.user_id, session.session_id).await?;
+    Ok(Json(ApiResponse::success_with(
+        200,
+        "Other sessions logged out",
+        serde_json::json!({}),
+    )))
+}
+
+/// Log out everywhere
+///
+/// Revokes every session, the current one included, and invalidates every access token
+/// issued to the user so far, this one included.
+#[utoipa::path(
+    post,
+    path = "/api/user/sessions/revoke-all",
+    tag = "user",
+    security(("bearerAuth" = [])),
+    responses(
+        (status = 200, description = "All sessions logged out", body = ApiResponseEmptyEnvelope),
+        (status = 401, description = "Unauthorized", body = ApiErrorEnvelope)
+    )
+)]
+pub async fn revoke_all_sessions_handler(
+    AuthenticatedUser(user_id): AuthenticatedUser,
+    State(state): State<AppState>,
+) -> Result<Json<Value>, AppError> {
+    revoke_all_sessions(&state.db, &state.token_versions, user_id).await?;
+    Ok(Json(ApiResponse::success_with(
+        200,
+        "All sessions logged out",
+        serde_json::json!({}),
+    )))
+}
+
+/// Start TOTP enrollment
+///
+/// Returns a new secret and its `otpauth://` URI for the user's authenticator app. Nothing
+/// changes at login until the enrollment is confirmed with a code; enrolling again before
+/// that replaces the secret.
+#[utoipa::path(
+    post,
+    path = "/api/user/mfa/enroll",
+    tag = "user",
+    security(("bearerAuth" = [])),
+    responses(
+        (status = 200, description = "Secret generated", body = ApiResponseTotpEnrollmentEnvelope),
+        (status = 401, description = "Unauthorized", body = ApiErrorEnvelope),
+        (status = 409, description = "Two-factor authentication is already enabled (`MFA_ALREADY_ENABLED`)", body = ApiErrorEnvelope),
+        (status = 503, description = "`MFA_ENCRYPTION_KEY` is not configured (`MFA_UNAVAILABLE`)", body = ApiErrorEnvelope)
+    )
+)]
+pub async fn enroll_mfa_handler(
+    AuthenticatedUser(user_id): AuthenticatedUser,
+    State(state): State<AppState>,
+) -> Result<Json<Value>, AppError> {
+    let enrollment = state.mfa.enroll(&state.db, user_id).await?;
+
+    Ok(Json(ApiResponse::success_ok(serde_json::json!(
+        TotpEnrollmentResponse {
+            secret: enrollment.secret,
+            otpauth_uri: enrollment.otpauth_uri,
+        }
+    ))))
+}
+
+/// Confirm TOTP enrollment with a first code
+///
+/// Enables two-factor authentication and returns the recovery codes, which are not shown again.
+#[utoipa::path(
+    post,
+    path = "/api/user/mfa/confirm",
+    tag = "user",
+    security(("bearerAuth" = [])),
+    request_body = MfaCodeRequest,
+    responses(
+        (status = 200, description = "Two-factor authentication enabled", body = ApiResponseRecoveryCodesEnvelope),
+        (status = 401, description = "Unauthorized", body = ApiErrorEnvelope),
+        (status = 404, description = "No enrollment is pending", body = ApiErrorEnvelope),
+        (status = 409, description = "Two-factor authentication is already enabled (`MFA_ALREADY_ENABLED`)", body = ApiErrorEnvelope),
+        (status = 422, description = "Validation failed or the code is incorrect", body = ApiErrorEnvelope)
+    )
+)]
+pub async fn confirm_mfa_handler(
+    AuthenticatedUser(user_id): AuthenticatedUser,
+    State(state): State<AppState>,
+    ValidatedJson(payload): ValidatedJson<MfaCodeRequest>,
+) -> Result<Json<Value>, AppError> {
+    let recovery_codes = state.mfa.confirm(&state.db, user_id, &payload.code).await?;
+
+    Ok(Json(ApiResponse::success_with(
+        200,
+        "Two-factor authentication enabled",
+        serde_json::json!(RecoveryCodesResponse { recovery_codes }),
+    )))
+}
+
+/// Replace the recovery codes
+///
+/// Takes a TOTP code or an unused recovery code; wrong ones count as failed logins. Every
+/// earlier recovery code stops working.
+#[utoipa::path(
+    post,
+    path = "/api/user/mfa/recovery-codes",
+    tag = "user",
+    security(("bearerAuth" = [])),
+    request_body = MfaCodeRequest,
+    responses(
+        (status = 200, description = "New recovery codes", body = ApiResponseRecoveryCodesEnvelope),
+        (status = 401, description = "Unauthorized", body = ApiErrorEnvelope),
+        (status = 404, description = "Two-factor authentication is not enabled", body = ApiErrorEnvelope),
+        (status = 422, description = "Validation failed or the code is incorrect", body = ApiErrorEnvelope),
+        (status = 429, description = "Too many failed attempts for this account or IP (`ACCOUNT_LOCKED`); see `Retry-After`", body = ApiErrorEnvelope,
+            headers(("Retry-After" = u64, description = "Seconds until the next attempt is allowed")))
+    )
+)]
+pub async fn regenerate_recovery_codes_handler(
+    AuthenticatedUser(user_id): AuthenticatedUser,
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    ValidatedJson(payload): ValidatedJson<MfaCodeRequest>,
+) -> Result<Json<Value>, AppError> {
+    let recovery_codes = state
+        .mfa
+        .regenerate_recovery_codes(
+            &state.db,
+            &state.login_throttle,
+            user_id,
+            &payload.code,
+            client_ip,
+        )
+        .await?;
+
+    Ok(Json(ApiResponse::success_ok(serde_json::json!(
+        RecoveryCodesResponse { recovery_codes }
+    ))))
+}
+
+/// Turn off two-factor authentication
+///
+/// Requires the password and a TOTP or recovery code; wrong ones count as failed logins.
+/// Removes the secret and all recovery codes.
+#[utoipa::path(
+    post,
+    path = "/api/user/mfa/disable",
+    tag = "user",
+    security(("bearerAuth" = [])),
+    request_body = DisableMfaRequest,
+    responses(
+        (status = 200, description = "Two-factor authentication disabled", body = ApiResponseEmptyEnvelope),
+        (status = 401, description = "Unauthorized", body = ApiErrorEnvelope),
+        (status = 404, description = "Two-factor authentication is not enabled", body = ApiErrorEnvelope),
+        (status = 422, description = "Validation failed, or the password or code is incorrect", body = ApiErrorEnvelope),
+        (status = 429, description = "Too many failed attempts for this account or IP (`ACCOUNT_LOCKED`); see `Retry-After`", body = ApiErrorEnvelope,
+            headers(("Retry-After" = u64, description = "Seconds until the next attempt is allowed")))
+    )
+)]
+pub async fn disable_mfa_handler(
+    AuthenticatedUser(user_id): AuthenticatedUser,
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    ValidatedJson(payload): ValidatedJson<DisableMfaRequest>,
+) -> Result<Json<Value>, AppError> {
+    let user = verify_current_password(
+        &state.db,
+        &state.password_hasher,
+        &state.login_throttle,
+        user_id,
+        "password",
+        &payload.password,
+        client_ip,
+    )
+    .await?;
+    state
+        .mfa
+        .disable(
+            &state.db,
+            &state.login_throttle,
+            &user,
+            &payload.code,
+            client_ip,
+        )
+        .await?;
+
+    Ok(Json(ApiResponse::success_with(
+        200,
+        "Two-factor authentication disabled",
+        serde_json::json!({}),
+    )))
+}
+
+/// Start adding a passkey
+///
+/// Returns options for `navigator.credentials.create()`, listing the user's existing passkeys
+/// so the authenticator does not register one twice.
+#[utoipa::path(
+    post,
+    path = "/api/user/passkeys/register/start",
+    tag = "user",
+    security(("bearerAuth" = [])),
+    responses(
+        (status = 200, description = "Creation options with a single-use challenge", body = ApiResponsePasskeyCreationEnvelope),
+        (status = 401, description = "Unauthorized", body = ApiErrorEnvelope)
+    )
+)]
+pub async fn start_passkey_registration_handler(
+    AuthenticatedUser(user_id): AuthenticatedUser,
+    State(state): State<AppState>,
+) -> Result<Json<Value>, AppError> {
+    let options = state
+        .passkeys
+        .start_registration(&state.db, user_id)
+        .await?;
+    Ok(Json(ApiResponse::success_ok(serde_json::json!(options))))
+}
+
+/// Finish adding a passkey
+///
+/// Takes the credential from `navigator.credentials.create()`, serialized with
+/// `PublicKeyCredential.toJSON()`. The passkey can then be used at `/api/auth/passkey/finish`.
+#[utoipa::path(
+    post,
+    path = "/api/user/passkeys/register/finish",
+    tag = "user",
+    security(("bearerAuth" = [])),
+    request_body = FinishPasskeyRegistrationRequest,
+    responses(
+        (status = 200, description = "Passkey registered", body = ApiResponsePasskeyEnvelope),
+        (status = 400, description = "Challenge is invalid, expired or already used (`INVALID_OR_EXPIRED_TOKEN`), or the credential failed verification or is already registered (`INVALID_PASSKEY`)", body = ApiErrorEnvelope),
+        (status = 401, description = "Unauthorized", body = ApiErrorEnvelope),
+        (status = 422, description = "Validation failed; `errors` lists the messages per field", body = ApiErrorEnvelope)
+    )
+)]
+pub async fn finish_passkey_registration_handler(
+    AuthenticatedUser(user_id): AuthenticatedUser,
+    State(state): State<AppState>,
+    ValidatedJson(payload): ValidatedJson<FinishPasskeyRegistrationRequest>,
+) -> Result<Json<Value>, AppError> {
+    let credential = state
+        .passkeys
+        .finish_registration(
+            &state.db,
+            user_id,
+            &payload.credential,
+            payload.name.as_deref(),
+        )
+        .await?;
+
+    Ok(Json(ApiResponse::success_with(
+        200,
+        "Passkey registered",
+        serde_json::json!(passkey_response(credential)),
+    )))
+}
+
+/// List the user's passkeys
+#[utoipa::path(
+    get,
+    path = "/api/user/passkeys",
+    tag = "user",
+    security(("bearerAuth" = [])),
+    responses(
+        (status = 200, description = "Passkeys, oldest first", body = ApiResponsePasskeyListEnvelope),
+        (status = 401, description = "Unauthorized", body = ApiErrorEnvelope)
+    )
+)]
+pub async fn list_passkeys_handler(
+    AuthenticatedUser(user_id): AuthenticatedUser,
+    State(state): State<AppState>,
+) -> Result<Json<Value>, AppError> {
+    let passkeys: Vec<PasskeyResponse> = state
+        .passkeys
+        .list(&state.db, user_id)
+        .await?
+        .into_iter()
+        .map(passkey_response)
+        .collect();
+
+    Ok(Json(ApiResponse::success_ok(serde_json::json!(passkeys))))
+}
+
+/// Remove a passkey
+#[utoipa::path(
+    delete,
+    path = "/api/user/passkeys/{id}",
+    tag = "user",
+    security(("bearerAuth" = [])),
+    params(("id" = Uuid, Path, description = "Passkey id")),
+    responses(
+        (status = 200, description = "Passkey removed", body = ApiResponseEmptyEnvelope),
+        (status = 401, description = "Unauthorized", body = ApiErrorEnvelope),
+        (status = 404, description = "The user has no such passkey", body = ApiErrorEnvelope)
+    )
+)]
+pub async fn delete_passkey_handler(
+    AuthenticatedUser(user_id): AuthenticatedUser,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Value>, AppError> {
+    state.passkeys.delete(&state.db, user_id, id).await?;
+    Ok(Json(ApiResponse::success_with(
+        200,
+        "Passkey removed",
+        serde_json::json!({}),
+    )))
+}
+
+fn passkey_response(credential: WebauthnCredential) -> PasskeyResponse {
+    PasskeyResponse {
+        id: credential.id,
+        name: credential.name,
+        created_at: credential.created_at,
+        last_used_at: credential.last_used_at,
+    }
+}
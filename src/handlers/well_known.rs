@@ -0,0 +1,19 @@
+use crate::state::AppState;
+use axum::extract::State;
+use axum::Json;
+use jsonwebtoken::jwk::JwkSet;
+
+/// Public keys used to verify access tokens (RFC 7517 JSON Web Key Set)
+///
+/// Empty when tokens are signed with an HMAC secret, which is never published.
+#[utoipa::path(
+    get,
+    path = "/.well-known/jwks.json",
+    tag = "well-known",
+    responses(
+        (status = 200, description = "JSON Web Key Set", content_type = "application/json")
+    )
+)]
+pub async fn jwks_handler(State(state): State<AppState>) -> Json<JwkSet> {
+    Json(state.keyring.keys().jwks.clone())
+}
@@ -1,13 +1,16 @@
-use axum::{routing::get, Extension, Router};
+use axum::{routing::get, Router};
 use dotenvy::dotenv;
 use std::env;
 use std::net::SocketAddr;
+use std::process;
+use std::sync::Arc;
 use tokio::net::TcpListener; // ADD THIS
 use tower_http::cors::{Any, CorsLayer};
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
 // Impor modul yang kita buat
+mod config;
 mod db;
 mod dtos;
 mod handlers;
@@ -16,6 +19,7 @@ mod models;
 mod repositories;
 mod routes; // Menambahkan ini untuk modularisasi rute
 mod services;
+mod state;
 mod utils;
 #[derive(OpenApi)]
 #[openapi(
@@ -24,7 +28,30 @@ mod utils;
         crate::handlers::auth::login_handler,
         crate::handlers::auth::logout_handler,
         crate::handlers::auth::refresh_token_handler,
+        crate::handlers::auth::verify_email_handler,
+        crate::handlers::auth::resend_verification_handler,
+        crate::handlers::auth::forgot_password_handler,
+        crate::handlers::auth::reset_password_handler,
+        crate::handlers::auth::verify_mfa_handler,
+        crate::handlers::auth::request_magic_link_handler,
+        crate::handlers::auth::consume_magic_link_handler,
+        crate::handlers::auth::start_passkey_login_handler,
+        crate::handlers::auth::finish_passkey_login_handler,
         crate::handlers::user::get_profile,
+        crate::handlers::user::change_password_handler,
+        crate::handlers::user::list_sessions_handler,
+        crate::handlers::user::revoke_session_handler,
+        crate::handlers::user::revoke_other_sessions_handler,
+        crate::handlers::user::revoke_all_sessions_handler,
+        crate::handlers::user::enroll_mfa_handler,
+        crate::handlers::user::confirm_mfa_handler,
+        crate::handlers::user::regenerate_recovery_codes_handler,
+        crate::handlers::user::disable_mfa_handler,
+        crate::handlers::user::start_passkey_registration_handler,
+        crate::handlers::user::finish_passkey_registration_handler,
+        crate::handlers::user::list_passkeys_handler,
+        crate::handlers::user::delete_passkey_handler,
+        crate::handlers::well_known::jwks_handler,
     ),
     components(
         schemas(
@@ -33,17 +60,58 @@ mod utils;
             crate::models::user::UserProfile,
             crate::models::token::TokenPair,
             crate::models::token::AuthClaims,
+            crate::models::token::TokenUse,
             crate::dtos::auth::LoginRequest,
+            crate::dtos::auth::VerifyEmailRequest,
+            crate::dtos::auth::ResendVerificationRequest,
+            crate::dtos::auth::ForgotPasswordRequest,
+            crate::dtos::auth::ResetPasswordRequest,
+            crate::dtos::auth::VerifyMfaRequest,
+            crate::dtos::auth::MagicLinkRequest,
+            crate::dtos::auth::MagicLinkResponse,
+            crate::dtos::auth::ConsumeMagicLinkRequest,
+            crate::dtos::auth::MfaChallengeResponse,
             crate::dtos::auth::TokenResponse,
+            crate::dtos::user::ChangePasswordRequest,
+            crate::dtos::user::MfaCodeRequest,
+            crate::dtos::user::DisableMfaRequest,
+            crate::dtos::user::TotpEnrollmentResponse,
+            crate::dtos::user::RecoveryCodesResponse,
+            crate::dtos::user::SessionResponse,
+            crate::dtos::webauthn::PasskeyCreationOptions,
+            crate::dtos::webauthn::PasskeyRequestOptions,
+            crate::dtos::webauthn::RelyingParty,
+            crate::dtos::webauthn::PasskeyUser,
+            crate::dtos::webauthn::CredentialParameters,
+            crate::dtos::webauthn::CredentialDescriptor,
+            crate::dtos::webauthn::AuthenticatorSelection,
+            crate::dtos::webauthn::RegistrationCredential,
+            crate::dtos::webauthn::AttestationResponse,
+            crate::dtos::webauthn::AuthenticationCredential,
+            crate::dtos::webauthn::AssertionResponse,
+            crate::dtos::webauthn::FinishPasskeyRegistrationRequest,
+            crate::dtos::webauthn::PasskeyLoginRequest,
+            crate::dtos::webauthn::PasskeyResponse,
             crate::dtos::common::ApiResponse,
             crate::dtos::common::ApiResponseTokenEnvelope,
             crate::dtos::common::ApiResponseEmptyEnvelope,
             crate::dtos::common::ApiResponseUserProfileEnvelope,
+            crate::dtos::common::ApiResponseMfaChallengeEnvelope,
+            crate::dtos::common::ApiResponseMagicLinkEnvelope,
+            crate::dtos::common::ApiResponseTotpEnrollmentEnvelope,
+            crate::dtos::common::ApiResponseRecoveryCodesEnvelope,
+            crate::dtos::common::ApiResponseSessionListEnvelope,
+            crate::dtos::common::ApiResponsePasskeyCreationEnvelope,
+            crate::dtos::common::ApiResponsePasskeyRequestEnvelope,
+            crate::dtos::common::ApiResponsePasskeyEnvelope,
+            crate::dtos::common::ApiResponsePasskeyListEnvelope,
+            crate::dtos::common::ApiErrorEnvelope,
         )
     ),
     tags(
         (name = "auth", description = "Authentication endpoints"),
-        (name = "user", description = "User endpoints")
+        (name = "user", description = "User endpoints"),
+        (name = "well-known", description = "Public metadata for token verification")
     )
 ,
     modifiers(&SecurityAddon)
@@ -75,16 +143,184 @@ impl utoipa::Modify for SecurityAddon {
     }
 }
 
+// Memuat ulang keyring JWT setiap kali proses menerima SIGHUP.
+#[cfg(unix)]
+async fn reload_keys_on_sighup(keyring: Arc<services::keys::KeyRing>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = signal(SignalKind::hangup()).expect("Failed to listen for SIGHUP");
+    while hangup.recv().await.is_some() {
+        match keyring.reload() {
+            Ok(()) => println!("🔑 JWT keys reloaded"),
+            Err(e) => eprintln!("Failed to reload JWT keys, keeping previous keys: {}", e),
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn reload_keys_on_sighup(_keyring: Arc<services::keys::KeyRing>) {}
+
 #[tokio::main]
 async fn main() {
     // Memuat variabel lingkungan dari file .env
     dotenv().ok();
 
+    let args: Vec<String> = env::args().collect();
+    let cli_command = matches!(
+        args.get(1).map(String::as_str),
+        Some("rotate-keys" | "unlock-account" | "log-out-user")
+    );
+
+    // Memuat dan memvalidasi seluruh konfigurasi sekali saat startup. Server juga memuat kunci JWT,
+    // kebijakan & hashing password, serta pengiriman email; perintah CLI hanya butuh konfigurasinya.
+    // Semua kesalahan dilaporkan sekaligus sebelum server mulai mendengarkan.
+    let loaded = if cli_command {
+        config::AppConfig::load().map(|config| (config, None))
+    } else {
+        config::AppConfig::load_with_resources()
+            .map(|(config, resources)| (config, Some(resources)))
+    };
+    let (config, resources) = loaded.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+
+    // Perintah CLI: `rotate-keys <kid>` menjadikan <kid> kunci penandatangan di JWT_KEYS_DIR.
+    // Server yang sedang berjalan memuat ulang kunci saat menerima SIGHUP.
+    if args.get(1).map(String::as_str) == Some("rotate-keys") {
+        let Some(kid) = args.get(2) else {
+            eprintln!("Usage: rotate-keys <kid>");
+            process::exit(1);
+        };
+        if let Err(e) = services::keys::promote_signing_key(&config.jwt, kid) {
+            eprintln!("Failed to rotate signing key: {}", e);
+            process::exit(1);
+        }
+        println!(
+            "🔑 Signing key is now '{}'. Send SIGHUP to running servers to reload.",
+            kid
+        );
+        return;
+    }
+
+    let config = Arc::new(config);
+
     // 1. Menyiapkan Pool Koneksi Database
-    let db_pool = db::setup_db_pool()
+    let db_pool = db::setup_db_pool(&config.database)
         .await
         .expect("Failed to connect to the database");
 
+    // Perintah CLI: `unlock-account <email>` menghapus lockout login akun tersebut.
+    if args.get(1).map(String::as_str) == Some("unlock-account") {
+        let Some(email) = args.get(2) else {
+            eprintln!("Usage: unlock-account <email>");
+            process::exit(1);
+        };
+        let unlocked = services::login_throttle::unlock_account(&db_pool, email)
+            .await
+            .unwrap_or_else(|e| {
+                eprintln!("Failed to unlock account: {}", e);
+                process::exit(1);
+            });
+        if unlocked {
+            println!("🔓 Cleared failed logins for '{}'.", email);
+        } else {
+            println!("No failed logins recorded for '{}'.", email);
+        }
+        return;
+    }
+
+    // Perintah CLI: `log-out-user <email>` mengakhiri seluruh sesi pengguna dan membatalkan
+    // semua access token-nya, misalnya saat akun diblokir. Server lain menyadarinya dalam 30 detik.
+    if args.get(1).map(String::as_str) == Some("log-out-user") {
+        let Some(email) = args.get(2) else {
+            eprintln!("Usage: log-out-user <email>");
+            process::exit(1);
+        };
+        let user = match repositories::user::find_user_by_email(&db_pool, email).await {
+            Ok(Some(user)) => user,
+            Ok(None) => {
+                eprintln!("No user with email '{}'.", email);
+                process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("Failed to look up user: {}", e);
+                process::exit(1);
+            }
+        };
+        let token_versions = services::token_version::TokenVersions::new();
+        if let Err(e) =
+            services::session::revoke_all_sessions(&db_pool, &token_versions, user.id).await
+        {
+            eprintln!("Failed to log out user: {}", e);
+            process::exit(1);
+        }
+        println!("🚪 Logged '{}' out everywhere.", email);
+        return;
+    }
+
+    let config::Resources {
+        keyring,
+        password_policy,
+        password_hasher,
+        outbox,
+    } = resources.expect("resources are loaded for the server");
+    let keyring = Arc::new(keyring);
+    tokio::spawn(reload_keys_on_sighup(keyring.clone()));
+    let password_policy = Arc::new(password_policy);
+    let password_hasher = Arc::new(password_hasher);
+    let login_throttle = Arc::new(services::login_throttle::LoginThrottle::new(
+        &config.login_throttle,
+    ));
+    let rate_limiter = Arc::new(services::rate_limit::RateLimiter::new(
+        &config.rate_limit,
+        db_pool.clone(),
+    ));
+    let outbox = Arc::new(outbox);
+    if config.mail.transport == config::MailTransportKind::Log {
+        println!("⚠️  MAIL_TRANSPORT is 'log'; emails are printed to stdout with their links and codes. Set MAIL_TRANSPORT=smtp in production");
+    }
+    let email_verifier = Arc::new(services::email_verification::EmailVerifier::new(
+        &config.email_verification,
+        &config.server.public_url,
+        outbox.clone(),
+    ));
+    let token_versions = Arc::new(services::token_version::TokenVersions::new());
+    let password_resetter = Arc::new(services::password_reset::PasswordResetter::new(
+        &config.password_reset,
+        &config.server.public_url,
+        outbox.clone(),
+        token_versions.clone(),
+    ));
+    let magic_links = Arc::new(services::magic_link::MagicLinks::new(
+        &config.magic_link,
+        &config.server.public_url,
+        outbox.clone(),
+    ));
+    let mfa = Arc::new(services::mfa::Mfa::new(&config.mfa));
+    if config.mfa.encryption_key.is_none() {
+        println!("ℹ️  MFA_ENCRYPTION_KEY is not set; two-factor enrollment is disabled");
+    }
+    let passkeys = Arc::new(services::webauthn::Passkeys::new(&config.webauthn));
+
+    // State bersama untuk seluruh handler: pool database, konfigurasi, keyring JWT, kebijakan & hashing password, pembatas login & rate limit, pengiriman email, verifikasi email, reset password & magic link, MFA, passkey, serta cache versi token
+    let state = state::AppState {
+        db: db_pool,
+        config: config.clone(),
+        keyring,
+        password_policy,
+        password_hasher,
+        login_throttle,
+        rate_limiter,
+        outbox,
+        email_verifier,
+        password_resetter,
+        magic_links,
+        mfa,
+        passkeys,
+        token_versions,
+    };
+
     // 2. Mengatur CORS (Cross-Origin Resource Sharing)
     // Ini penting untuk mengizinkan permintaan dari domain lain (misalnya, frontend)
     let cors = CorsLayer::new().allow_origin(Any).allow_headers(Any);
@@ -96,28 +332,30 @@ async fn main() {
         .route("/", get(|| async { "OK" }))
         .route("/healthz", get(|| async { "ok" }))
         // Menggabungkan rute-rute otentikasi dari `routes/auth.rs`
-        .nest("/api/auth", routes::auth::auth_routes())
-        .nest("/api/user", routes::user::user_routes())
+        .nest("/api/auth", routes::auth::auth_routes(&state))
+        .nest("/api/user", routes::user::user_routes(&state))
+        .nest("/.well-known", routes::well_known::well_known_routes())
         // Swagger UI & OpenAPI JSON
         .merge(SwaggerUi::new("/docs").url("/api-doc/openapi.json", ApiDoc::openapi()))
         // // Menambahkan rute lain yang memerlukan autentikasi
         // .route("/api/protected", get(handlers::user::protected_handler))
-        // Menyisipkan pool database sebagai 'Extension' agar bisa diakses oleh handlers
-        .layer(Extension(db_pool))
+        // Menyisipkan state bersama agar bisa diakses oleh handlers melalui `State<AppState>`
+        .with_state(state)
         // Menerapkan middleware CORS ke seluruh aplikasi
         .layer(cors);
 
     // 4. Mengatur Alamat Server
-    let addr: SocketAddr = env::var("BIND_ADDRESS")
-        .unwrap_or_else(|_| "127.0.0.1:3000".to_string())
-        .parse()
-        .expect("Invalid BIND_ADDRESS format");
+    let addr = config.server.bind_address;
 
     println!("🚀 Server listening on {}", addr);
 
     // 5. Menjalankan Server Axum
     let listener = TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app.into_make_service())
-        .await
-        .unwrap();
+    // ConnectInfo dibutuhkan untuk mengetahui IP klien (lihat `middleware::client_ip`)
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
 }
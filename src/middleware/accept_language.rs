@@ -0,0 +1,45 @@
+use axum::{
+    extract::FromRequestParts,
+    http::{header, request::Parts},
+};
+use std::convert::Infallible;
+
+/// Language tags from the `Accept-Language` header, most preferred first (e.g.
+/// `["id-ID", "id", "en"]`). Empty when the header is missing; `*` and tags with `q=0`
+/// are dropped.
+pub struct AcceptLanguage(pub Vec<String>);
+
+#[axum::async_trait]
+impl<S> FromRequestParts<S> for AcceptLanguage
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let header = parts
+            .headers
+            .get(header::ACCEPT_LANGUAGE)
+            .and_then(|h| h.to_str().ok())
+            .unwrap_or_default();
+
+        let mut languages: Vec<(String, f32)> = header
+            .split(',')
+            .filter_map(|entry| {
+                let mut params = entry.split(';');
+                let tag = params.next()?.trim();
+                let quality = params
+                    .filter_map(|p| p.trim().strip_prefix("q="))
+                    .find_map(|q| q.parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                (!tag.is_empty() && tag != "*" && quality > 0.0).then(|| (tag.to_string(), quality))
+            })
+            .collect();
+        // Stable, so equally weighted tags keep the client's order.
+        languages.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        Ok(AcceptLanguage(
+            languages.into_iter().map(|(tag, _)| tag).collect(),
+        ))
+    }
+}
@@ -1,39 +1,75 @@
 use axum::{
-    extract::{FromRequestParts}, // Removed State
-    http::{request::Parts, StatusCode},
-    response::{IntoResponse, Response},
+    extract::{FromRef, FromRequestParts},
+    http::request::Parts,
 };
 // REMOVED: use axum_extra::extract::PrivateCookie;
+use crate::models::token::AuthClaims;
 use crate::services::token::validate_access_token;
+use crate::state::AppState;
+use crate::utils::errors::AppError;
 use uuid::Uuid;
+
+/// The user of a valid access token that has not been invalidated by a token version bump.
 pub struct AuthenticatedUser(pub Uuid);
 
+/// Like `AuthenticatedUser`, plus the session (refresh token family) the access token was
+/// issued for. `session_id` is `None` for access tokens issued before sessions were tracked.
+pub struct AuthenticatedSession {
+    pub user_id: Uuid,
+    pub session_id: Option<Uuid>,
+}
+
 #[axum::async_trait]
 impl<S> FromRequestParts<S> for AuthenticatedUser
 where
+    AppState: FromRef<S>,
     S: Send + Sync,
 {
-    type Rejection = Response;
-
-    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
-        let auth_header = parts
-            .headers
-            .get("Authorization")
-            .and_then(|h| h.to_str().ok());
-
-        let token = if let Some(header) = auth_header {
-            if let Some(token_str) = header.strip_prefix("Bearer ") {
-                token_str
-            } else {
-                return Err(StatusCode::UNAUTHORIZED.into_response());
-            }
-        } else {
-            return Err(StatusCode::UNAUTHORIZED.into_response());
-        };
+    type Rejection = AppError;
 
-        match validate_access_token(token) {
-            Ok(claims) => Ok(AuthenticatedUser(claims.sub)),
-            Err(_) => Err(StatusCode::UNAUTHORIZED.into_response()),
-        }
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let claims = authenticate(parts, &AppState::from_ref(state)).await?;
+        Ok(AuthenticatedUser(claims.sub))
+    }
+}
+
+#[axum::async_trait]
+impl<S> FromRequestParts<S> for AuthenticatedSession
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let claims = authenticate(parts, &AppState::from_ref(state)).await?;
+        Ok(AuthenticatedSession {
+            user_id: claims.sub,
+            session_id: claims.sid,
+        })
     }
-}
\ No newline at end of file
+}
+
+async fn authenticate(parts: &Parts, state: &AppState) -> Result<AuthClaims, AppError> {
+    let auth_header = parts
+        .headers
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok());
+
+    let token = if let Some(header) = auth_header {
+        if let Some(token_str) = header.strip_prefix("Bearer ") {
+            token_str
+        } else {
+            return Err(AppError::Unauthorized);
+        }
+    } else {
+        return Err(AppError::Unauthorized);
+    };
+
+    let claims = validate_access_token(&state.keyring, token)?;
+    state
+        .token_versions
+        .check(&state.db, claims.sub, claims.ver)
+        .await?;
+    Ok(claims)
+}
@@ -0,0 +1,45 @@
+use crate::state::AppState;
+use axum::{
+    extract::{ConnectInfo, FromRef, FromRequestParts},
+    http::request::Parts,
+};
+use std::convert::Infallible;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+/// Address of the client making the request.
+///
+/// The peer address of the connection, or with `TRUST_PROXY_HEADERS` the address reported by
+/// the reverse proxy in front of the app: `X-Real-IP`, else the last `X-Forwarded-For` entry
+/// (the one the proxy appended; earlier entries come from the client and can be forged).
+pub struct ClientIp(pub IpAddr);
+
+#[axum::async_trait]
+impl<S> FromRequestParts<S> for ClientIp
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let state = AppState::from_ref(state);
+
+        if state.config.server.trust_proxy_headers {
+            let header = |name: &str| parts.headers.get(name).and_then(|h| h.to_str().ok());
+            let forwarded = header("X-Real-IP")
+                .or_else(|| header("X-Forwarded-For").and_then(|h| h.rsplit(',').next()))
+                .and_then(|ip| ip.trim().parse().ok());
+            if let Some(ip) = forwarded {
+                return Ok(ClientIp(ip));
+            }
+        }
+
+        // Only missing when the app is served without `into_make_service_with_connect_info`.
+        let ip = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip())
+            .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        Ok(ClientIp(ip))
+    }
+}
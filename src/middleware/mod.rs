@@ -1 +1,6 @@
-pub mod auth;
\ No newline at end of file
+pub mod accept_language;
+pub mod auth;
+pub mod client_ip;
+pub mod rate_limit;
+pub mod session_client;
+pub mod validation;
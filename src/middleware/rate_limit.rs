@@ -0,0 +1,95 @@
+use crate::config::RateLimitKey;
+use crate::middleware::client_ip::ClientIp;
+use crate::services::rate_limit::{RateLimitDecision, RouteGroup};
+use crate::services::token::validate_access_token;
+use crate::state::AppState;
+use crate::utils::errors::AppError;
+use axum::{
+    extract::{FromRequestParts, Request, State},
+    http::{request::Parts, HeaderMap, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+/// Token-bucket rate limiting for a route group, applied in `routes/` with
+/// `middleware::from_fn_with_state((state.clone(), RouteGroup::Auth), rate_limit)`.
+///
+/// Every response carries `RateLimit-Limit`, `RateLimit-Remaining`, `RateLimit-Reset` and
+/// `RateLimit-Policy`; requests over the limit get 429 `RATE_LIMITED` with `Retry-After`.
+pub async fn rate_limit(
+    State((state, group)): State<(AppState, RouteGroup)>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !state.rate_limiter.enabled() {
+        return next.run(request).await;
+    }
+
+    let (mut parts, body) = request.into_parts();
+    let key = bucket_key(&state, group, &mut parts).await;
+    let request = Request::from_parts(parts, body);
+
+    // Limits are a safeguard; an unreachable store should not take the API down with it.
+    let decision = match state.rate_limiter.check(group, &key).await {
+        Ok(decision) => decision,
+        Err(e) => {
+            eprintln!(
+                "❌ Rate limit store unavailable, request let through: {}",
+                e
+            );
+            return next.run(request).await;
+        }
+    };
+
+    let mut response = if decision.allowed {
+        next.run(request).await
+    } else {
+        AppError::RateLimited {
+            retry_after: decision.retry_after,
+        }
+        .into_response()
+    };
+    set_headers(response.headers_mut(), &decision);
+    response
+}
+
+async fn bucket_key(state: &AppState, group: RouteGroup, parts: &mut Parts) -> String {
+    let subject = match state.rate_limiter.rule(group).key {
+        RateLimitKey::Ip => None,
+        // Only a valid access token counts, so the user id cannot be forged to dodge the limit.
+        RateLimitKey::User => parts
+            .headers
+            .get("Authorization")
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "))
+            .and_then(|token| validate_access_token(&state.keyring, token).ok())
+            .map(|claims| format!("user:{}", claims.sub)),
+        // Only configured keys count, so a made-up key per request cannot dodge the limit.
+        RateLimitKey::ApiKey => parts
+            .headers
+            .get("X-API-Key")
+            .and_then(|key| state.rate_limiter.known_api_key(key.as_bytes()))
+            .map(|id| format!("key:{}", id)),
+    };
+
+    let subject = match subject {
+        Some(subject) => subject,
+        None => {
+            let ClientIp(ip) = ClientIp::from_request_parts(parts, state)
+                .await
+                .unwrap_or_else(|never| match never {});
+            format!("ip:{}", ip)
+        }
+    };
+    format!("{}:{}", group.name(), subject)
+}
+
+fn set_headers(headers: &mut HeaderMap, decision: &RateLimitDecision) {
+    headers.insert("RateLimit-Limit", HeaderValue::from(decision.limit));
+    headers.insert("RateLimit-Remaining", HeaderValue::from(decision.remaining));
+    headers.insert("RateLimit-Reset", HeaderValue::from(decision.reset));
+    if let Ok(policy) = HeaderValue::from_str(&format!("{};w={}", decision.limit, decision.window))
+    {
+        headers.insert("RateLimit-Policy", policy);
+    }
+}
@@ -0,0 +1,38 @@
+use crate::middleware::client_ip::ClientIp;
+use crate::models::session::SessionClient;
+use crate::state::AppState;
+use axum::{
+    extract::{FromRef, FromRequestParts},
+    http::{header, request::Parts},
+};
+use std::convert::Infallible;
+
+// Longest `User-Agent` stored with a session, in characters.
+const MAX_USER_AGENT_LENGTH: usize = 512;
+
+/// The client details recorded with a new session: its `User-Agent` (if any, truncated) and
+/// its address as resolved by `ClientIp`.
+#[axum::async_trait]
+impl<S> FromRequestParts<S> for SessionClient
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let ClientIp(ip_address) = ClientIp::from_request_parts(parts, state).await?;
+        let user_agent = parts
+            .headers
+            .get(header::USER_AGENT)
+            .and_then(|h| h.to_str().ok())
+            .map(str::trim)
+            .filter(|ua| !ua.is_empty())
+            .map(|ua| ua.chars().take(MAX_USER_AGENT_LENGTH).collect());
+
+        Ok(SessionClient {
+            user_agent,
+            ip_address,
+        })
+    }
+}
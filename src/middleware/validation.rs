@@ -0,0 +1,52 @@
+use crate::utils::errors::AppError;
+use axum::{
+    extract::{rejection::JsonRejection, FromRequest, Request},
+    Json,
+};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use validator::{Validate, ValidationErrors};
+
+/// `Json<T>` that also runs `T`'s `#[validate(...)]` rules.
+///
+/// Malformed bodies are rejected with `INVALID_REQUEST_BODY`, bodies that break a rule
+/// with 422 `VALIDATION_FAILED` and the messages per field under `errors`.
+pub struct ValidatedJson<T>(pub T);
+
+#[axum::async_trait]
+impl<T, S> FromRequest<S> for ValidatedJson<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) =
+            Json::<T>::from_request(req, state)
+                .await
+                .map_err(|rejection: JsonRejection| {
+                    AppError::InvalidBody(rejection.status(), rejection.body_text())
+                })?;
+        value.validate().map_err(field_errors)?;
+        Ok(ValidatedJson(value))
+    }
+}
+
+fn field_errors(errors: ValidationErrors) -> AppError {
+    let fields = errors
+        .field_errors()
+        .into_iter()
+        .map(|(field, errors)| {
+            let messages = errors
+                .iter()
+                .map(|e| match &e.message {
+                    Some(message) => message.to_string(),
+                    None => format!("Failed the {} rule", e.code),
+                })
+                .collect();
+            (field.to_string(), messages)
+        })
+        .collect::<HashMap<_, _>>();
+    AppError::Validation(fields)
+}
@@ -0,0 +1,9 @@
+use uuid::Uuid;
+
+// Baris pada tabel `user_totp`. `secret` masih terenkripsi (nonce 12 byte diikuti ciphertext).
+#[derive(Debug, sqlx::FromRow)]
+pub struct UserTotp {
+    pub user_id: Uuid,
+    pub secret: Vec<u8>,
+    pub enabled_at: Option<i64>,
+}
@@ -1,2 +1,5 @@
+pub mod mfa;
+pub mod session;
 pub mod token;
-pub mod user;
\ No newline at end of file
+pub mod user;
+pub mod webauthn;
@@ -0,0 +1,19 @@
+use std::net::IpAddr;
+use uuid::Uuid;
+
+// Klien yang memulai sesi: dicatat saat login agar pengguna bisa mengenali perangkatnya.
+#[derive(Debug, Clone)]
+pub struct SessionClient {
+    pub user_agent: Option<String>,
+    pub ip_address: IpAddr,
+}
+
+// Baris pada tabel `sessions`. `id` sama dengan `family_id` refresh token dan klaim `sid`.
+#[derive(Debug, sqlx::FromRow)]
+pub struct Session {
+    pub id: Uuid,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: i64,
+    pub last_used_at: i64,
+}
@@ -5,12 +5,51 @@ use uuid::Uuid;
 // Struct untuk menyimpan data klaim yang akan dimasukkan ke dalam JWT.
 // 'sub' (subject) biasanya adalah ID pengguna.
 // 'exp' (expiration) adalah waktu kedaluwarsa token.
+// 'iat' (issued at) adalah waktu token dibuat.
+// 'jti' (JWT ID) adalah ID unik token, dipakai untuk melacak refresh token.
+// 'iss' dan 'aud' adalah penerbit dan audiens token, diverifikasi saat validasi.
+// 'sid' adalah ID sesi (family refresh token) tempat token diterbitkan; kosong pada token sekali pakai.
+// 'ver' adalah versi token pengguna saat access token diterbitkan (0 pada jenis token lain).
+// Access token dengan versi lebih lama dari `users.token_version` ditolak.
+// Token lama tanpa 'ver' dianggap versi 0.
+// 'token_use' membedakan access token, refresh token dan token sekali pakai (verifikasi email, reset password, tantangan MFA) agar tidak bisa saling dipakai.
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct AuthClaims {
     #[schema(example = "550e8400-e29b-41d4-a716-446655440000")]
     pub sub: Uuid,
     #[schema(example = 1700000000)]
     pub exp: i64,
+    #[schema(example = 1699999100)]
+    pub iat: i64,
+    #[schema(example = "0b4f2c1e-8d3a-4f5b-9c6d-7e8f9a0b1c2d")]
+    pub jti: Uuid,
+    #[schema(example = "rust-axum-pg-boilerplate")]
+    pub iss: String,
+    #[schema(example = "rust-axum-pg-boilerplate")]
+    pub aud: String,
+    pub token_use: TokenUse,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "7c9e6679-7425-40de-944b-e07fc1f90ae7")]
+    pub sid: Option<Uuid>,
+    #[serde(default)]
+    #[schema(example = 0)]
+    pub ver: i32,
+}
+
+// Jenis token yang disimpan di klaim 'token_use'.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenUse {
+    Access,
+    Refresh,
+    #[serde(rename = "email_verification")]
+    EmailVerification,
+    #[serde(rename = "password_reset")]
+    PasswordReset,
+    #[serde(rename = "mfa_challenge")]
+    MfaChallenge,
+    #[serde(rename = "magic_link")]
+    MagicLink,
 }
 
 // Struct untuk mengelola pasangan token (access token dan refresh token)
@@ -22,3 +61,13 @@ pub struct TokenPair {
     #[schema(example = "dGhpcy1pcz1hLXJlZnJlc2gtdG9rZW4tZXhhbXBsZQ...")]
     pub refresh_token: String,
 }
+
+// Baris pada tabel `refresh_tokens`. Hanya hash dari `jti` yang disimpan,
+// sehingga isi tabel tidak bisa dipakai untuk memalsukan token.
+#[derive(Debug, sqlx::FromRow)]
+pub struct RefreshToken {
+    pub user_id: Uuid,
+    pub family_id: Uuid,
+    pub expires_at: i64,
+    pub revoked_at: Option<i64>,
+}
@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 use uuid::Uuid;
+use validator::{Validate, ValidationError};
 
 #[derive(Serialize, Deserialize, Debug, sqlx::FromRow, ToSchema)]
 pub struct User {
@@ -16,18 +17,49 @@ pub struct User {
     pub created_at: i64,
     #[schema(example = 1696166400)] // Example Unix timestamp
     pub updated_at: i64,
+    /// When the email address was verified; `null` while unverified
+    #[schema(example = 1696166400)] // Example Unix timestamp
+    pub email_verified_at: Option<i64>,
 }
 
-#[derive(Serialize, Deserialize, Debug, ToSchema)]
+#[derive(Serialize, Deserialize, Debug, ToSchema, Validate)]
 pub struct NewUser {
-    #[schema(example = "alice")]
+    /// Letters, digits, `_`, `-` and `.`
+    #[schema(
+        example = "alice",
+        min_length = 3,
+        max_length = 32,
+        pattern = r"^[A-Za-z0-9_.-]+$"
+    )]
+    #[validate(
+        length(min = 3, max = 32, message = "Must be between 3 and 32 characters"),
+        custom(function = "validate_username")
+    )]
     pub username: String,
-    #[schema(example = "alice@example.com")]
+    #[schema(example = "alice@example.com", format = "email", max_length = 255)]
+    #[validate(
+        email(message = "Must be a valid email address"),
+        length(max = 255, message = "Must be at most 255 characters")
+    )]
     pub email: String,
-    #[schema(example = "Passw0rd!")]
+    // Only bounds the request size; strength rules live in the configured password policy.
+    #[schema(example = "correct-Horse-battery-9", format = Password, min_length = 1, max_length = 1024)]
+    #[validate(length(min = 1, max = 1024, message = "Must be between 1 and 1024 characters"))]
     pub password: String,
 }
 
+fn validate_username(username: &str) -> Result<(), ValidationError> {
+    if username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+    {
+        Ok(())
+    } else {
+        Err(ValidationError::new("username")
+            .with_message("May only contain letters, digits, '_', '-' and '.'".into()))
+    }
+}
+
 #[derive(Debug, Serialize, ToSchema)]
 pub struct UserProfile {
     #[schema(example = "550e8400-e29b-41d4-a716-446655440000")]
@@ -40,4 +72,7 @@ pub struct UserProfile {
     pub created_at: i64,
     #[schema(example = 1696166400)] // Example Unix timestamp
     pub updated_at: i64,
+    /// When the email address was verified; `null` while unverified
+    #[schema(example = 1696166400)] // Example Unix timestamp
+    pub email_verified_at: Option<i64>,
 }
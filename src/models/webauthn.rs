@@ -0,0 +1,32 @@
+use uuid::Uuid;
+
+// Baris pada tabel `webauthn_credentials` (passkey milik pengguna).
+// `public_key` adalah COSE_Key dari authenticator, disimpan apa adanya.
+#[derive(Debug, sqlx::FromRow)]
+pub struct WebauthnCredential {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub credential_id: Vec<u8>,
+    pub public_key: Vec<u8>,
+    pub sign_count: i64,
+    pub transports: Vec<String>,
+    pub name: String,
+    pub created_at: i64,
+    pub last_used_at: Option<i64>,
+}
+
+// Jenis seremoni WebAuthn, disimpan di kolom `webauthn_challenges.ceremony`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ceremony {
+    Registration,
+    Authentication,
+}
+
+impl Ceremony {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Ceremony::Registration => "registration",
+            Ceremony::Authentication => "authentication",
+        }
+    }
+}
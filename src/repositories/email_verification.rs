@@ -0,0 +1,69 @@
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+pub async fn insert_email_verification_token(
+    pool: &Pool<Postgres>,
+    user_id: Uuid,
+    token_hash: &str,
+    email: &str,
+    expires_at: i64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO email_verification_tokens (user_id, token_hash, email, expires_at)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        user_id,
+        token_hash,
+        email,
+        expires_at
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Marks the token as used if it is unused and unexpired, returning the user and email it
+/// was issued for. A single conditional update, so a token cannot be redeemed twice.
+pub async fn use_email_verification_token(
+    pool: &Pool<Postgres>,
+    token_hash: &str,
+    now: i64,
+) -> Result<Option<(Uuid, String)>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE email_verification_tokens
+        SET used_at = $2
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > $2
+        RETURNING user_id, email
+        "#,
+        token_hash,
+        now
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| (row.user_id, row.email)))
+}
+
+/// Retires every unused token of the user, e.g. before a new one is sent.
+pub async fn invalidate_email_verification_tokens(
+    pool: &Pool<Postgres>,
+    user_id: Uuid,
+    now: i64,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE email_verification_tokens
+        SET used_at = $2
+        WHERE user_id = $1 AND used_at IS NULL
+        "#,
+        user_id,
+        now
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,112 @@
+use sqlx::{Pool, Postgres};
+
+pub const ACCOUNT_SCOPE: &str = "account";
+pub const IP_SCOPE: &str = "ip";
+
+/// The scope (`ACCOUNT_SCOPE` or `IP_SCOPE`) and `locked_until` of whichever of the account
+/// and IP rows is locked longest, if either is locked.
+pub async fn find_active_lockout(
+    pool: &Pool<Postgres>,
+    account_key: &str,
+    ip_key: &str,
+    now: i64,
+) -> Result<Option<(&'static str, i64)>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT scope, locked_until AS "locked_until!"
+        FROM login_throttles
+        WHERE ((scope = $1 AND key = $2) OR (scope = $3 AND key = $4))
+            AND locked_until > $5
+        ORDER BY locked_until DESC
+        LIMIT 1
+        "#,
+        ACCOUNT_SCOPE,
+        account_key,
+        IP_SCOPE,
+        ip_key,
+        now
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| {
+        let scope = if row.scope == ACCOUNT_SCOPE {
+            ACCOUNT_SCOPE
+        } else {
+            IP_SCOPE
+        };
+        (scope, row.locked_until)
+    }))
+}
+
+/// Counts one more failure and returns the total within the window. A row whose last
+/// failure is older than `window_start` starts again from one.
+pub async fn record_login_failure(
+    pool: &Pool<Postgres>,
+    scope: &str,
+    key: &str,
+    now: i64,
+    window_start: i64,
+) -> Result<i32, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        INSERT INTO login_throttles (scope, key, failures, last_failure_at)
+        VALUES ($1, $2, 1, $3)
+        ON CONFLICT (scope, key) DO UPDATE SET
+            failures = CASE
+                WHEN login_throttles.last_failure_at < $4 THEN 1
+                ELSE login_throttles.failures + 1
+            END,
+            last_failure_at = $3
+        RETURNING failures
+        "#,
+        scope,
+        key,
+        now,
+        window_start
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(row.failures)
+}
+
+pub async fn set_locked_until(
+    pool: &Pool<Postgres>,
+    scope: &str,
+    key: &str,
+    locked_until: i64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE login_throttles SET locked_until = $3
+        WHERE scope = $1 AND key = $2
+        "#,
+        scope,
+        key,
+        locked_until
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Forgets every failure recorded for `key`. Returns whether there was anything to clear.
+pub async fn clear_login_failures(
+    pool: &Pool<Postgres>,
+    scope: &str,
+    key: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM login_throttles WHERE scope = $1 AND key = $2
+        "#,
+        scope,
+        key
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
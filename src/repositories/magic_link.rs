@@ -0,0 +1,92 @@
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+pub async fn insert_magic_link_token(
+    pool: &Pool<Postgres>,
+    user_id: Uuid,
+    token_hash: &str,
+    email: &str,
+    device_hash: Option<&str>,
+    expires_at: i64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO magic_link_tokens (user_id, token_hash, email, device_hash, expires_at)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        user_id,
+        token_hash,
+        email,
+        device_hash,
+        expires_at
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Marks the token as used if it is unused, unexpired and either unbound or bound to
+/// `device_hash`, returning the user and email it was issued for. A single conditional update,
+/// so a link cannot be redeemed twice, and a bound link stays usable when redeemed elsewhere.
+pub async fn use_magic_link_token(
+    pool: &Pool<Postgres>,
+    token_hash: &str,
+    device_hash: Option<&str>,
+    now: i64,
+) -> Result<Option<(Uuid, String)>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE magic_link_tokens
+        SET used_at = $3
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > $3
+            AND (device_hash IS NULL OR device_hash = $2)
+        RETURNING user_id, email
+        "#,
+        token_hash,
+        device_hash,
+        now
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| (row.user_id, row.email)))
+}
+
+/// Retires every unused link of the user, e.g. once one of them has been used.
+pub async fn invalidate_magic_link_tokens(
+    pool: &Pool<Postgres>,
+    user_id: Uuid,
+    now: i64,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE magic_link_tokens
+        SET used_at = $2
+        WHERE user_id = $1 AND used_at IS NULL
+        "#,
+        user_id,
+        now
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Drops links that expired before `now`, used or not; neither can be redeemed any more.
+pub async fn delete_expired_magic_link_tokens(
+    pool: &Pool<Postgres>,
+    now: i64,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM magic_link_tokens WHERE expires_at <= $1
+        "#,
+        now
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,220 @@
+use crate::models::mfa::UserTotp;
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+pub async fn find_user_totp(
+    pool: &Pool<Postgres>,
+    user_id: Uuid,
+) -> Result<Option<UserTotp>, sqlx::Error> {
+    sqlx::query_as!(
+        UserTotp,
+        r#"
+        SELECT user_id, secret, enabled_at
+        FROM user_totp
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Stores a new, not yet confirmed secret, replacing an earlier unconfirmed one. Returns
+/// `false` if the user already has TOTP enabled, in which case nothing changes.
+pub async fn upsert_pending_totp(
+    pool: &Pool<Postgres>,
+    user_id: Uuid,
+    secret: &[u8],
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO user_totp (user_id, secret)
+        VALUES ($1, $2)
+        ON CONFLICT (user_id) DO UPDATE
+        SET secret = EXCLUDED.secret, last_used_step = NULL, created_at = EXTRACT(EPOCH FROM now())
+        WHERE user_totp.enabled_at IS NULL
+        "#,
+        user_id,
+        secret
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Enables a pending secret, recording `step` as used. Returns `false` if it was not pending.
+pub async fn enable_totp(
+    pool: &Pool<Postgres>,
+    user_id: Uuid,
+    step: i64,
+    now: i64,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE user_totp
+        SET enabled_at = $3, last_used_step = $2
+        WHERE user_id = $1 AND enabled_at IS NULL
+        "#,
+        user_id,
+        step,
+        now
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Records `step` as used if it is later than the last used one. A single conditional update,
+/// so the same code cannot be accepted twice.
+pub async fn use_totp_step(
+    pool: &Pool<Postgres>,
+    user_id: Uuid,
+    step: i64,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE user_totp
+        SET last_used_step = $2
+        WHERE user_id = $1 AND enabled_at IS NOT NULL
+            AND (last_used_step IS NULL OR last_used_step < $2)
+        "#,
+        user_id,
+        step
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Removes the user's TOTP secret and recovery codes.
+pub async fn delete_user_mfa(pool: &Pool<Postgres>, user_id: Uuid) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query!("DELETE FROM user_totp WHERE user_id = $1", user_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!("DELETE FROM mfa_recovery_codes WHERE user_id = $1", user_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await
+}
+
+/// Replaces all of the user's recovery codes with `code_hashes`.
+pub async fn replace_recovery_codes(
+    pool: &Pool<Postgres>,
+    user_id: Uuid,
+    code_hashes: &[String],
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query!("DELETE FROM mfa_recovery_codes WHERE user_id = $1", user_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO mfa_recovery_codes (user_id, code_hash)
+        SELECT $1, code_hash FROM UNNEST($2::VARCHAR(64)[]) AS code_hash
+        "#,
+        user_id,
+        code_hashes
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await
+}
+
+/// Marks an unused recovery code of the user as used. Returns `false` if there is none.
+pub async fn use_recovery_code(
+    pool: &Pool<Postgres>,
+    user_id: Uuid,
+    code_hash: &str,
+    now: i64,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE mfa_recovery_codes
+        SET used_at = $3
+        WHERE id = (
+            SELECT id FROM mfa_recovery_codes
+            WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+            LIMIT 1
+        )
+        "#,
+        user_id,
+        code_hash,
+        now
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn insert_mfa_challenge(
+    pool: &Pool<Postgres>,
+    user_id: Uuid,
+    token_hash: &str,
+    expires_at: i64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO mfa_challenges (user_id, token_hash, expires_at)
+        VALUES ($1, $2, $3)
+        "#,
+        user_id,
+        token_hash,
+        expires_at
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Counts an attempt against an open challenge, returning the user it was issued for.
+/// Returns `None` once the challenge is used, expired or out of attempts. Counting before the
+/// code is checked keeps concurrent guesses within `max_attempts`.
+pub async fn record_mfa_challenge_attempt(
+    pool: &Pool<Postgres>,
+    token_hash: &str,
+    now: i64,
+    max_attempts: i32,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE mfa_challenges
+        SET attempts = attempts + 1
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > $2 AND attempts < $3
+        RETURNING user_id
+        "#,
+        token_hash,
+        now,
+        max_attempts
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| row.user_id))
+}
+
+/// Marks the challenge as used. Returns `false` if it already was.
+pub async fn complete_mfa_challenge(
+    pool: &Pool<Postgres>,
+    token_hash: &str,
+    now: i64,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE mfa_challenges
+        SET used_at = $2
+        WHERE token_hash = $1 AND used_at IS NULL
+        "#,
+        token_hash,
+        now
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
@@ -1,2 +1,10 @@
+pub mod email_verification;
+pub mod login_throttle;
+pub mod magic_link;
+pub mod mfa;
+pub mod password_reset;
+pub mod rate_limit;
+pub mod refresh_token;
+pub mod session;
 pub mod user;
-
+pub mod webauthn;
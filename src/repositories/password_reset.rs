@@ -0,0 +1,67 @@
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+pub async fn insert_password_reset_token(
+    pool: &Pool<Postgres>,
+    user_id: Uuid,
+    token_hash: &str,
+    expires_at: i64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO password_reset_tokens (user_id, token_hash, expires_at)
+        VALUES ($1, $2, $3)
+        "#,
+        user_id,
+        token_hash,
+        expires_at
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Marks the token as used if it is unused and unexpired, returning the user it was issued
+/// for. A single conditional update, so a token cannot be redeemed twice.
+pub async fn use_password_reset_token(
+    pool: &Pool<Postgres>,
+    token_hash: &str,
+    now: i64,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE password_reset_tokens
+        SET used_at = $2
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > $2
+        RETURNING user_id
+        "#,
+        token_hash,
+        now
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| row.user_id))
+}
+
+/// Retires every unused token of the user, e.g. once the password has been reset.
+pub async fn invalidate_password_reset_tokens(
+    pool: &Pool<Postgres>,
+    user_id: Uuid,
+    now: i64,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE password_reset_tokens
+        SET used_at = $2
+        WHERE user_id = $1 AND used_at IS NULL
+        "#,
+        user_id,
+        now
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,53 @@
+use sqlx::{Pool, Postgres};
+
+/// Refills the bucket for the time since its last update, then takes one token if there is
+/// a whole one. Returns the tokens left and whether the request was allowed; a new bucket
+/// starts full. The single statement keeps concurrent instances from double-spending.
+pub async fn take_rate_limit_token(
+    pool: &Pool<Postgres>,
+    key: &str,
+    capacity: f64,
+    tokens_per_ms: f64,
+    now_ms: i64,
+) -> Result<(f64, bool), sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        INSERT INTO rate_limit_buckets AS b (key, tokens, allowed, updated_at_ms)
+        VALUES ($1, $2::float8 - 1, TRUE, $4)
+        ON CONFLICT (key) DO UPDATE SET
+            tokens = CASE
+                WHEN LEAST($2, b.tokens + ($4 - b.updated_at_ms) * $3::float8) >= 1
+                    THEN LEAST($2, b.tokens + ($4 - b.updated_at_ms) * $3::float8) - 1
+                ELSE LEAST($2, b.tokens + ($4 - b.updated_at_ms) * $3::float8)
+            END,
+            allowed = LEAST($2, b.tokens + ($4 - b.updated_at_ms) * $3::float8) >= 1,
+            updated_at_ms = $4
+        RETURNING tokens, allowed
+        "#,
+        key,
+        capacity,
+        tokens_per_ms,
+        now_ms
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok((row.tokens, row.allowed))
+}
+
+/// Drops buckets untouched since `before_ms`; they would have refilled completely anyway.
+pub async fn delete_idle_rate_limit_buckets(
+    pool: &Pool<Postgres>,
+    before_ms: i64,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM rate_limit_buckets WHERE updated_at_ms < $1
+        "#,
+        before_ms
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,189 @@
+use crate::models::token::RefreshToken;
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+pub async fn insert_refresh_token(
+    pool: &Pool<Postgres>,
+    user_id: Uuid,
+    family_id: Uuid,
+    token_hash: &str,
+    expires_at: i64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO refresh_tokens (user_id, family_id, token_hash, expires_at)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        user_id,
+        family_id,
+        token_hash,
+        expires_at
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn find_refresh_token_by_hash(
+    pool: &Pool<Postgres>,
+    token_hash: &str,
+) -> Result<Option<RefreshToken>, sqlx::Error> {
+    let token = sqlx::query_as!(
+        RefreshToken,
+        r#"
+        SELECT user_id, family_id, expires_at, revoked_at
+        FROM refresh_tokens WHERE token_hash = $1
+        "#,
+        token_hash
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(token)
+}
+
+/// Marks the token as revoked. Returns `false` if it was already revoked or does not exist.
+pub async fn revoke_refresh_token_by_hash(
+    pool: &Pool<Postgres>,
+    token_hash: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE refresh_tokens
+        SET revoked_at = EXTRACT(EPOCH FROM now())
+        WHERE token_hash = $1 AND revoked_at IS NULL
+        "#,
+        token_hash
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Revokes the token with `old_token_hash` and records its successor in the same family, in
+/// one transaction. Returns `false`, recording nothing, if the old token was already revoked
+/// or does not exist.
+pub async fn replace_refresh_token(
+    pool: &Pool<Postgres>,
+    old_token_hash: &str,
+    user_id: Uuid,
+    family_id: Uuid,
+    token_hash: &str,
+    expires_at: i64,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let revoked = sqlx::query!(
+        r#"
+        UPDATE refresh_tokens
+        SET revoked_at = EXTRACT(EPOCH FROM now())
+        WHERE token_hash = $1 AND revoked_at IS NULL
+        "#,
+        old_token_hash
+    )
+    .execute(&mut *tx)
+    .await?;
+    if revoked.rows_affected() == 0 {
+        return Ok(false);
+    }
+    sqlx::query!(
+        r#"
+        INSERT INTO refresh_tokens (user_id, family_id, token_hash, expires_at)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        user_id,
+        family_id,
+        token_hash,
+        expires_at
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+/// Revokes every still-active token in the family. Returns the number of tokens revoked.
+pub async fn revoke_refresh_token_family(
+    pool: &Pool<Postgres>,
+    family_id: Uuid,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE refresh_tokens
+        SET revoked_at = EXTRACT(EPOCH FROM now())
+        WHERE family_id = $1 AND revoked_at IS NULL
+        "#,
+        family_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Revokes every still-active token of the user, logging them out everywhere.
+/// Returns the number of tokens revoked.
+pub async fn revoke_user_refresh_tokens(
+    pool: &Pool<Postgres>,
+    user_id: Uuid,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE refresh_tokens
+        SET revoked_at = EXTRACT(EPOCH FROM now())
+        WHERE user_id = $1 AND revoked_at IS NULL
+        "#,
+        user_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Revokes every still-active token of the user outside `keep_family_id`, logging out every
+/// other session. Returns the number of tokens revoked.
+pub async fn revoke_other_refresh_tokens(
+    pool: &Pool<Postgres>,
+    user_id: Uuid,
+    keep_family_id: Uuid,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE refresh_tokens
+        SET revoked_at = EXTRACT(EPOCH FROM now())
+        WHERE user_id = $1 AND family_id <> $2 AND revoked_at IS NULL
+        "#,
+        user_id,
+        keep_family_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Revokes the still-active tokens of one of the user's families, ending that session.
+/// Returns `false` if the user has no active session with this id.
+pub async fn revoke_user_refresh_token_family(
+    pool: &Pool<Postgres>,
+    user_id: Uuid,
+    family_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE refresh_tokens
+        SET revoked_at = EXTRACT(EPOCH FROM now())
+        WHERE user_id = $1 AND family_id = $2 AND revoked_at IS NULL
+            AND expires_at > EXTRACT(EPOCH FROM now())
+        "#,
+        user_id,
+        family_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
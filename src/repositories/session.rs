@@ -0,0 +1,63 @@
+use crate::models::session::Session;
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+pub async fn insert_session(
+    pool: &Pool<Postgres>,
+    id: Uuid,
+    user_id: Uuid,
+    user_agent: Option<&str>,
+    ip_address: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO sessions (id, user_id, user_agent, ip_address)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        id,
+        user_id,
+        user_agent,
+        ip_address
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Records that the session's refresh token was just used.
+pub async fn touch_session(pool: &Pool<Postgres>, id: Uuid, now: i64) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE sessions SET last_used_at = $2 WHERE id = $1",
+        id,
+        now
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// The user's sessions that can still refresh, most recently used first.
+pub async fn list_active_sessions(
+    pool: &Pool<Postgres>,
+    user_id: Uuid,
+    now: i64,
+) -> Result<Vec<Session>, sqlx::Error> {
+    sqlx::query_as!(
+        Session,
+        r#"
+        SELECT s.id, s.user_agent, s.ip_address, s.created_at, s.last_used_at
+        FROM sessions s
+        WHERE s.user_id = $1 AND EXISTS (
+            SELECT 1 FROM refresh_tokens rt
+            WHERE rt.family_id = s.id AND rt.revoked_at IS NULL AND rt.expires_at > $2
+        )
+        ORDER BY s.last_used_at DESC
+        "#,
+        user_id,
+        now
+    )
+    .fetch_all(pool)
+    .await
+}
@@ -2,6 +2,10 @@ use crate::models::user::{NewUser, User};
 use sqlx::{Pool, Postgres};
 use uuid::Uuid;
 
+// Default Postgres names of the UNIQUE constraints created by the users migration.
+pub const USERNAME_UNIQUE_CONSTRAINT: &str = "users_username_key";
+pub const EMAIL_UNIQUE_CONSTRAINT: &str = "users_email_key";
+
 pub async fn insert_user(
     pool: &Pool<Postgres>,
     new_user: &NewUser,
@@ -18,7 +22,8 @@ pub async fn insert_user(
             email, 
             password_hash, 
             EXTRACT(EPOCH FROM created_at) as "created_at!: i64",
-            EXTRACT(EPOCH FROM updated_at) as "updated_at!: i64"
+            EXTRACT(EPOCH FROM updated_at) as "updated_at!: i64",
+            email_verified_at
         "#,
         new_user.username,
         new_user.email,
@@ -43,7 +48,8 @@ pub async fn find_user_by_email(
             email, 
             password_hash, 
             EXTRACT(EPOCH FROM created_at) as "created_at!: i64",
-            EXTRACT(EPOCH FROM updated_at) as "updated_at!: i64"
+            EXTRACT(EPOCH FROM updated_at) as "updated_at!: i64",
+            email_verified_at
         FROM users WHERE email = $1
         "#,
         email
@@ -67,7 +73,8 @@ pub async fn find_user_by_id(
             email, 
             password_hash, 
             EXTRACT(EPOCH FROM created_at) as "created_at!: i64",
-            EXTRACT(EPOCH FROM updated_at) as "updated_at!: i64"
+            EXTRACT(EPOCH FROM updated_at) as "updated_at!: i64",
+            email_verified_at
         FROM users WHERE id = $1
         "#,
         user_id
@@ -77,3 +84,84 @@ pub async fn find_user_by_id(
 
     Ok(user)
 }
+
+pub async fn update_user_password_hash(
+    pool: &Pool<Postgres>,
+    user_id: Uuid,
+    password_hash: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET password_hash = $2, updated_at = now()
+        WHERE id = $1
+        "#,
+        user_id,
+        password_hash
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Marks the email as verified if it is still the user's address. Returns `false` when the
+/// user is gone or has changed their email since the token was issued.
+pub async fn mark_user_email_verified(
+    pool: &Pool<Postgres>,
+    user_id: Uuid,
+    email: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE users
+        SET email_verified_at = COALESCE(email_verified_at, EXTRACT(EPOCH FROM now())),
+            updated_at = now()
+        WHERE id = $1 AND email = $2
+        "#,
+        user_id,
+        email
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// The user's current token version, or `None` if the user does not exist.
+pub async fn find_user_token_version(
+    pool: &Pool<Postgres>,
+    user_id: Uuid,
+) -> Result<Option<i32>, sqlx::Error> {
+    let version = sqlx::query_scalar!(
+        r#"
+        SELECT token_version FROM users WHERE id = $1
+        "#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(version)
+}
+
+/// Increments the user's token version, returning the new one, or `None` if the user does
+/// not exist.
+pub async fn bump_user_token_version(
+    pool: &Pool<Postgres>,
+    user_id: Uuid,
+) -> Result<Option<i32>, sqlx::Error> {
+    let version = sqlx::query_scalar!(
+        r#"
+        UPDATE users
+        SET token_version = token_version + 1, updated_at = now()
+        WHERE id = $1
+        RETURNING token_version
+        "#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(version)
+}
@@ -0,0 +1,175 @@
+use crate::models::webauthn::{Ceremony, WebauthnCredential};
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+pub async fn insert_webauthn_challenge(
+    pool: &Pool<Postgres>,
+    challenge_hash: &str,
+    ceremony: Ceremony,
+    user_id: Option<Uuid>,
+    expires_at: i64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO webauthn_challenges (challenge_hash, ceremony, user_id, expires_at)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        challenge_hash,
+        ceremony.as_str(),
+        user_id,
+        expires_at
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Deletes the challenge if it is unexpired and belongs to `ceremony`, returning the user it
+/// was issued for (`None` inside for logins). A single conditional delete, so a challenge
+/// cannot be answered twice.
+pub async fn use_webauthn_challenge(
+    pool: &Pool<Postgres>,
+    challenge_hash: &str,
+    ceremony: Ceremony,
+    now: i64,
+) -> Result<Option<Option<Uuid>>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        DELETE FROM webauthn_challenges
+        WHERE challenge_hash = $1 AND ceremony = $2 AND expires_at > $3
+        RETURNING user_id
+        "#,
+        challenge_hash,
+        ceremony.as_str(),
+        now
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| row.user_id))
+}
+
+/// Drops challenges that expired before `now` without being answered.
+pub async fn delete_expired_webauthn_challenges(
+    pool: &Pool<Postgres>,
+    now: i64,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM webauthn_challenges WHERE expires_at <= $1
+        "#,
+        now
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Returns `None` if the credential id is already registered (to any user).
+pub async fn insert_webauthn_credential(
+    pool: &Pool<Postgres>,
+    user_id: Uuid,
+    credential_id: &[u8],
+    public_key: &[u8],
+    sign_count: i64,
+    transports: &[String],
+    name: &str,
+) -> Result<Option<WebauthnCredential>, sqlx::Error> {
+    sqlx::query_as!(
+        WebauthnCredential,
+        r#"
+        INSERT INTO webauthn_credentials (user_id, credential_id, public_key, sign_count, transports, name)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (credential_id) DO NOTHING
+        RETURNING id, user_id, credential_id, public_key, sign_count, transports, name, created_at, last_used_at
+        "#,
+        user_id,
+        credential_id,
+        public_key,
+        sign_count,
+        transports,
+        name
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn find_webauthn_credential(
+    pool: &Pool<Postgres>,
+    credential_id: &[u8],
+) -> Result<Option<WebauthnCredential>, sqlx::Error> {
+    sqlx::query_as!(
+        WebauthnCredential,
+        r#"
+        SELECT id, user_id, credential_id, public_key, sign_count, transports, name, created_at, last_used_at
+        FROM webauthn_credentials
+        WHERE credential_id = $1
+        "#,
+        credential_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn list_webauthn_credentials(
+    pool: &Pool<Postgres>,
+    user_id: Uuid,
+) -> Result<Vec<WebauthnCredential>, sqlx::Error> {
+    sqlx::query_as!(
+        WebauthnCredential,
+        r#"
+        SELECT id, user_id, credential_id, public_key, sign_count, transports, name, created_at, last_used_at
+        FROM webauthn_credentials
+        WHERE user_id = $1
+        ORDER BY created_at
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Records a successful login with the credential. Only moves `sign_count` forward, so two
+/// concurrent logins cannot both pass the clone check with the same counter.
+pub async fn record_webauthn_credential_use(
+    pool: &Pool<Postgres>,
+    id: Uuid,
+    previous_sign_count: i64,
+    sign_count: i64,
+    now: i64,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE webauthn_credentials
+        SET sign_count = $3, last_used_at = $4
+        WHERE id = $1 AND sign_count = $2
+        "#,
+        id,
+        previous_sign_count,
+        sign_count,
+        now
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Deletes one of the user's credentials. Returns `false` if the user has no such credential.
+pub async fn delete_webauthn_credential(
+    pool: &Pool<Postgres>,
+    user_id: Uuid,
+    id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM webauthn_credentials WHERE id = $1 AND user_id = $2",
+        id,
+        user_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
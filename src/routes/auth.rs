@@ -1,13 +1,34 @@
 // File: src/routes/auth.rs
 
-use axum::{routing::{post}, Router};
+use axum::{middleware, routing::post, Router};
 
-use crate::handlers::auth::{login_handler, register_handler, logout_handler, refresh_token_handler };
+use crate::handlers::auth::{
+    consume_magic_link_handler, finish_passkey_login_handler, forgot_password_handler,
+    login_handler, logout_handler, refresh_token_handler, register_handler,
+    request_magic_link_handler, resend_verification_handler, reset_password_handler,
+    start_passkey_login_handler, verify_email_handler, verify_mfa_handler,
+};
+use crate::middleware::rate_limit::rate_limit;
+use crate::services::rate_limit::RouteGroup;
+use crate::state::AppState;
 
-pub fn auth_routes() -> Router { // return non-generic Router state
+pub fn auth_routes(state: &AppState) -> Router<AppState> {
     Router::new()
         .route("/register", post(register_handler))
         .route("/login", post(login_handler))
         .route("/refresh", post(refresh_token_handler)) // Placeholder for refresh handler
-        .route("/logout", post(logout_handler))  // Placeholder for logout handler
-}
\ No newline at end of file
+        .route("/logout", post(logout_handler)) // Placeholder for logout handler
+        .route("/verify-email", post(verify_email_handler))
+        .route("/resend-verification", post(resend_verification_handler))
+        .route("/forgot-password", post(forgot_password_handler))
+        .route("/reset-password", post(reset_password_handler))
+        .route("/mfa/verify", post(verify_mfa_handler))
+        .route("/magic-link", post(request_magic_link_handler))
+        .route("/magic-link/consume", post(consume_magic_link_handler))
+        .route("/passkey/start", post(start_passkey_login_handler))
+        .route("/passkey/finish", post(finish_passkey_login_handler))
+        .route_layer(middleware::from_fn_with_state(
+            (state.clone(), RouteGroup::Auth),
+            rate_limit,
+        ))
+}
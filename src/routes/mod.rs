@@ -1,4 +1,5 @@
 // File: src/routes/mod.rs
 
 pub mod auth; // This line makes the 'auth' module public
-pub mod user;
\ No newline at end of file
+pub mod user;
+pub mod well_known;
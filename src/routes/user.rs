@@ -1,5 +1,70 @@
-use axum::{routing::get, Router};
+use axum::{
+    middleware,
+    routing::{delete, get, post},
+    Router,
+};
 
-pub fn user_routes() -> Router {
-    Router::new().route("/profile", get(crate::handlers::user::get_profile))
+use crate::middleware::rate_limit::rate_limit;
+use crate::services::rate_limit::RouteGroup;
+use crate::state::AppState;
+
+pub fn user_routes(state: &AppState) -> Router<AppState> {
+    Router::new()
+        .route("/profile", get(crate::handlers::user::get_profile))
+        .route(
+            "/password",
+            post(crate::handlers::user::change_password_handler),
+        )
+        .route(
+            "/sessions",
+            get(crate::handlers::user::list_sessions_handler),
+        )
+        .route(
+            "/sessions/revoke-others",
+            post(crate::handlers::user::revoke_other_sessions_handler),
+        )
+        .route(
+            "/sessions/revoke-all",
+            post(crate::handlers::user::revoke_all_sessions_handler),
+        )
+        .route(
+            "/sessions/:id",
+            delete(crate::handlers::user::revoke_session_handler),
+        )
+        .route(
+            "/mfa/enroll",
+            post(crate::handlers::user::enroll_mfa_handler),
+        )
+        .route(
+            "/mfa/confirm",
+            post(crate::handlers::user::confirm_mfa_handler),
+        )
+        .route(
+            "/mfa/recovery-codes",
+            post(crate::handlers::user::regenerate_recovery_codes_handler),
+        )
+        .route(
+            "/mfa/disable",
+            post(crate::handlers::user::disable_mfa_handler),
+        )
+        .route(
+            "/passkeys/register/start",
+            post(crate::handlers::user::start_passkey_registration_handler),
+        )
+        .route(
+            "/passkeys/register/finish",
+            post(crate::handlers::user::finish_passkey_registration_handler),
+        )
+        .route(
+            "/passkeys",
+            get(crate::handlers::user::list_passkeys_handler),
+        )
+        .route(
+            "/passkeys/:id",
+            delete(crate::handlers::user::delete_passkey_handler),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            (state.clone(), RouteGroup::Api),
+            rate_limit,
+        ))
 }
@@ -0,0 +1,7 @@
+use axum::{routing::get, Router};
+
+use crate::state::AppState;
+
+pub fn well_known_routes() -> Router<AppState> {
+    Router::new().route("/jwks.json", get(crate::handlers::well_known::jwks_handler))
+}
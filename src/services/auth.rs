@@ -1,57 +1,182 @@
+use crate::dtos::auth::LoginRequest;
+use crate::dtos::webauthn::AuthenticationCredential;
+use crate::models::session::SessionClient;
 use crate::models::token::TokenPair;
-use crate::models::user::NewUser;
-use crate::repositories::user::{find_user_by_email, insert_user};
-use crate::services::token::{generate_access_token, generate_refresh_token};
-use argon2::{
-    password_hash::{rand_core::OsRng, PasswordHasher, PasswordVerifier, SaltString},
-    Argon2,
+use crate::models::user::{NewUser, User};
+use crate::repositories::user::{
+    find_user_by_email, find_user_by_id, insert_user, update_user_password_hash,
+    EMAIL_UNIQUE_CONSTRAINT, USERNAME_UNIQUE_CONSTRAINT,
 };
+use crate::services::email_verification::EmailVerifier;
+use crate::services::keys::KeyRing;
+use crate::services::login_throttle::LoginThrottle;
+use crate::services::mfa::{Mfa, MfaChallenge};
+use crate::services::password_hash::{PasswordHasher, Verification};
+use crate::services::password_policy::PasswordPolicy;
+use crate::services::token::generate_token_pair;
+use crate::services::webauthn::Passkeys;
+use crate::utils::errors::AppError;
 use sqlx::{Pool, Postgres};
+use std::net::IpAddr;
+use uuid::Uuid;
 
-pub async fn register_user(pool: &Pool<Postgres>, new_user: &NewUser) -> Result<TokenPair, String> {
-    let salt = SaltString::generate(&mut OsRng);
-    let argon2 = Argon2::default();
-    let password_hash = argon2
-        .hash_password(new_user.password.as_bytes(), &salt)
-        .unwrap()
-        .to_string();
+/// Where a login goes once the password has checked out.
+pub enum LoginOutcome {
+    Tokens(TokenPair),
+    /// Two-factor authentication is enabled; tokens come from `/api/auth/mfa/verify`.
+    MfaRequired(MfaChallenge),
+}
+
+/// Creates the user and sends a verification link. The caller issues tokens unless the
+/// deployment requires a verified email to log in.
+pub async fn register_user(
+    pool: &Pool<Postgres>,
+    keyring: &KeyRing,
+    password_policy: &PasswordPolicy,
+    password_hasher: &PasswordHasher,
+    email_verifier: &EmailVerifier,
+    new_user: &NewUser,
+    languages: &[String],
+) -> Result<User, AppError> {
+    password_policy.validate(&new_user.password, &[&new_user.username, &new_user.email])?;
+
+    let password_hash = password_hasher.hash(&new_user.password)?;
 
     let user = insert_user(pool, new_user, &password_hash)
         .await
-        .map_err(|e| e.to_string())?;
+        .map_err(|e| match e {
+            sqlx::Error::Database(db) if db.is_unique_violation() => {
+                let field = match db.constraint() {
+                    Some(USERNAME_UNIQUE_CONSTRAINT) => Some("username"),
+                    Some(EMAIL_UNIQUE_CONSTRAINT) => Some("email"),
+                    _ => None,
+                };
+                AppError::UserAlreadyExists(field)
+            }
+            // Anything else (connection loss, timeouts, ...) is our problem, not the client's.
+            e => AppError::Database(e),
+        })?;
 
-    let access_token = generate_access_token(user.id).map_err(|e| e.to_string())?;
-    let refresh_token = generate_refresh_token(user.id).map_err(|e| e.to_string())?;
-
-    Ok(TokenPair {
-        access_token,
-        refresh_token,
-    })
+    // The account exists either way; the user can ask for another link.
+    if let Err(e) = email_verifier.send(pool, keyring, &user, languages).await {
+        eprintln!(
+            "❌ Failed to send verification email to user {}: {}",
+            user.id, e
+        );
+    }
+    Ok(user)
 }
 
+/// Checks the credentials, subject to login throttling and email verification, and returns
+/// the user. See `complete_login` for what happens next.
 pub async fn authenticate_user(
     pool: &Pool<Postgres>,
+    password_hasher: &PasswordHasher,
+    login_throttle: &LoginThrottle,
+    email_verifier: &EmailVerifier,
+    mfa: &Mfa,
+    credentials: &LoginRequest,
+    client_ip: IpAddr,
+) -> Result<User, AppError> {
+    let (email, password) = (credentials.email.as_str(), credentials.password.as_str());
+    login_throttle.check(pool, email, client_ip).await?;
+
+    let user = match verify_credentials(pool, password_hasher, email, password).await {
+        Err(AppError::InvalidCredentials) => {
+            login_throttle
+                .record_failure(pool, email, client_ip)
+                .await?;
+            return Err(AppError::InvalidCredentials);
+        }
+        result => result?,
+    };
+
+    // Checked after the password so the response does not reveal unverified accounts.
+    if email_verifier.required() && user.email_verified_at.is_none() {
+        return Err(AppError::EmailNotVerified);
+    }
+
+    // With a second factor the login is not over yet: failures stay on the books until
+    // `Mfa::verify_challenge` accepts a code, so repeated password logins do not reset the
+    // throttle on code guessing.
+    if !mfa.is_enabled(pool, user.id).await? {
+        login_throttle.record_success(pool, email).await?;
+    }
+
+    Ok(user)
+}
+
+/// Logs in with a passkey assertion. A user-verified passkey is already two factors (the
+/// device and its PIN or biometric), so there is no TOTP challenge afterwards.
+pub async fn authenticate_passkey(
+    pool: &Pool<Postgres>,
+    keyring: &KeyRing,
+    passkeys: &Passkeys,
+    email_verifier: &EmailVerifier,
+    credential: &AuthenticationCredential,
+    client: &SessionClient,
+) -> Result<TokenPair, AppError> {
+    let user_id = passkeys.finish_login(pool, credential).await?;
+    let user = find_user_by_id(pool, user_id)
+        .await?
+        .ok_or(AppError::InvalidPasskey("Unknown passkey"))?;
+    if email_verifier.required() && user.email_verified_at.is_none() {
+        return Err(AppError::EmailNotVerified);
+    }
+    generate_token_pair(pool, keyring, user.id, client).await
+}
+
+/// Issues tokens for an authenticated user, or an MFA challenge if they have a second factor.
+pub async fn complete_login(
+    pool: &Pool<Postgres>,
+    keyring: &KeyRing,
+    mfa: &Mfa,
+    user_id: Uuid,
+    client: &SessionClient,
+) -> Result<LoginOutcome, AppError> {
+    if mfa.is_enabled(pool, user_id).await? {
+        return Ok(LoginOutcome::MfaRequired(
+            mfa.challenge(pool, keyring, user_id).await?,
+        ));
+    }
+    Ok(LoginOutcome::Tokens(
+        generate_token_pair(pool, keyring, user_id, client).await?,
+    ))
+}
+
+async fn verify_credentials(
+    pool: &Pool<Postgres>,
+    password_hasher: &PasswordHasher,
     email: &str,
     password: &str,
-) -> Result<TokenPair, String> {
-    let user = find_user_by_email(pool, email)
-        .await
-        .map_err(|e| format!("Database error: {}", e))?
-        .ok_or_else(|| "User not found".to_string())?;
-
-    let parsed_hash = argon2::password_hash::PasswordHash::new(&user.password_hash).unwrap();
-    if Argon2::default()
-        .verify_password(password.as_bytes(), &parsed_hash)
-        .is_err()
-    {
-        return Err("Invalid credentials".to_string());
-    }
+) -> Result<User, AppError> {
+    // Unknown emails and wrong passwords must be indistinguishable, in the response and
+    // in how long it takes to produce it.
+    let Some(user) = find_user_by_email(pool, email).await? else {
+        password_hasher.verify_dummy(password);
+        return Err(AppError::InvalidCredentials);
+    };
+
+    // A stored hash that cannot be parsed is our bug, reported as an internal error.
+    let Verification::Match { needs_rehash } =
+        password_hasher.verify(password, &user.password_hash)?
+    else {
+        return Err(AppError::InvalidCredentials);
+    };
 
-    let access_token = generate_access_token(user.id).map_err(|e| e.to_string())?;
-    let refresh_token = generate_refresh_token(user.id).map_err(|e| e.to_string())?;
+    // Upgrade legacy or weaker hashes while the plaintext is at hand. The login itself
+    // already succeeded, so a failure here is only logged.
+    if needs_rehash {
+        let upgraded = match password_hasher.hash(password) {
+            Ok(hash) => update_user_password_hash(pool, user.id, &hash)
+                .await
+                .map_err(AppError::from),
+            Err(e) => Err(AppError::from(e)),
+        };
+        if let Err(e) = upgraded {
+            eprintln!("❌ Failed to rehash password for user {}: {}", user.id, e);
+        }
+    }
 
-    Ok(TokenPair {
-        access_token,
-        refresh_token,
-    })
+    Ok(user)
 }
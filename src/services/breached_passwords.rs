@@ -0,0 +1,193 @@
+use crate::services::password_policy::PasswordRule;
+use sha1::{Digest, Sha1};
+use std::path::{Path, PathBuf};
+
+/// Rejects passwords found in a local copy of a breached-password corpus, so the check
+/// works without any network access.
+///
+/// The corpus is either:
+/// - a directory of HIBP range files as written by the Pwned Passwords downloader, one file
+///   per 5-hex-digit SHA-1 prefix (`<PREFIX>.txt` or `<PREFIX>`) with `SUFFIX:COUNT` lines.
+///   Only the matching range file is read per check, so the full corpus never sits in memory.
+/// - a single file of `HASH:COUNT` (or bare `HASH`) lines with full 40-digit SHA-1 hashes,
+///   loaded into memory at startup. Meant for trimmed lists such as the most common passwords.
+pub struct BreachedPasswordRule {
+    corpus: Corpus,
+    min_count: u64,
+}
+
+enum Corpus {
+    RangeDir(PathBuf),
+    // Sorted by hash for binary search
+    Hashes(Vec<([u8; 20], u64)>),
+}
+
+impl BreachedPasswordRule {
+    pub fn load(path: &Path, min_count: u64) -> Result<Self, String> {
+        let corpus = if path.is_dir() {
+            Corpus::RangeDir(path.to_path_buf())
+        } else {
+            let content =
+                std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            let mut hashes = content
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .enumerate()
+                .map(|(n, line)| {
+                    parse_line(line, 40)
+                        .and_then(|(hash, count)| Some((hex_hash(hash)?, count)))
+                        .ok_or_else(|| {
+                            format!("{}:{}: expected HASH[:COUNT]", path.display(), n + 1)
+                        })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            hashes.sort_unstable();
+            Corpus::Hashes(hashes)
+        };
+        Ok(Self { corpus, min_count })
+    }
+
+    // How often the password appears in the corpus, 0 if it is not there.
+    fn occurrences(&self, password: &str) -> u64 {
+        let digest: [u8; 20] = Sha1::digest(password.as_bytes()).into();
+        match &self.corpus {
+            Corpus::Hashes(hashes) => hashes
+                .binary_search_by(|(hash, _)| hash.cmp(&digest))
+                .map(|i| hashes[i].1)
+                .unwrap_or(0),
+            Corpus::RangeDir(dir) => {
+                let hash = hex::encode_upper(digest);
+                let (prefix, suffix) = hash.split_at(5);
+                match read_range(dir, prefix) {
+                    Ok(range) => range
+                        .lines()
+                        .filter_map(|line| parse_line(line.trim(), 35))
+                        .find(|(candidate, _)| candidate.eq_ignore_ascii_case(suffix))
+                        .map(|(_, count)| count)
+                        .unwrap_or(0),
+                    // A missing range file must not block registrations; report it and let it pass.
+                    Err(e) => {
+                        eprintln!("❌ Breached password range {} unavailable: {}", prefix, e);
+                        0
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl PasswordRule for BreachedPasswordRule {
+    fn check(&self, password: &str, _: &[&str]) -> Vec<String> {
+        if self.occurrences(password) >= self.min_count {
+            vec!["Has appeared in a data breach; choose a different password".to_string()]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+fn read_range(dir: &Path, prefix: &str) -> std::io::Result<String> {
+    std::fs::read_to_string(dir.join(format!("{}.txt", prefix)))
+        .or_else(|_| std::fs::read_to_string(dir.join(prefix)))
+}
+
+// `HEX[:COUNT]` where HEX has `hex_len` digits; a missing count means "seen once".
+fn parse_line(line: &str, hex_len: usize) -> Option<(&str, u64)> {
+    let (hash, count) = match line.split_once(':') {
+        Some((hash, count)) => (hash, count.trim().parse().ok()?),
+        None => (line, 1),
+    };
+    (hash.len() == hex_len && hash.bytes().all(|b| b.is_ascii_hexdigit())).then_some((hash, count))
+}
+
+fn hex_hash(hash: &str) -> Option<[u8; 20]> {
+    let mut bytes = [0u8; 20];
+    hex::decode_to_slice(hash, &mut bytes).ok()?;
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sha1_hex(password: &str) -> String {
+        hex::encode_upper(Sha1::digest(password.as_bytes()))
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("{}-{}", name, uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn parses_hash_and_count_lines() {
+        let suffix = "1E4C9B93F3F0682250B6CF8331B7EE68FD8";
+        assert_eq!(
+            parse_line(&format!("{}:3861493", suffix), 35),
+            Some((suffix, 3861493))
+        );
+        assert_eq!(
+            parse_line(&format!("{}: 12", suffix), 35),
+            Some((suffix, 12))
+        );
+        assert_eq!(parse_line(suffix, 35), Some((suffix, 1)));
+        assert_eq!(parse_line(suffix, 40), None);
+        assert_eq!(parse_line(&format!("{}:many", suffix), 35), None);
+        assert_eq!(parse_line(&suffix.replace('E', "G"), 35), None);
+    }
+
+    #[test]
+    fn counts_occurrences_in_a_hash_file() {
+        let path = temp_path("breached-hashes");
+        std::fs::write(
+            &path,
+            format!(
+                "{}:12\n\n{}\n{}:3\n",
+                sha1_hex("letmein"),
+                sha1_hex("password").to_lowercase(),
+                sha1_hex("qwerty")
+            ),
+        )
+        .unwrap();
+        let rule = BreachedPasswordRule::load(&path, 5).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(rule.occurrences("letmein"), 12);
+        assert_eq!(rule.occurrences("password"), 1);
+        assert_eq!(rule.occurrences("qwerty"), 3);
+        assert_eq!(rule.occurrences("Velvet-Orbit-Canyon-42"), 0);
+        assert_eq!(rule.check("letmein", &[]).len(), 1);
+        // Below the configured minimum count.
+        assert!(rule.check("qwerty", &[]).is_empty());
+    }
+
+    #[test]
+    fn rejects_malformed_hash_files() {
+        let path = temp_path("breached-hashes");
+        std::fs::write(&path, format!("{}:1\nnot-a-hash\n", sha1_hex("letmein"))).unwrap();
+        let err = BreachedPasswordRule::load(&path, 1).err().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(err.ends_with(":2: expected HASH[:COUNT]"), "{}", err);
+    }
+
+    #[test]
+    fn counts_occurrences_in_a_range_directory() {
+        let dir = temp_path("breached-ranges");
+        std::fs::create_dir(&dir).unwrap();
+        let password = sha1_hex("password");
+        let letmein = sha1_hex("letmein");
+        std::fs::write(
+            dir.join(format!("{}.txt", &password[..5])),
+            format!("{}:42\r\n", password[5..].to_lowercase()),
+        )
+        .unwrap();
+        std::fs::write(dir.join(&letmein[..5]), format!("{}:7\n", &letmein[5..])).unwrap();
+        let rule = BreachedPasswordRule::load(&dir, 1).unwrap();
+
+        assert_eq!(rule.occurrences("password"), 42);
+        assert_eq!(rule.occurrences("letmein"), 7);
+        // No range file for this prefix: reported and let through.
+        assert_eq!(rule.occurrences("Velvet-Orbit-Canyon-42"), 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
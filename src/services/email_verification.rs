@@ -0,0 +1,148 @@
+use crate::config::EmailVerificationConfig;
+use crate::models::token::TokenUse;
+use crate::models::user::User;
+use crate::repositories::email_verification::{
+    insert_email_verification_token, invalidate_email_verification_tokens,
+    use_email_verification_token,
+};
+use crate::repositories::user::{find_user_by_email, mark_user_email_verified};
+use crate::services::keys::KeyRing;
+use crate::services::mail_templates::MailTemplate;
+use crate::services::mailer::Outbox;
+use crate::services::token::{generate_one_time_token, hash_token_id, validate_one_time_token};
+use crate::utils::errors::AppError;
+use chrono::Utc;
+use serde_json::json;
+use sqlx::{Pool, Postgres};
+use std::sync::Arc;
+
+/// Sends and redeems email verification links.
+///
+/// A link carries a signed `email_verification` token whose hashed `jti` is recorded in
+/// `email_verification_tokens`; redeeming it marks the row used, so every link works once.
+/// Tokens are tied to the address they were sent to and stop working if the email changes.
+pub struct EmailVerifier {
+    config: EmailVerificationConfig,
+    public_url: String,
+    outbox: Arc<Outbox>,
+}
+
+impl EmailVerifier {
+    pub fn new(config: &EmailVerificationConfig, public_url: &str, outbox: Arc<Outbox>) -> Self {
+        Self {
+            config: config.clone(),
+            public_url: public_url.to_string(),
+            outbox,
+        }
+    }
+
+    /// Whether unverified users are kept from logging in.
+    pub fn required(&self) -> bool {
+        self.config.required
+    }
+
+    /// Issues a token for the user's current email and sends the verification link in the
+    /// first of `languages` that has a template.
+    pub async fn send(
+        &self,
+        pool: &Pool<Postgres>,
+        keyring: &KeyRing,
+        user: &User,
+        languages: &[String],
+    ) -> Result<(), AppError> {
+        let (token, claims) = generate_one_time_token(
+            keyring,
+            user.id,
+            TokenUse::EmailVerification,
+            self.config.token_expiry,
+        )?;
+        insert_email_verification_token(
+            pool,
+            user.id,
+            &hash_token_id(claims.jti),
+            &user.email,
+            claims.exp,
+        )
+        .await?;
+
+        let link = format!("{}/verify-email?token={}", self.public_url, token);
+        self.outbox.send(
+            &user.email,
+            MailTemplate::VerifyEmail,
+            languages,
+            json!({
+                "username": user.username,
+                "link": link,
+                "expires_in_minutes": self.config.token_expiry.num_minutes(),
+            }),
+        )
+    }
+
+    /// Sends a fresh link if the email belongs to an unverified user, retiring older links.
+    /// The lookup and the email are handled in the background, so neither the response nor
+    /// how long it takes reveals which emails exist or are unverified; failures are logged.
+    pub fn resend(
+        self: &Arc<Self>,
+        pool: &Pool<Postgres>,
+        keyring: &Arc<KeyRing>,
+        email: &str,
+        languages: &[String],
+    ) {
+        let (verifier, pool, keyring) = (self.clone(), pool.clone(), keyring.clone());
+        let (email, languages) = (email.to_string(), languages.to_vec());
+        tokio::spawn(async move {
+            if let Err(e) = verifier
+                .resend_link(&pool, &keyring, &email, &languages)
+                .await
+            {
+                eprintln!("❌ Failed to resend verification link: {}", e);
+            }
+        });
+    }
+
+    async fn resend_link(
+        &self,
+        pool: &Pool<Postgres>,
+        keyring: &KeyRing,
+        email: &str,
+        languages: &[String],
+    ) -> Result<(), AppError> {
+        let Some(user) = find_user_by_email(pool, email).await? else {
+            return Ok(());
+        };
+        if user.email_verified_at.is_some() {
+            return Ok(());
+        }
+
+        invalidate_email_verification_tokens(pool, user.id, Utc::now().timestamp()).await?;
+        self.send(pool, keyring, &user, languages).await
+    }
+
+    /// Redeems a verification token and marks the user's email as verified.
+    pub async fn verify(
+        &self,
+        pool: &Pool<Postgres>,
+        keyring: &KeyRing,
+        token: &str,
+    ) -> Result<(), AppError> {
+        let claims =
+            validate_one_time_token(keyring, token, TokenUse::EmailVerification).map_err(|_| {
+                AppError::InvalidOneTimeToken("Verification token is invalid or has expired")
+            })?;
+
+        let (user_id, email) =
+            use_email_verification_token(pool, &hash_token_id(claims.jti), Utc::now().timestamp())
+                .await?
+                .filter(|(user_id, _)| *user_id == claims.sub)
+                .ok_or(AppError::InvalidOneTimeToken(
+                    "Verification token is invalid, has expired or has already been used",
+                ))?;
+
+        if !mark_user_email_verified(pool, user_id, &email).await? {
+            return Err(AppError::InvalidOneTimeToken(
+                "The email address has changed since this token was issued",
+            ));
+        }
+        Ok(())
+    }
+}
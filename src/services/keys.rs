@@ -0,0 +1,565 @@
+use crate::config::{is_hmac, JwtConfig};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, EllipticCurveKeyParameters,
+    EllipticCurveKeyType, Jwk, JwkSet, OctetKeyPairParameters, OctetKeyPairType,
+    OctetKeyParameters, OctetKeyType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use sha2::{Digest, Sha256};
+use spki::der::DecodePem;
+use spki::SubjectPublicKeyInfoOwned;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+// File in the keys directory holding the key id used for signing new tokens.
+const CURRENT_KEY_FILE: &str = "current";
+
+// Key material used to sign and verify JWTs: one current signing key plus any number of
+// previous keys that are still accepted for verification, selected by the `kid` header.
+// HMAC algorithms (HS256/384/512) use the configured secrets; asymmetric algorithms
+// (RS*, PS*, ES256/384, EdDSA) load PEM files and publish every public key as a JWK.
+pub struct JwtKeys {
+    pub algorithm: Algorithm,
+    pub kid: Option<String>,
+    pub encoding: EncodingKey,
+    pub jwks: JwkSet,
+    // Current key first, then previous keys.
+    verification: Vec<(Option<String>, DecodingKey)>,
+}
+
+/// Holds the active `JwtKeys` and the configuration needed to reload them after a rotation.
+pub struct KeyRing {
+    config: JwtConfig,
+    keys: RwLock<Arc<JwtKeys>>,
+}
+
+impl KeyRing {
+    pub fn load(config: &JwtConfig) -> Result<Self, String> {
+        Ok(Self {
+            keys: RwLock::new(Arc::new(JwtKeys::load(config)?)),
+            config: config.clone(),
+        })
+    }
+
+    pub fn config(&self) -> &JwtConfig {
+        &self.config
+    }
+
+    pub fn keys(&self) -> Arc<JwtKeys> {
+        self.keys.read().expect("JWT keyring lock poisoned").clone()
+    }
+
+    /// Reloads the keys (e.g. after `rotate-keys` changed the current key).
+    /// The previous keys stay active if the new ones fail to load.
+    pub fn reload(&self) -> Result<(), String> {
+        let keys = JwtKeys::load(&self.config)?;
+        *self.keys.write().expect("JWT keyring lock poisoned") = Arc::new(keys);
+        Ok(())
+    }
+}
+
+/// Makes `kid` the signing key in the keys directory. Keys that were current before remain
+/// valid for verification as long as their public key file stays in the directory.
+pub fn promote_signing_key(config: &JwtConfig, kid: &str) -> Result<(), String> {
+    let dir = config
+        .keys_dir
+        .as_deref()
+        .ok_or_else(|| "JWT_KEYS_DIR must be set to rotate signing keys".to_string())?;
+    if is_hmac(config.algorithm) {
+        return Err(
+            "Key rotation via JWT_KEYS_DIR requires an asymmetric JWT_ALGORITHM".to_string(),
+        );
+    }
+
+    // Make sure the new key actually loads before switching to it.
+    let private_pem = read_file(&dir.join(format!("{}.pem", kid)))?;
+    let public_pem = read_file(&dir.join(format!("{}.pub.pem", kid)))?;
+    load_key_pair(
+        config.algorithm,
+        Some(kid.to_string()),
+        Some(&private_pem),
+        &public_pem,
+    )?;
+
+    std::fs::write(dir.join(CURRENT_KEY_FILE), format!("{}\n", kid))
+        .map_err(|e| format!("Failed to update current key: {}", e))
+}
+
+impl JwtKeys {
+    pub fn load(config: &JwtConfig) -> Result<Self, String> {
+        let algorithm = config.algorithm;
+
+        if is_hmac(algorithm) {
+            return Self::from_secrets(config);
+        }
+
+        if let Some(dir) = &config.keys_dir {
+            return Self::from_dir(algorithm, dir);
+        }
+
+        let (private_path, public_path) = config
+            .private_key_path
+            .as_deref()
+            .zip(config.public_key_path.as_deref())
+            .ok_or_else(|| {
+                "JWT_PRIVATE_KEY_PATH and JWT_PUBLIC_KEY_PATH must be set".to_string()
+            })?;
+        let private_pem = read_file(private_path)?;
+        let public_pem = read_file(public_path)?;
+        let (encoding, decoding, jwk) = load_key_pair(
+            algorithm,
+            config.key_id.clone(),
+            Some(&private_pem),
+            &public_pem,
+        )?;
+
+        Ok(Self {
+            algorithm,
+            kid: jwk.common.key_id.clone(),
+            encoding: encoding.expect("private key was provided"),
+            verification: vec![(jwk.common.key_id.clone(), decoding)],
+            jwks: JwkSet { keys: vec![jwk] },
+        })
+    }
+
+    /// Looks up the verification key for a token's `kid` header.
+    /// Tokens without a `kid` (issued before key ids were used) are checked against the current key.
+    pub fn decoding_key(&self, kid: Option<&str>) -> Option<&DecodingKey> {
+        match kid {
+            None => self.verification.first().map(|(_, key)| key),
+            Some(kid) => self
+                .verification
+                .iter()
+                .find(|(candidate, _)| candidate.as_deref() == Some(kid))
+                .map(|(_, key)| key),
+        }
+    }
+
+    fn from_secrets(config: &JwtConfig) -> Result<Self, String> {
+        let secret = config
+            .secret
+            .as_deref()
+            .ok_or_else(|| "JWT_SECRET must be set".to_string())?;
+
+        let mut verification = Vec::new();
+        let current_kid = config.key_id.clone().unwrap_or_else(|| secret_kid(secret));
+        verification.push((
+            Some(current_kid.clone()),
+            DecodingKey::from_secret(secret.as_ref()),
+        ));
+        for old in &config.previous_secrets {
+            verification.push((
+                Some(secret_kid(old)),
+                DecodingKey::from_secret(old.as_ref()),
+            ));
+        }
+
+        // Symmetric secrets are never published, so the key set stays empty.
+        Ok(Self {
+            algorithm: config.algorithm,
+            kid: Some(current_kid),
+            encoding: EncodingKey::from_secret(secret.as_ref()),
+            verification,
+            jwks: JwkSet { keys: Vec::new() },
+        })
+    }
+
+    // Directory layout: `<kid>.pem` (private) and `<kid>.pub.pem` (public) per key,
+    // plus a `current` file naming the signing kid. Previous keys only need the public half.
+    fn from_dir(algorithm: Algorithm, dir: &Path) -> Result<Self, String> {
+        let current_kid = read_file(&dir.join(CURRENT_KEY_FILE))?.trim().to_string();
+        if current_kid.is_empty() {
+            return Err(format!("{} is empty", dir.join(CURRENT_KEY_FILE).display()));
+        }
+
+        let private_pem = read_file(&dir.join(format!("{}.pem", current_kid)))?;
+        let public_pem = read_file(&dir.join(format!("{}.pub.pem", current_kid)))?;
+        let (encoding, decoding, jwk) = load_key_pair(
+            algorithm,
+            Some(current_kid.clone()),
+            Some(&private_pem),
+            &public_pem,
+        )?;
+
+        let mut verification = vec![(Some(current_kid.clone()), decoding)];
+        let mut jwks = vec![jwk];
+
+        let entries = std::fs::read_dir(dir)
+            .map_err(|e| format!("Failed to read JWT_KEYS_DIR ({}): {}", dir.display(), e))?;
+        let mut previous: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                name.strip_suffix(".pub.pem").map(str::to_string)
+            })
+            .filter(|kid| *kid != current_kid)
+            .collect();
+        previous.sort();
+
+        for kid in previous {
+            let public_pem = read_file(&dir.join(format!("{}.pub.pem", kid)))?;
+            let (_, decoding, jwk) =
+                load_key_pair(algorithm, Some(kid.clone()), None, &public_pem)?;
+            verification.push((Some(kid), decoding));
+            jwks.push(jwk);
+        }
+
+        Ok(Self {
+            algorithm,
+            kid: Some(current_kid),
+            encoding: encoding.expect("private key was provided"),
+            verification,
+            jwks: JwkSet { keys: jwks },
+        })
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, String> {
+    std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+}
+
+// Parses one asymmetric key. The private key is optional for verification-only keys.
+fn load_key_pair(
+    algorithm: Algorithm,
+    kid: Option<String>,
+    private_pem: Option<&str>,
+    public_pem: &str,
+) -> Result<(Option<EncodingKey>, DecodingKey, Jwk), String> {
+    let (encoding, decoding) = match algorithm {
+        Algorithm::RS256
+        | Algorithm::RS384
+        | Algorithm::RS512
+        | Algorithm::PS256
+        | Algorithm::PS384
+        | Algorithm::PS512 => (
+            private_pem.map(|pem| EncodingKey::from_rsa_pem(pem.as_bytes())),
+            DecodingKey::from_rsa_pem(public_pem.as_bytes()),
+        ),
+        Algorithm::ES256 | Algorithm::ES384 => (
+            private_pem.map(|pem| EncodingKey::from_ec_pem(pem.as_bytes())),
+            DecodingKey::from_ec_pem(public_pem.as_bytes()),
+        ),
+        Algorithm::EdDSA => (
+            private_pem.map(|pem| EncodingKey::from_ed_pem(pem.as_bytes())),
+            DecodingKey::from_ed_pem(public_pem.as_bytes()),
+        ),
+        _ => return Err("HMAC algorithms do not use PEM keys".to_string()),
+    };
+    let encoding = encoding
+        .transpose()
+        .map_err(|e| format!("Invalid JWT private key: {}", e))?;
+    let decoding = decoding.map_err(|e| format!("Invalid JWT public key: {}", e))?;
+
+    let params = public_key_params(algorithm, public_pem)?;
+    // Default the key id to the RFC 7638 thumbprint so it is stable for a given key.
+    let kid = kid.unwrap_or_else(|| thumbprint(&params));
+    let jwk = Jwk {
+        common: CommonParameters {
+            public_key_use: Some(PublicKeyUse::Signature),
+            algorithm: Some(algorithm),
+            key_id: Some(kid),
+            ..Default::default()
+        },
+        algorithm: params,
+    };
+
+    Ok((encoding, decoding, jwk))
+}
+
+// Extracts the public components from a SubjectPublicKeyInfo PEM ("BEGIN PUBLIC KEY").
+fn public_key_params(
+    algorithm: Algorithm,
+    public_pem: &str,
+) -> Result<AlgorithmParameters, String> {
+    let spki = SubjectPublicKeyInfoOwned::from_pem(public_pem)
+        .map_err(|e| format!("Invalid JWT public key: {}", e))?;
+    let key = spki.subject_public_key.raw_bytes();
+
+    match algorithm {
+        Algorithm::ES256 | Algorithm::ES384 => {
+            let (curve, size) = if algorithm == Algorithm::ES256 {
+                (EllipticCurve::P256, 32)
+            } else {
+                (EllipticCurve::P384, 48)
+            };
+            // Uncompressed SEC1 point: 0x04 || x || y
+            if key.len() != 1 + 2 * size || key[0] != 0x04 {
+                return Err("JWT public key does not match JWT_ALGORITHM".to_string());
+            }
+            Ok(AlgorithmParameters::EllipticCurve(
+                EllipticCurveKeyParameters {
+                    key_type: EllipticCurveKeyType::EC,
+                    curve,
+                    x: URL_SAFE_NO_PAD.encode(&key[1..=size]),
+                    y: URL_SAFE_NO_PAD.encode(&key[1 + size..]),
+                },
+            ))
+        }
+        Algorithm::EdDSA => {
+            if key.len() != 32 {
+                return Err("JWT public key does not match JWT_ALGORITHM".to_string());
+            }
+            Ok(AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                key_type: OctetKeyPairType::OctetKeyPair,
+                curve: EllipticCurve::Ed25519,
+                x: URL_SAFE_NO_PAD.encode(key),
+            }))
+        }
+        _ => {
+            let rsa = pkcs1::RsaPublicKey::try_from(key)
+                .map_err(|_| "JWT public key does not match JWT_ALGORITHM".to_string())?;
+            Ok(AlgorithmParameters::RSA(RSAKeyParameters {
+                key_type: RSAKeyType::RSA,
+                n: URL_SAFE_NO_PAD.encode(rsa.modulus.as_bytes()),
+                e: URL_SAFE_NO_PAD.encode(rsa.public_exponent.as_bytes()),
+            }))
+        }
+    }
+}
+
+// The thumbprint of an HMAC secret is safe to expose in the `kid` header and lets
+// previous secrets be selected without configuring ids for them.
+fn secret_kid(secret: &str) -> String {
+    thumbprint(&AlgorithmParameters::OctetKey(OctetKeyParameters {
+        key_type: OctetKeyType::Octet,
+        value: URL_SAFE_NO_PAD.encode(secret),
+    }))
+}
+
+// RFC 7638 JWK thumbprint: SHA-256 over the required members in lexicographic order.
+fn thumbprint(params: &AlgorithmParameters) -> String {
+    let canonical = match params {
+        AlgorithmParameters::RSA(p) => format!(r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#, p.e, p.n),
+        AlgorithmParameters::EllipticCurve(p) => {
+            let crv = if p.curve == EllipticCurve::P256 {
+                "P-256"
+            } else {
+                "P-384"
+            };
+            format!(
+                r#"{{"crv":"{}","kty":"EC","x":"{}","y":"{}"}}"#,
+                crv, p.x, p.y
+            )
+        }
+        AlgorithmParameters::OctetKeyPair(p) => {
+            format!(r#"{{"crv":"Ed25519","kty":"OKP","x":"{}"}}"#, p.x)
+        }
+        AlgorithmParameters::OctetKey(p) => format!(r#"{{"k":"{}","kty":"oct"}}"#, p.value),
+    };
+    URL_SAFE_NO_PAD.encode(Sha256::digest(canonical.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::engine::general_purpose::STANDARD;
+    use chrono::Duration;
+    use jsonwebtoken::{decode, decode_header, encode, Header, Validation};
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+    use serde_json::{json, Value};
+    use std::path::PathBuf;
+
+    // DER prefix of a P-256 SubjectPublicKeyInfo, followed by the uncompressed point.
+    const P256_SPKI_PREFIX: [u8; 26] = [
+        0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x08,
+        0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00,
+    ];
+
+    fn pem(label: &str, der: &[u8]) -> String {
+        let body = STANDARD.encode(der);
+        let lines: Vec<&str> = body
+            .as_bytes()
+            .chunks(64)
+            .map(|line| std::str::from_utf8(line).unwrap())
+            .collect();
+        format!(
+            "-----BEGIN {label}-----\n{}\n-----END {label}-----\n",
+            lines.join("\n")
+        )
+    }
+
+    // A fresh ES256 key pair as (private PKCS#8 PEM, public SPKI PEM, uncompressed point).
+    fn es256_key() -> (String, String, Vec<u8>) {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng)
+            .unwrap();
+        let point = pair.public_key().as_ref().to_vec();
+        let spki = [P256_SPKI_PREFIX.as_slice(), &point].concat();
+        (
+            pem("PRIVATE KEY", pkcs8.as_ref()),
+            pem("PUBLIC KEY", &spki),
+            point,
+        )
+    }
+
+    fn config(algorithm: Algorithm) -> JwtConfig {
+        JwtConfig {
+            algorithm,
+            secret: None,
+            previous_secrets: Vec::new(),
+            private_key_path: None,
+            public_key_path: None,
+            keys_dir: None,
+            key_id: None,
+            access_token_expiry: Duration::minutes(15),
+            refresh_token_expiry: Duration::days(7),
+            issuer: "test-issuer".to_string(),
+            audience: "test-audience".to_string(),
+        }
+    }
+
+    // A keys directory holding `kids`, the first of them current.
+    fn keys_dir(kids: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("keys-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        for kid in kids {
+            add_key(&dir, kid);
+        }
+        std::fs::write(dir.join(CURRENT_KEY_FILE), format!("{}\n", kids[0])).unwrap();
+        dir
+    }
+
+    fn add_key(dir: &Path, kid: &str) {
+        let (private_pem, public_pem, _) = es256_key();
+        std::fs::write(dir.join(format!("{}.pem", kid)), private_pem).unwrap();
+        std::fs::write(dir.join(format!("{}.pub.pem", kid)), public_pem).unwrap();
+    }
+
+    fn sign(keys: &JwtKeys) -> String {
+        let mut header = Header::new(keys.algorithm);
+        header.kid = keys.kid.clone();
+        encode(
+            &header,
+            &json!({ "sub": "alice", "exp": 4102444800u64 }),
+            &keys.encoding,
+        )
+        .unwrap()
+    }
+
+    // Verifies with the key the token's `kid` selects, as `decode_claims` does.
+    fn verify(keys: &JwtKeys, token: &str) -> bool {
+        let kid = decode_header(token).unwrap().kid;
+        let Some(key) = keys.decoding_key(kid.as_deref()) else {
+            return false;
+        };
+        let mut validation = Validation::new(keys.algorithm);
+        validation.set_required_spec_claims(&["exp"]);
+        decode::<Value>(token, key, &validation).is_ok()
+    }
+
+    #[test]
+    fn verifies_tokens_signed_with_a_previous_key_after_rotation() {
+        let dir = keys_dir(&["2025-01"]);
+        let mut config = config(Algorithm::ES256);
+        config.keys_dir = Some(dir.clone());
+        let keyring = KeyRing::load(&config).unwrap();
+        let old_token = sign(&keyring.keys());
+
+        add_key(&dir, "2025-02");
+        promote_signing_key(&config, "2025-02").unwrap();
+        keyring.reload().unwrap();
+        let keys = keyring.keys();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let new_token = sign(&keys);
+        assert_eq!(keys.kid.as_deref(), Some("2025-02"));
+        assert_eq!(
+            decode_header(&new_token).unwrap().kid.as_deref(),
+            Some("2025-02")
+        );
+        assert!(verify(&keys, &new_token));
+        assert!(verify(&keys, &old_token));
+        let published: Vec<_> = keys
+            .jwks
+            .keys
+            .iter()
+            .map(|jwk| jwk.common.key_id.clone().unwrap())
+            .collect();
+        assert_eq!(published, ["2025-02", "2025-01"]);
+    }
+
+    #[test]
+    fn rejects_tokens_with_an_unknown_kid() {
+        let (dir, retired_dir) = (keys_dir(&["current"]), keys_dir(&["retired"]));
+        let keys = JwtKeys::from_dir(Algorithm::ES256, &dir).unwrap();
+        let retired = JwtKeys::from_dir(Algorithm::ES256, &retired_dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        std::fs::remove_dir_all(&retired_dir).unwrap();
+
+        assert!(keys.decoding_key(Some("retired")).is_none());
+        assert!(!verify(&keys, &sign(&retired)));
+    }
+
+    #[test]
+    fn keeps_the_current_key_when_promotion_fails() {
+        let dir = keys_dir(&["2025-01"]);
+        let mut config = config(Algorithm::ES256);
+        config.keys_dir = Some(dir.clone());
+
+        assert!(promote_signing_key(&config, "missing").is_err());
+        let current = std::fs::read_to_string(dir.join(CURRENT_KEY_FILE)).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(current.trim(), "2025-01");
+    }
+
+    #[test]
+    fn verifies_tokens_signed_with_a_previous_secret() {
+        let mut old = config(Algorithm::HS256);
+        old.secret = Some("old-secret-0123456789abcdef0123456789".to_string());
+        let old_token = sign(&JwtKeys::load(&old).unwrap());
+
+        let mut rotated = config(Algorithm::HS256);
+        rotated.secret = Some("new-secret-0123456789abcdef0123456789".to_string());
+        rotated.previous_secrets = vec![old.secret.clone().unwrap()];
+        let keys = JwtKeys::load(&rotated).unwrap();
+
+        assert!(verify(&keys, &old_token));
+        assert!(verify(&keys, &sign(&keys)));
+        assert!(keys.jwks.keys.is_empty());
+
+        rotated.previous_secrets.clear();
+        assert!(!verify(&JwtKeys::load(&rotated).unwrap(), &old_token));
+    }
+
+    #[test]
+    fn computes_rfc_7638_thumbprints() {
+        // The example key from RFC 7638, section 3.1.
+        let params = AlgorithmParameters::RSA(RSAKeyParameters {
+            key_type: RSAKeyType::RSA,
+            n: "0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw".to_string(),
+            e: "AQAB".to_string(),
+        });
+        assert_eq!(
+            thumbprint(&params),
+            "NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs"
+        );
+    }
+
+    #[test]
+    fn publishes_an_ec_public_key_named_by_its_thumbprint() {
+        let (private_pem, public_pem, point) = es256_key();
+        let (encoding, _, jwk) =
+            load_key_pair(Algorithm::ES256, None, Some(&private_pem), &public_pem).unwrap();
+        assert!(encoding.is_some());
+
+        let AlgorithmParameters::EllipticCurve(params) = &jwk.algorithm else {
+            panic!("expected an EC key, got {:?}", jwk.algorithm);
+        };
+        assert_eq!(params.x, URL_SAFE_NO_PAD.encode(&point[1..33]));
+        assert_eq!(params.y, URL_SAFE_NO_PAD.encode(&point[33..]));
+        assert_eq!(jwk.common.key_id, Some(thumbprint(&jwk.algorithm)));
+        assert_eq!(jwk.common.algorithm, Some(Algorithm::ES256));
+    }
+
+    #[test]
+    fn rejects_keys_that_do_not_match_the_algorithm() {
+        let (private_pem, public_pem, _) = es256_key();
+        assert!(load_key_pair(Algorithm::EdDSA, None, Some(&private_pem), &public_pem).is_err());
+        assert!(load_key_pair(Algorithm::ES384, None, None, &public_pem).is_err());
+        assert!(load_key_pair(Algorithm::ES256, None, None, "not a key").is_err());
+    }
+}
@@ -0,0 +1,241 @@
+use crate::config::LoginThrottleConfig;
+use crate::repositories::login_throttle::{
+    clear_login_failures, find_active_lockout, record_login_failure, set_locked_until,
+    ACCOUNT_SCOPE, IP_SCOPE,
+};
+use crate::utils::errors::AppError;
+use chrono::Utc;
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+
+// How long a cached lockout is trusted before the database is asked again, so an unlock
+// made by another process (e.g. `unlock-account`) is picked up quickly.
+const CACHE_TTL_SECS: i64 = 30;
+
+// (scope, key) -> (locked_until, cached_until)
+type LockoutCache = Mutex<HashMap<(&'static str, String), (i64, i64)>>;
+
+/// Failed-login tracking per account and per source IP.
+///
+/// Every failure on an account delays the next attempt exponentially (`backoff_base`,
+/// doubled each time); `max_failures` within `failure_window` locks the account for
+/// `lockout_duration`. An IP is locked the same way after `ip_max_failures`, without backoff
+/// since many users may share one address. Accounts are keyed by email, whether or not it
+/// is registered, so lockouts do not reveal which emails exist.
+pub struct LoginThrottle {
+    config: LoginThrottleConfig,
+    cache: Option<LockoutCache>,
+}
+
+impl LoginThrottle {
+    pub fn new(config: &LoginThrottleConfig) -> Self {
+        Self {
+            config: config.clone(),
+            cache: config.in_memory.then(Default::default),
+        }
+    }
+
+    /// Rejects the attempt with `ACCOUNT_LOCKED` while the account or the IP is locked.
+    pub async fn check(
+        &self,
+        pool: &Pool<Postgres>,
+        email: &str,
+        ip: IpAddr,
+    ) -> Result<(), AppError> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+        let now = Utc::now().timestamp();
+        let account = account_key(email);
+        let ip = ip.to_string();
+
+        if let Some(cache) = &self.cache {
+            let mut cache = cache.lock().expect("login throttle cache lock poisoned");
+            cache.retain(|_, (_, cached_until)| *cached_until > now);
+            let cached = [(ACCOUNT_SCOPE, &account), (IP_SCOPE, &ip)]
+                .into_iter()
+                .filter_map(|(scope, key)| cache.get(&(scope, key.clone())))
+                .map(|(locked_until, _)| *locked_until)
+                .max();
+            if let Some(locked_until) = cached.filter(|until| *until > now) {
+                return Err(locked(locked_until, now));
+            }
+        }
+
+        match find_active_lockout(pool, &account, &ip, now).await? {
+            Some((scope, locked_until)) => {
+                // Cached under the row that is locked: an IP lockout must not lock the
+                // account out for every other address.
+                let key = if scope == ACCOUNT_SCOPE {
+                    &account
+                } else {
+                    &ip
+                };
+                self.cache_lockout(scope, key, locked_until, now);
+                Err(locked(locked_until, now))
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub async fn record_failure(
+        &self,
+        pool: &Pool<Postgres>,
+        email: &str,
+        ip: IpAddr,
+    ) -> Result<(), AppError> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+        let now = Utc::now().timestamp();
+        let window_start = now - self.config.failure_window.num_seconds();
+        let lockout = self.config.lockout_duration.num_seconds();
+
+        let account = account_key(email);
+        let failures =
+            record_login_failure(pool, ACCOUNT_SCOPE, &account, now, window_start).await?;
+        let delay = self.account_delay(failures as u32);
+        set_locked_until(pool, ACCOUNT_SCOPE, &account, now + delay).await?;
+        self.cache_lockout(ACCOUNT_SCOPE, &account, now + delay, now);
+
+        let ip = ip.to_string();
+        let failures = record_login_failure(pool, IP_SCOPE, &ip, now, window_start).await?;
+        if failures as u32 >= self.config.ip_max_failures {
+            set_locked_until(pool, IP_SCOPE, &ip, now + lockout).await?;
+            self.cache_lockout(IP_SCOPE, &ip, now + lockout, now);
+        }
+
+        Ok(())
+    }
+
+    /// A successful login clears the account's failures; the IP's decay with the window.
+    pub async fn record_success(&self, pool: &Pool<Postgres>, email: &str) -> Result<(), AppError> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+        let account = account_key(email);
+        clear_login_failures(pool, ACCOUNT_SCOPE, &account).await?;
+        if let Some(cache) = &self.cache {
+            cache
+                .lock()
+                .expect("login throttle cache lock poisoned")
+                .remove(&(ACCOUNT_SCOPE, account));
+        }
+        Ok(())
+    }
+
+    // Seconds the account stays locked after its `failures`-th failure within the window.
+    fn account_delay(&self, failures: u32) -> i64 {
+        let lockout = self.config.lockout_duration.num_seconds();
+        if failures >= self.config.max_failures {
+            return lockout;
+        }
+        let doublings = failures.saturating_sub(1).min(30);
+        self.config
+            .backoff_base
+            .num_seconds()
+            .saturating_mul(1 << doublings)
+            .min(lockout)
+    }
+
+    fn cache_lockout(&self, scope: &'static str, key: &str, locked_until: i64, now: i64) {
+        if let Some(cache) = &self.cache {
+            let cached_until = locked_until.min(now + CACHE_TTL_SECS);
+            cache
+                .lock()
+                .expect("login throttle cache lock poisoned")
+                .insert((scope, key.to_string()), (locked_until, cached_until));
+        }
+    }
+}
+
+/// Admin unlock: forgets the failures and lockout of the account with this email.
+/// Returns whether it had any.
+pub async fn unlock_account(pool: &Pool<Postgres>, email: &str) -> Result<bool, AppError> {
+    Ok(clear_login_failures(pool, ACCOUNT_SCOPE, &account_key(email)).await?)
+}
+
+fn account_key(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+fn locked(locked_until: i64, now: i64) -> AppError {
+    AppError::AccountLocked {
+        retry_after: (locked_until - now).max(1) as u64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn throttle(backoff_base: i64, max_failures: u32, lockout_duration: i64) -> LoginThrottle {
+        LoginThrottle::new(&LoginThrottleConfig {
+            enabled: true,
+            max_failures,
+            ip_max_failures: 100,
+            backoff_base: Duration::seconds(backoff_base),
+            failure_window: Duration::minutes(15),
+            lockout_duration: Duration::seconds(lockout_duration),
+            in_memory: false,
+        })
+    }
+
+    #[test]
+    fn doubles_the_delay_with_every_failure() {
+        let throttle = throttle(1, 10, 900);
+        let delays: Vec<i64> = (1..=6).map(|n| throttle.account_delay(n)).collect();
+        assert_eq!(delays, [1, 2, 4, 8, 16, 32]);
+    }
+
+    #[test]
+    fn caps_the_backoff_at_the_lockout_duration() {
+        let throttle = throttle(30, 10, 300);
+        assert_eq!(throttle.account_delay(4), 240);
+        assert_eq!(throttle.account_delay(5), 300);
+        assert_eq!(throttle.account_delay(9), 300);
+    }
+
+    #[test]
+    fn does_not_overflow_on_long_failure_streaks() {
+        let throttle = throttle(1 << 40, u32::MAX, 300);
+        assert_eq!(throttle.account_delay(1_000), 300);
+    }
+
+    #[test]
+    fn locks_the_account_out_after_max_failures() {
+        let throttle = throttle(1, 5, 900);
+        assert_eq!(throttle.account_delay(4), 8);
+        assert_eq!(throttle.account_delay(5), 900);
+        assert_eq!(throttle.account_delay(6), 900);
+    }
+
+    #[test]
+    fn caches_lockouts_no_longer_than_the_cache_ttl() {
+        let throttle = LoginThrottle::new(&LoginThrottleConfig {
+            in_memory: true,
+            ..throttle(1, 5, 900).config
+        });
+        throttle.cache_lockout(ACCOUNT_SCOPE, "a@example.com", 1_900, 1_000);
+        throttle.cache_lockout(IP_SCOPE, "127.0.0.1", 1_010, 1_000);
+        let cache = throttle.cache.as_ref().unwrap().lock().unwrap();
+        assert_eq!(
+            cache[&(ACCOUNT_SCOPE, "a@example.com".to_string())],
+            (1_900, 1_000 + CACHE_TTL_SECS)
+        );
+        assert_eq!(cache[&(IP_SCOPE, "127.0.0.1".to_string())], (1_010, 1_010));
+    }
+
+    #[test]
+    fn reports_at_least_one_second_to_wait() {
+        let retry_after = |err| match err {
+            AppError::AccountLocked { retry_after } => retry_after,
+            _ => unreachable!(),
+        };
+        assert_eq!(retry_after(locked(1_060, 1_000)), 60);
+        assert_eq!(retry_after(locked(1_000, 1_000)), 1);
+    }
+}
@@ -0,0 +1,192 @@
+use crate::config::MagicLinkConfig;
+use crate::models::token::TokenUse;
+use crate::repositories::magic_link::{
+    delete_expired_magic_link_tokens, insert_magic_link_token, invalidate_magic_link_tokens,
+    use_magic_link_token,
+};
+use crate::repositories::user::{find_user_by_email, mark_user_email_verified};
+use crate::services::keys::KeyRing;
+use crate::services::mail_templates::MailTemplate;
+use crate::services::mailer::Outbox;
+use crate::services::token::{generate_one_time_token, hash_token_id, validate_one_time_token};
+use crate::utils::errors::AppError;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::Utc;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Postgres};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use uuid::Uuid;
+
+// Expired links are swept this often, when the next one is sent.
+const SWEEP_INTERVAL_SECS: i64 = 10 * 60;
+
+const INVALID_MAGIC_LINK: AppError = AppError::InvalidOneTimeToken(
+    "Login link is invalid, has expired, has already been used or was requested on another device",
+);
+
+/// Sends and redeems passwordless login links.
+///
+/// A link carries a signed `magic_link` token whose hashed `jti` is recorded in
+/// `magic_link_tokens`; redeeming it marks the row used, so every link works once. With
+/// `same_device`, the request also returns a device token that must accompany the link, so a
+/// link opened on another device (or intercepted from the mailbox alone) is refused.
+pub struct MagicLinks {
+    config: MagicLinkConfig,
+    public_url: String,
+    outbox: Arc<Outbox>,
+    last_sweep: AtomicI64,
+}
+
+impl MagicLinks {
+    pub fn new(config: &MagicLinkConfig, public_url: &str, outbox: Arc<Outbox>) -> Self {
+        Self {
+            config: config.clone(),
+            public_url: public_url.to_string(),
+            outbox,
+            last_sweep: AtomicI64::new(0),
+        }
+    }
+
+    /// Emails a login link if the email is registered, returning the device token when links
+    /// are bound to the requesting device. The lookup, the token and the email are all handled
+    /// in the background, so neither the response nor how long it takes reveals which emails
+    /// exist; failures are logged.
+    pub fn request(
+        self: &Arc<Self>,
+        pool: &Pool<Postgres>,
+        keyring: &Arc<KeyRing>,
+        email: &str,
+        languages: &[String],
+    ) -> Result<Option<String>, AppError> {
+        self.ensure_enabled()?;
+        let device_token = self.config.same_device.then(generate_device_token);
+
+        let (magic_links, pool, keyring) = (self.clone(), pool.clone(), keyring.clone());
+        let (email, languages) = (email.to_string(), languages.to_vec());
+        let device_token_hash = device_token.as_deref().map(hash_device_token);
+        tokio::spawn(async move {
+            let sent = magic_links
+                .send_link(
+                    &pool,
+                    &keyring,
+                    &email,
+                    &languages,
+                    device_token_hash.as_deref(),
+                )
+                .await;
+            if let Err(e) = sent {
+                eprintln!("❌ Failed to send login link: {}", e);
+            }
+        });
+        Ok(device_token)
+    }
+
+    async fn send_link(
+        &self,
+        pool: &Pool<Postgres>,
+        keyring: &KeyRing,
+        email: &str,
+        languages: &[String],
+        device_token_hash: Option<&str>,
+    ) -> Result<(), AppError> {
+        let now = Utc::now().timestamp();
+        let last_sweep = self.last_sweep.load(Ordering::Relaxed);
+        if now - last_sweep >= SWEEP_INTERVAL_SECS
+            && self
+                .last_sweep
+                .compare_exchange(last_sweep, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            delete_expired_magic_link_tokens(pool, now).await?;
+        }
+
+        let Some(user) = find_user_by_email(pool, email).await? else {
+            return Ok(());
+        };
+
+        let (token, claims) = generate_one_time_token(
+            keyring,
+            user.id,
+            TokenUse::MagicLink,
+            self.config.token_expiry,
+        )?;
+        insert_magic_link_token(
+            pool,
+            user.id,
+            &hash_token_id(claims.jti),
+            &user.email,
+            device_token_hash,
+            claims.exp,
+        )
+        .await?;
+
+        let link = format!("{}/magic-link?token={}", self.public_url, token);
+        self.outbox.send(
+            &user.email,
+            MailTemplate::MagicLink,
+            languages,
+            json!({
+                "username": user.username,
+                "link": link,
+                "expires_in_minutes": self.config.token_expiry.num_minutes(),
+                "same_device": self.config.same_device,
+            }),
+        )
+    }
+
+    /// Redeems a login link, returning the user to log in. Retires the user's other links and,
+    /// since following the link proves access to the mailbox, marks the email as verified.
+    pub async fn consume(
+        &self,
+        pool: &Pool<Postgres>,
+        keyring: &KeyRing,
+        token: &str,
+        device_token: Option<&str>,
+    ) -> Result<Uuid, AppError> {
+        self.ensure_enabled()?;
+        let claims = validate_one_time_token(keyring, token, TokenUse::MagicLink)
+            .map_err(|_| AppError::InvalidOneTimeToken("Login link is invalid or has expired"))?;
+
+        let now = Utc::now().timestamp();
+        let device_hash = device_token.map(hash_device_token);
+        let (user_id, email) = use_magic_link_token(
+            pool,
+            &hash_token_id(claims.jti),
+            device_hash.as_deref(),
+            now,
+        )
+        .await?
+        .filter(|(user_id, _)| *user_id == claims.sub)
+        .ok_or(INVALID_MAGIC_LINK)?;
+
+        invalidate_magic_link_tokens(pool, user_id, now).await?;
+        if !mark_user_email_verified(pool, user_id, &email).await? {
+            return Err(AppError::InvalidOneTimeToken(
+                "The email address has changed since this link was sent",
+            ));
+        }
+        Ok(user_id)
+    }
+
+    fn ensure_enabled(&self) -> Result<(), AppError> {
+        if !self.config.enabled {
+            return Err(AppError::NotFound("Magic-link login is disabled"));
+        }
+        Ok(())
+    }
+}
+
+fn generate_device_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn hash_device_token(device_token: &str) -> String {
+    hex::encode(Sha256::digest(device_token.as_bytes()))
+}
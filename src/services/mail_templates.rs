@@ -0,0 +1,219 @@
+use crate::utils::errors::AppError;
+use minijinja::Environment;
+use serde::Serialize;
+use std::path::Path;
+
+/// Emails the application sends. Each needs `<name>.subject.txt`, `<name>.txt` and
+/// `<name>.html` in the default locale; other locales may translate any subset.
+#[derive(Debug, Clone, Copy)]
+pub enum MailTemplate {
+    VerifyEmail,
+    PasswordReset,
+    MagicLink,
+}
+
+impl MailTemplate {
+    pub const ALL: &'static [MailTemplate] = &[
+        MailTemplate::VerifyEmail,
+        MailTemplate::PasswordReset,
+        MailTemplate::MagicLink,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MailTemplate::VerifyEmail => "verify_email",
+            MailTemplate::PasswordReset => "password_reset",
+            MailTemplate::MagicLink => "magic_link",
+        }
+    }
+}
+
+/// A rendered email, ready for any transport.
+pub struct RenderedMail {
+    pub subject: String,
+    pub text: String,
+    pub html: String,
+}
+
+/// Email templates loaded from `<dir>/<locale>/`, rendered with MiniJinja.
+///
+/// `.html` templates are auto-escaped, `.txt` ones are not. Templates may `{% extends %}`
+/// or `{% include %}` any other file under the directory by its relative path
+/// (e.g. `layout.html`).
+pub struct MailTemplates {
+    env: Environment<'static>,
+    locales: Vec<String>,
+    default_locale: String,
+}
+
+impl MailTemplates {
+    pub fn load(dir: &Path, default_locale: &str) -> Result<Self, String> {
+        let mut env = Environment::new();
+        let mut locales = Vec::new();
+        add_templates(&mut env, dir, dir, &mut locales)?;
+
+        for template in MailTemplate::ALL {
+            for suffix in ["subject.txt", "txt", "html"] {
+                let name = format!("{}/{}.{}", default_locale, template.name(), suffix);
+                if env.get_template(&name).is_err() {
+                    return Err(format!("{} is missing from {}", name, dir.display()));
+                }
+            }
+        }
+
+        Ok(Self {
+            env,
+            locales,
+            default_locale: default_locale.to_string(),
+        })
+    }
+
+    /// Renders `template` in the first of `languages` (in order of preference) that has it,
+    /// falling back to the default locale.
+    pub fn render(
+        &self,
+        template: MailTemplate,
+        languages: &[String],
+        context: impl Serialize,
+    ) -> Result<RenderedMail, AppError> {
+        let locale = self.locale_for(template, languages);
+        let render = |suffix: &str| {
+            self.env
+                .get_template(&format!("{}/{}.{}", locale, template.name(), suffix))
+                .and_then(|t| t.render(&context))
+                .map_err(|e| AppError::Mail(format!("failed to render {}: {}", template.name(), e)))
+        };
+
+        Ok(RenderedMail {
+            subject: render("subject.txt")?.trim().to_string(),
+            text: render("txt")?,
+            html: render("html")?,
+        })
+    }
+
+    // `pt-BR` matches a `pt-BR` directory (in any case) first, then `pt`.
+    fn locale_for(&self, template: MailTemplate, languages: &[String]) -> &str {
+        let has = |locale: &str| {
+            self.env
+                .get_template(&format!("{}/{}.subject.txt", locale, template.name()))
+                .is_ok()
+        };
+        languages
+            .iter()
+            .map(|language| language.to_lowercase())
+            .flat_map(|language| {
+                let primary = language.split('-').next().unwrap_or_default().to_string();
+                [language, primary]
+            })
+            .find_map(|candidate| {
+                self.locales
+                    .iter()
+                    .find(|locale| locale.eq_ignore_ascii_case(&candidate) && has(locale))
+            })
+            .map(String::as_str)
+            .unwrap_or(&self.default_locale)
+    }
+}
+
+// Registers every file under `dir` by its path relative to `root`, with `/` separators.
+fn add_templates(
+    env: &mut Environment<'static>,
+    root: &Path,
+    dir: &Path,
+    locales: &mut Vec<String>,
+) -> Result<(), String> {
+    let entries = std::fs::read_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    for entry in entries {
+        let path = entry
+            .map_err(|e| format!("{}: {}", dir.display(), e))?
+            .path();
+        if path.is_dir() {
+            if dir == root {
+                if let Some(locale) = path.file_name().and_then(|n| n.to_str()) {
+                    locales.push(locale.to_string());
+                }
+            }
+            add_templates(env, root, &path, locales)?;
+            continue;
+        }
+
+        let name = path
+            .strip_prefix(root)
+            .map_err(|e| e.to_string())?
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let source =
+            std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        env.add_template_owned(name, source)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn templates() -> MailTemplates {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("templates/email");
+        MailTemplates::load(&dir, "en").unwrap()
+    }
+
+    fn context() -> serde_json::Value {
+        json!({
+            "username": "<alice>",
+            "link": "https://app.example.com/verify-email?token=abc",
+            "expires_in_minutes": 30,
+        })
+    }
+
+    #[test]
+    fn renders_every_part_in_the_default_locale() {
+        let mail = templates()
+            .render(MailTemplate::VerifyEmail, &[], context())
+            .unwrap();
+        assert_eq!(mail.subject, "Verify your email address");
+        assert!(mail.text.contains("Hi <alice>,"));
+        assert!(mail
+            .text
+            .contains("https://app.example.com/verify-email?token=abc"));
+        assert!(mail.text.contains("expires in 30 minutes"));
+        // HTML is escaped and wrapped in the shared layout.
+        assert!(mail.html.contains("Hi &lt;alice&gt;,"));
+        assert!(mail.html.starts_with("<!DOCTYPE html>"));
+    }
+
+    #[test]
+    fn picks_the_preferred_language() {
+        let templates = templates();
+        let languages = |tags: &[&str]| tags.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+
+        let mail = templates
+            .render(
+                MailTemplate::VerifyEmail,
+                &languages(&["fr", "id-ID", "en"]),
+                context(),
+            )
+            .unwrap();
+        assert_eq!(mail.subject, "Verifikasi alamat email Anda");
+
+        let mail = templates
+            .render(MailTemplate::VerifyEmail, &languages(&["fr"]), context())
+            .unwrap();
+        assert_eq!(mail.subject, "Verify your email address");
+    }
+
+    #[test]
+    fn rejects_a_directory_missing_default_templates() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("templates/email");
+        let error = MailTemplates::load(&dir, "fr").err().unwrap();
+        assert!(
+            error.starts_with("fr/verify_email.subject.txt is missing"),
+            "{}",
+            error
+        );
+    }
+}
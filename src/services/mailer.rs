@@ -0,0 +1,301 @@
+use crate::config::{MailConfig, MailTransportKind, SmtpConfig, SmtpTls};
+use crate::services::mail_templates::{MailTemplate, MailTemplates};
+use crate::utils::errors::AppError;
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncFileTransport, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serde::Serialize;
+use std::sync::Arc;
+
+/// A message with a plain-text and an HTML alternative.
+pub struct Email {
+    pub from: Mailbox,
+    pub to: Mailbox,
+    pub subject: String,
+    pub text: String,
+    pub html: String,
+}
+
+impl Email {
+    fn to_message(&self) -> Result<Message, AppError> {
+        Message::builder()
+            .from(self.from.clone())
+            .to(self.to.clone())
+            .subject(&self.subject)
+            .multipart(MultiPart::alternative_plain_html(
+                self.text.clone(),
+                self.html.clone(),
+            ))
+            .map_err(|e| AppError::Mail(e.to_string()))
+    }
+}
+
+/// Delivers emails. Selected with `MAIL_TRANSPORT`.
+#[axum::async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: &Email) -> Result<(), AppError>;
+}
+
+/// Builds the transport named by the configuration.
+pub fn mailer_from_config(config: &MailConfig) -> Result<Arc<dyn Mailer>, String> {
+    Ok(match config.transport {
+        MailTransportKind::Log => Arc::new(LogMailer),
+        MailTransportKind::File => {
+            std::fs::create_dir_all(&config.file_dir)
+                .map_err(|e| format!("{}: {}", config.file_dir.display(), e))?;
+            Arc::new(FileMailer {
+                transport: AsyncFileTransport::new(&config.file_dir),
+            })
+        }
+        MailTransportKind::Smtp => {
+            let smtp = config.smtp.as_ref().ok_or("SMTP settings are missing")?;
+            Arc::new(SmtpMailer::new(smtp)?)
+        }
+    })
+}
+
+/// Sends through an SMTP relay, reusing pooled connections.
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpMailer {
+    pub fn new(config: &SmtpConfig) -> Result<Self, String> {
+        let builder = match config.tls {
+            SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host),
+            SmtpTls::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)
+                .map_err(|e| e.to_string())?,
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host)
+                .map_err(|e| e.to_string())?,
+        };
+        let mut builder = builder.port(config.port);
+        if let (Some(username), Some(password)) = (&config.username, &config.password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+        Ok(Self {
+            transport: builder.build(),
+        })
+    }
+}
+
+#[axum::async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: &Email) -> Result<(), AppError> {
+        self.transport
+            .send(email.to_message()?)
+            .await
+            .map_err(|e| AppError::Mail(e.to_string()))?;
+        Ok(())
+    }
+}
+
+/// Writes every message as `<uuid>.eml` into a directory, for inspection or a pickup service.
+pub struct FileMailer {
+    transport: AsyncFileTransport<Tokio1Executor>,
+}
+
+#[axum::async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email: &Email) -> Result<(), AppError> {
+        self.transport
+            .send(email.to_message()?)
+            .await
+            .map_err(|e| AppError::Mail(e.to_string()))?;
+        Ok(())
+    }
+}
+
+/// Prints the text part to stdout instead of sending anything. For development.
+pub struct LogMailer;
+
+#[axum::async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, email: &Email) -> Result<(), AppError> {
+        println!(
+            "📧 To: {}\n   Subject: {}\n\n{}",
+            email.to, email.subject, email.text
+        );
+        Ok(())
+    }
+}
+
+/// Renders templated emails and hands them to the configured `Mailer`.
+pub struct Outbox {
+    mailer: Arc<dyn Mailer>,
+    templates: MailTemplates,
+    from: Mailbox,
+}
+
+impl Outbox {
+    pub fn from_config(config: &MailConfig) -> Result<Self, String> {
+        Ok(Self {
+            mailer: mailer_from_config(config)?,
+            templates: MailTemplates::load(&config.templates_dir, &config.default_locale)?,
+            from: config.from.clone(),
+        })
+    }
+
+    /// Renders `template` for the recipient's `languages` and sends it in the background, so
+    /// requests neither wait for the transport nor take longer when an email goes out.
+    /// Delivery failures are logged.
+    pub fn send(
+        &self,
+        to: &str,
+        template: MailTemplate,
+        languages: &[String],
+        context: impl Serialize,
+    ) -> Result<(), AppError> {
+        let rendered = self.templates.render(template, languages, context)?;
+        let email = Email {
+            from: self.from.clone(),
+            to: to
+                .parse()
+                .map_err(|e| AppError::Mail(format!("invalid recipient {}: {}", to, e)))?,
+            subject: rendered.subject,
+            text: rendered.text,
+            html: rendered.html,
+        };
+
+        let mailer = self.mailer.clone();
+        tokio::spawn(async move {
+            if let Err(e) = mailer.send(&email).await {
+                eprintln!(
+                    "❌ Failed to send '{}' to {}: {}",
+                    email.subject, email.to, e
+                );
+            }
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    fn email() -> Email {
+        Email {
+            from: "App <noreply@example.com>".parse().unwrap(),
+            to: "alice@example.com".parse().unwrap(),
+            subject: "Verify your email address".to_string(),
+            text: "Open this link to verify".to_string(),
+            html: "<p>Open this link to verify</p>".to_string(),
+        }
+    }
+
+    // Accepts one SMTP session and returns the DATA it received.
+    async fn smtp_sink(listener: TcpListener) -> String {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        writer
+            .write_all(b"220 localhost ESMTP sink\r\n")
+            .await
+            .unwrap();
+
+        let mut data = String::new();
+        let mut in_data = false;
+        while let Some(line) = lines.next_line().await.unwrap() {
+            if in_data {
+                if line == "." {
+                    writer.write_all(b"250 OK queued\r\n").await.unwrap();
+                    return data;
+                }
+                data.push_str(&line);
+                data.push('\n');
+                continue;
+            }
+            let reply: &[u8] = match line.get(..4).map(str::to_ascii_uppercase).as_deref() {
+                Some("EHLO") => b"250-localhost\r\n250 8BITMIME\r\n",
+                Some("DATA") => {
+                    in_data = true;
+                    b"354 End data with <CR><LF>.<CR><LF>\r\n"
+                }
+                Some("QUIT") => b"221 Bye\r\n",
+                _ => b"250 OK\r\n",
+            };
+            writer.write_all(reply).await.unwrap();
+        }
+        panic!("SMTP session ended before a message was sent");
+    }
+
+    #[tokio::test]
+    async fn smtp_mailer_delivers_to_the_relay() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let sink = tokio::spawn(smtp_sink(listener));
+
+        let mailer = SmtpMailer::new(&SmtpConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            username: None,
+            password: None,
+            tls: SmtpTls::None,
+        })
+        .unwrap();
+        mailer.send(&email()).await.unwrap();
+
+        let message = sink.await.unwrap();
+        assert!(
+            message.contains("From: App <noreply@example.com>"),
+            "{}",
+            message
+        );
+        assert!(message.contains("To: alice@example.com"), "{}", message);
+        assert!(
+            message.contains("Subject: Verify your email address"),
+            "{}",
+            message
+        );
+        assert!(
+            message.contains("Content-Type: multipart/alternative"),
+            "{}",
+            message
+        );
+        assert!(message.contains("Open this link to verify"), "{}", message);
+        assert!(
+            message.contains("<p>Open this link to verify</p>"),
+            "{}",
+            message
+        );
+    }
+
+    #[tokio::test]
+    async fn file_mailer_writes_an_eml_file() {
+        let dir = std::env::temp_dir().join(format!("mailer-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mailer = FileMailer {
+            transport: AsyncFileTransport::new(&dir),
+        };
+        mailer.send(&email()).await.unwrap();
+
+        let files: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        let message = files
+            .first()
+            .map(|file| std::fs::read_to_string(file).unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].extension().and_then(|e| e.to_str()), Some("eml"));
+        let message = message.unwrap();
+        assert!(
+            message.contains("From: App <noreply@example.com>"),
+            "{}",
+            message
+        );
+        assert!(message.contains("To: alice@example.com"), "{}", message);
+        assert!(
+            message.contains("Subject: Verify your email address"),
+            "{}",
+            message
+        );
+        assert!(message.contains("Content-Type: text/plain"), "{}", message);
+        assert!(message.contains("Content-Type: text/html"), "{}", message);
+        assert!(message.contains("Open this link to verify"), "{}", message);
+    }
+}
@@ -0,0 +1,368 @@
+use crate::config::MfaConfig;
+use crate::models::mfa::UserTotp;
+use crate::models::token::TokenUse;
+use crate::models::user::User;
+use crate::repositories::mfa::{
+    complete_mfa_challenge, delete_user_mfa, enable_totp, find_user_totp, insert_mfa_challenge,
+    record_mfa_challenge_attempt, replace_recovery_codes, upsert_pending_totp, use_recovery_code,
+    use_totp_step,
+};
+use crate::repositories::user::find_user_by_id;
+use crate::services::keys::KeyRing;
+use crate::services::login_throttle::LoginThrottle;
+use crate::services::token::{generate_one_time_token, hash_token_id, validate_one_time_token};
+use crate::utils::errors::AppError;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use chrono::Utc;
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Postgres};
+use std::net::IpAddr;
+use totp_rs::{Algorithm, TOTP};
+use uuid::Uuid;
+
+// RFC 6238 defaults, which is what authenticator apps assume when the URI does not say.
+const TOTP_DIGITS: usize = 6;
+const TOTP_STEP: u64 = 30;
+const TOTP_SECRET_BYTES: usize = 20;
+const NONCE_BYTES: usize = 12;
+// 32 symbols, so each random byte maps to one without bias; no 0/o or 1/l to confuse.
+const RECOVERY_CODE_ALPHABET: &[u8; 32] = b"abcdefghijkmnpqrstuvwxyz23456789";
+
+const INVALID_CHALLENGE: AppError = AppError::InvalidOneTimeToken(
+    "MFA challenge is invalid, has expired, has already been used or has run out of attempts",
+);
+
+/// A freshly generated TOTP secret, for the user to add to an authenticator app.
+pub struct TotpEnrollment {
+    /// Base32, for typing in by hand
+    pub secret: String,
+    /// `otpauth://totp/...`, the payload to render as a QR code
+    pub otpauth_uri: String,
+}
+
+/// The pending second step of a login.
+pub struct MfaChallenge {
+    pub token: String,
+    pub expires_in: i64,
+}
+
+/// TOTP two-factor authentication with single-use recovery codes.
+///
+/// Secrets are encrypted with `MFA_ENCRYPTION_KEY` before they are stored; recovery codes are
+/// stored as SHA-256 hashes. A code is accepted for the current 30-second step and one step
+/// either side, and never twice.
+pub struct Mfa {
+    config: MfaConfig,
+    cipher: Option<Aes256Gcm>,
+}
+
+impl Mfa {
+    pub fn new(config: &MfaConfig) -> Self {
+        Self {
+            config: config.clone(),
+            cipher: config.encryption_key.map(|key| Aes256Gcm::new(&key.into())),
+        }
+    }
+
+    pub async fn is_enabled(&self, pool: &Pool<Postgres>, user_id: Uuid) -> Result<bool, AppError> {
+        let totp = find_user_totp(pool, user_id).await?;
+        Ok(totp.is_some_and(|totp| totp.enabled_at.is_some()))
+    }
+
+    /// Generates a new secret for the user. It only takes effect once confirmed with a code;
+    /// enrolling again before that replaces it.
+    pub async fn enroll(
+        &self,
+        pool: &Pool<Postgres>,
+        user_id: Uuid,
+    ) -> Result<TotpEnrollment, AppError> {
+        let user = find_user_by_id(pool, user_id)
+            .await?
+            .ok_or(AppError::NotFound("User not found"))?;
+
+        let mut secret = vec![0u8; TOTP_SECRET_BYTES];
+        OsRng.fill_bytes(&mut secret);
+        if !upsert_pending_totp(pool, user.id, &self.encrypt(&secret)?).await? {
+            return Err(AppError::MfaAlreadyEnabled);
+        }
+
+        let totp = self.totp(secret, user.email);
+        Ok(TotpEnrollment {
+            secret: totp.get_secret_base32(),
+            otpauth_uri: totp.get_url(),
+        })
+    }
+
+    /// Enables the pending secret if `code` matches it, returning the new recovery codes.
+    pub async fn confirm(
+        &self,
+        pool: &Pool<Postgres>,
+        user_id: Uuid,
+        code: &str,
+    ) -> Result<Vec<String>, AppError> {
+        let totp = match find_user_totp(pool, user_id).await? {
+            Some(totp) if totp.enabled_at.is_some() => return Err(AppError::MfaAlreadyEnabled),
+            Some(totp) => totp,
+            None => return Err(AppError::NotFound("No two-factor enrollment is pending")),
+        };
+
+        let step = self
+            .matching_step(&totp, &normalize_code(code))?
+            .ok_or_else(|| AppError::invalid_field("code", "Is incorrect"))?;
+        if !enable_totp(pool, user_id, step, Utc::now().timestamp()).await? {
+            return Err(AppError::MfaAlreadyEnabled);
+        }
+        self.issue_recovery_codes(pool, user_id).await
+    }
+
+    /// Replaces the user's recovery codes after checking a current TOTP or recovery code.
+    pub async fn regenerate_recovery_codes(
+        &self,
+        pool: &Pool<Postgres>,
+        login_throttle: &LoginThrottle,
+        user_id: Uuid,
+        code: &str,
+        client_ip: IpAddr,
+    ) -> Result<Vec<String>, AppError> {
+        let user = find_user_by_id(pool, user_id)
+            .await?
+            .ok_or(AppError::NotFound("User not found"))?;
+        self.redeem_user_code(pool, login_throttle, &user, code, client_ip)
+            .await?;
+        self.issue_recovery_codes(pool, user_id).await
+    }
+
+    /// Turns two-factor authentication off. Takes the password, checked by the caller with
+    /// `verify_current_password`, as well as a code, so a stolen session alone cannot remove
+    /// the second factor.
+    pub async fn disable(
+        &self,
+        pool: &Pool<Postgres>,
+        login_throttle: &LoginThrottle,
+        user: &User,
+        code: &str,
+        client_ip: IpAddr,
+    ) -> Result<(), AppError> {
+        self.redeem_user_code(pool, login_throttle, user, code, client_ip)
+            .await?;
+        delete_user_mfa(pool, user.id).await?;
+        Ok(())
+    }
+
+    /// Opens the second step of a login for a user whose password checked out.
+    pub async fn challenge(
+        &self,
+        pool: &Pool<Postgres>,
+        keyring: &KeyRing,
+        user_id: Uuid,
+    ) -> Result<MfaChallenge, AppError> {
+        let (token, claims) = generate_one_time_token(
+            keyring,
+            user_id,
+            TokenUse::MfaChallenge,
+            self.config.challenge_expiry,
+        )?;
+        insert_mfa_challenge(pool, user_id, &hash_token_id(claims.jti), claims.exp).await?;
+
+        Ok(MfaChallenge {
+            token,
+            expires_in: self.config.challenge_expiry.num_seconds(),
+        })
+    }
+
+    /// Completes a login challenge with a TOTP or recovery code, returning the user to issue
+    /// tokens for. Each challenge allows `MFA_MAX_ATTEMPTS` codes and succeeds once. Wrong
+    /// codes count as failed logins of the account, so a new challenge per password login
+    /// does not buy unlimited guesses.
+    pub async fn verify_challenge(
+        &self,
+        pool: &Pool<Postgres>,
+        keyring: &KeyRing,
+        login_throttle: &LoginThrottle,
+        token: &str,
+        code: &str,
+        client_ip: IpAddr,
+    ) -> Result<Uuid, AppError> {
+        let claims =
+            validate_one_time_token(keyring, token, TokenUse::MfaChallenge).map_err(|_| {
+                AppError::InvalidOneTimeToken("MFA challenge is invalid or has expired")
+            })?;
+        let user = find_user_by_id(pool, claims.sub)
+            .await?
+            .ok_or(INVALID_CHALLENGE)?;
+        login_throttle.check(pool, &user.email, client_ip).await?;
+
+        let token_hash = hash_token_id(claims.jti);
+        let now = Utc::now().timestamp();
+
+        let user_id =
+            record_mfa_challenge_attempt(pool, &token_hash, now, self.config.max_attempts)
+                .await?
+                .filter(|user_id| *user_id == claims.sub)
+                .ok_or(INVALID_CHALLENGE)?;
+        // Disabled since the password was checked: start over with a plain login.
+        let totp = find_user_totp(pool, user_id)
+            .await?
+            .filter(|totp| totp.enabled_at.is_some())
+            .ok_or(INVALID_CHALLENGE)?;
+
+        if !self.redeem_code(pool, &totp, code).await? {
+            login_throttle
+                .record_failure(pool, &user.email, client_ip)
+                .await?;
+            return Err(AppError::InvalidMfaCode);
+        }
+        if !complete_mfa_challenge(pool, &token_hash, now).await? {
+            return Err(INVALID_CHALLENGE);
+        }
+        login_throttle.record_success(pool, &user.email).await?;
+        Ok(user_id)
+    }
+
+    async fn enabled_totp(
+        &self,
+        pool: &Pool<Postgres>,
+        user_id: Uuid,
+    ) -> Result<UserTotp, AppError> {
+        find_user_totp(pool, user_id)
+            .await?
+            .filter(|totp| totp.enabled_at.is_some())
+            .ok_or(AppError::NotFound(
+                "Two-factor authentication is not enabled",
+            ))
+    }
+
+    // Spends a code sent by a logged-in user. Wrong codes count as failed logins, as they do
+    // at `verify_challenge`, so an access token alone does not buy unlimited guesses.
+    async fn redeem_user_code(
+        &self,
+        pool: &Pool<Postgres>,
+        login_throttle: &LoginThrottle,
+        user: &User,
+        code: &str,
+        client_ip: IpAddr,
+    ) -> Result<(), AppError> {
+        login_throttle.check(pool, &user.email, client_ip).await?;
+        let totp = self.enabled_totp(pool, user.id).await?;
+        if !self.redeem_code(pool, &totp, code).await? {
+            login_throttle
+                .record_failure(pool, &user.email, client_ip)
+                .await?;
+            return Err(AppError::invalid_field("code", "Is incorrect"));
+        }
+        Ok(())
+    }
+
+    // Spends a TOTP code (six digits) or a recovery code (anything else).
+    async fn redeem_code(
+        &self,
+        pool: &Pool<Postgres>,
+        totp: &UserTotp,
+        code: &str,
+    ) -> Result<bool, AppError> {
+        let code = normalize_code(code);
+        if code.len() == TOTP_DIGITS && code.bytes().all(|b| b.is_ascii_digit()) {
+            return match self.matching_step(totp, &code)? {
+                Some(step) => Ok(use_totp_step(pool, totp.user_id, step).await?),
+                None => Ok(false),
+            };
+        }
+        let used = use_recovery_code(
+            pool,
+            totp.user_id,
+            &hash_recovery_code(&code),
+            Utc::now().timestamp(),
+        )
+        .await?;
+        Ok(used)
+    }
+
+    // The time step `code` is valid for, if any. Whether it was already used is up to the caller.
+    fn matching_step(&self, totp: &UserTotp, code: &str) -> Result<Option<i64>, AppError> {
+        let generator = self.totp(self.decrypt(&totp.secret)?, String::new());
+        let current = Utc::now().timestamp() as u64 / TOTP_STEP;
+        Ok([current - 1, current, current + 1]
+            .into_iter()
+            .find(|step| generator.check(code, step * TOTP_STEP))
+            .map(|step| step as i64))
+    }
+
+    async fn issue_recovery_codes(
+        &self,
+        pool: &Pool<Postgres>,
+        user_id: Uuid,
+    ) -> Result<Vec<String>, AppError> {
+        let codes: Vec<String> = (0..self.config.recovery_codes)
+            .map(|_| generate_recovery_code())
+            .collect();
+        let hashes: Vec<String> = codes
+            .iter()
+            .map(|code| hash_recovery_code(&normalize_code(code)))
+            .collect();
+        replace_recovery_codes(pool, user_id, &hashes).await?;
+        Ok(codes)
+    }
+
+    fn totp(&self, secret: Vec<u8>, account_name: String) -> TOTP {
+        // Skew 0: `matching_step` checks the neighbouring steps itself to learn which one matched.
+        // The secret length and issuer are already valid, which is all `TOTP::new` would check.
+        TOTP::new_unchecked(
+            Algorithm::SHA1,
+            TOTP_DIGITS,
+            0,
+            TOTP_STEP,
+            secret,
+            Some(self.config.issuer.clone()),
+            account_name,
+        )
+    }
+
+    fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, AppError> {
+        let cipher = self.cipher.as_ref().ok_or(AppError::MfaUnavailable)?;
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| AppError::Encryption("failed to encrypt TOTP secret"))?;
+        Ok([nonce.as_slice(), &ciphertext].concat())
+    }
+
+    fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, AppError> {
+        let cipher = self.cipher.as_ref().ok_or(AppError::MfaUnavailable)?;
+        if data.len() < NONCE_BYTES {
+            return Err(AppError::Encryption("stored TOTP secret is truncated"));
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_BYTES);
+        cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| {
+                AppError::Encryption(
+                    "failed to decrypt TOTP secret; was MFA_ENCRYPTION_KEY changed?",
+                )
+            })
+    }
+}
+
+// `abcde-fghij`: 50 random bits, enough that a fast hash is safe to store.
+fn generate_recovery_code() -> String {
+    let mut bytes = [0u8; 10];
+    OsRng.fill_bytes(&mut bytes);
+    let symbols: String = bytes
+        .iter()
+        .map(|b| RECOVERY_CODE_ALPHABET[(b & 31) as usize] as char)
+        .collect();
+    format!("{}-{}", &symbols[..5], &symbols[5..])
+}
+
+// Codes may be typed with spaces, dashes or capitals.
+fn normalize_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+fn hash_recovery_code(code: &str) -> String {
+    hex::encode(Sha256::digest(code.as_bytes()))
+}
@@ -1,3 +1,18 @@
 pub mod auth;
+pub mod breached_passwords;
+pub mod email_verification;
+pub mod keys;
+pub mod login_throttle;
+pub mod magic_link;
+pub mod mail_templates;
+pub mod mailer;
+pub mod mfa;
+pub mod password_hash;
+pub mod password_policy;
+pub mod password_reset;
+pub mod rate_limit;
+pub mod session;
 pub mod token;
+pub mod token_version;
 pub mod user;
+pub mod webauthn;
@@ -0,0 +1,135 @@
+use crate::config::Argon2Config;
+use argon2::password_hash::{
+    rand_core::OsRng, Error, PasswordHash, PasswordHasher as _, PasswordVerifier as _, SaltString,
+};
+use argon2::{Algorithm, Argon2, KeyId, Params, ParamsBuilder, Version};
+use sha2::{Digest, Sha256};
+
+/// Hashes new passwords with the configured Argon2 variant and costs, and verifies stored
+/// hashes of any supported kind: Argon2 with any parameters, plus bcrypt and scrypt hashes
+/// imported from the legacy system.
+///
+/// With a pepper configured, new hashes carry a `keyid` derived from it in their PHC string,
+/// so hashes made before the pepper was introduced are still recognized (and upgraded).
+pub struct PasswordHasher {
+    algorithm: Algorithm,
+    params: Params,
+    pepper: Option<Vec<u8>>,
+    // Hash of a random password with the current settings, verified against for unknown
+    // accounts so a lookup miss costs the same as a wrong password.
+    dummy_hash: String,
+}
+
+/// Outcome of checking a password against a stored hash.
+pub enum Verification {
+    Mismatch,
+    /// `needs_rehash` is set when the stored hash is weaker than the current configuration.
+    Match {
+        needs_rehash: bool,
+    },
+}
+
+impl PasswordHasher {
+    pub fn from_config(config: &Argon2Config) -> Result<Self, String> {
+        let pepper = config.pepper.as_ref().map(|p| p.as_bytes().to_vec());
+
+        let mut builder = ParamsBuilder::new();
+        builder
+            .m_cost(config.params.m_cost())
+            .t_cost(config.params.t_cost())
+            .p_cost(config.params.p_cost());
+        if let Some(pepper) = &pepper {
+            builder.keyid(pepper_id(pepper).map_err(|e| e.to_string())?);
+        }
+        let params = builder.build().map_err(|e| e.to_string())?;
+
+        let mut hasher = Self {
+            algorithm: config.algorithm,
+            params,
+            pepper,
+            dummy_hash: String::new(),
+        };
+        // Also surfaces an unusable pepper at startup rather than on the first registration.
+        let random = SaltString::generate(&mut OsRng);
+        hasher.dummy_hash = hasher.hash(random.as_str()).map_err(|e| e.to_string())?;
+        Ok(hasher)
+    }
+
+    pub fn hash(&self, password: &str) -> Result<String, Error> {
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = self
+            .argon2(self.pepper.is_some())?
+            .hash_password(password.as_bytes(), &salt)?;
+        Ok(hash.to_string())
+    }
+
+    /// Checks `password` against `stored`. Fails only when `stored` cannot be parsed.
+    pub fn verify(&self, password: &str, stored: &str) -> Result<Verification, Error> {
+        // bcrypt predates the PHC string format: `$2a$`, `$2b$` or `$2y$`.
+        if stored.starts_with("$2") {
+            return match bcrypt::verify(password, stored) {
+                Ok(true) => Ok(Verification::Match { needs_rehash: true }),
+                Ok(false) => Ok(Verification::Mismatch),
+                Err(_) => Err(Error::PhcStringField),
+            };
+        }
+
+        let hash = PasswordHash::new(stored)?;
+        let result = match hash.algorithm.as_str() {
+            "scrypt" => scrypt::Scrypt
+                .verify_password(password.as_bytes(), &hash)
+                .map(|_| true),
+            _ => {
+                let params = Params::try_from(&hash)?;
+                self.argon2(!params.keyid().is_empty())?
+                    .verify_password(password.as_bytes(), &hash)
+                    .map(|_| self.is_weaker(&hash, &params))
+            }
+        };
+
+        match result {
+            Ok(needs_rehash) => Ok(Verification::Match { needs_rehash }),
+            Err(Error::Password) => Ok(Verification::Mismatch),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Spends the same work as verifying a real hash; used when there is no account to check.
+    pub fn verify_dummy(&self, password: &str) {
+        let _ = self.verify(password, &self.dummy_hash);
+    }
+
+    // The secret only applies to hashes that were made with it (they carry a `keyid`).
+    fn argon2(&self, peppered: bool) -> Result<Argon2<'_>, Error> {
+        match self.pepper.as_deref().filter(|_| peppered) {
+            Some(pepper) => Ok(Argon2::new_with_secret(
+                pepper,
+                self.algorithm,
+                Version::V0x13,
+                self.params.clone(),
+            )?),
+            None => Ok(Argon2::new(
+                self.algorithm,
+                Version::V0x13,
+                self.params.clone(),
+            )),
+        }
+    }
+
+    // Rehash on a different variant, an older version, lower costs or a missing/changed
+    // pepper. Costs above the configured ones are kept, so lowering the config never
+    // downgrades existing hashes.
+    fn is_weaker(&self, hash: &PasswordHash, params: &Params) -> bool {
+        hash.algorithm != self.algorithm.ident()
+            || hash.version != Some(Version::V0x13.into())
+            || params.m_cost() < self.params.m_cost()
+            || params.t_cost() < self.params.t_cost()
+            || params.p_cost() < self.params.p_cost()
+            || params.keyid() != self.params.keyid()
+    }
+}
+
+// Identifies the pepper in stored hashes without revealing it.
+fn pepper_id(pepper: &[u8]) -> argon2::Result<KeyId> {
+    KeyId::new(&Sha256::digest(pepper)[..6])
+}
@@ -0,0 +1,407 @@
+use crate::config::PasswordPolicyConfig;
+use crate::services::breached_passwords::BreachedPasswordRule;
+use crate::utils::errors::AppError;
+use std::collections::HashMap;
+
+/// A single password requirement. The policy runs every rule and reports all failures.
+pub trait PasswordRule: Send + Sync {
+    /// Messages for each way `password` breaks the rule; empty when it passes.
+    /// `user_inputs` are the account's own values (username, email) for rules that need them.
+    fn check(&self, password: &str, user_inputs: &[&str]) -> Vec<String>;
+}
+
+/// Password requirements built once from `PasswordPolicyConfig` and shared through `AppState`.
+/// Enforced wherever a password is set, never on login.
+pub struct PasswordPolicy {
+    rules: Vec<Box<dyn PasswordRule>>,
+}
+
+impl PasswordPolicy {
+    /// Fails when the breached-password corpus cannot be loaded.
+    pub fn from_config(config: &PasswordPolicyConfig) -> Result<Self, String> {
+        let mut rules: Vec<Box<dyn PasswordRule>> = vec![
+            Box::new(LengthRule {
+                min: config.min_length,
+                max: config.max_length,
+            }),
+            Box::new(CharacterClassRule {
+                lowercase: config.require_lowercase,
+                uppercase: config.require_uppercase,
+                digit: config.require_digit,
+                symbol: config.require_symbol,
+            }),
+        ];
+        if config.disallow_user_info {
+            rules.push(Box::new(UserInfoRule));
+        }
+        if config.min_strength > 0 {
+            rules.push(Box::new(StrengthRule {
+                min_score: config.min_strength,
+            }));
+        }
+        if let Some(path) = &config.breached_passwords_path {
+            rules.push(Box::new(BreachedPasswordRule::load(
+                path,
+                config.breached_min_count,
+            )?));
+        }
+        Ok(Self { rules })
+    }
+
+    /// Rejects `password` with `VALIDATION_FAILED`, listing every broken rule under `password`.
+    pub fn validate(&self, password: &str, user_inputs: &[&str]) -> Result<(), AppError> {
+        let messages: Vec<String> = self
+            .rules
+            .iter()
+            .flat_map(|rule| rule.check(password, user_inputs))
+            .collect();
+        if messages.is_empty() {
+            Ok(())
+        } else {
+            Err(AppError::Validation(HashMap::from([(
+                "password".to_string(),
+                messages,
+            )])))
+        }
+    }
+}
+
+struct LengthRule {
+    min: usize,
+    max: usize,
+}
+
+impl PasswordRule for LengthRule {
+    fn check(&self, password: &str, _: &[&str]) -> Vec<String> {
+        let length = password.chars().count();
+        if length < self.min || length > self.max {
+            vec![format!(
+                "Must be between {} and {} characters",
+                self.min, self.max
+            )]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+struct CharacterClassRule {
+    lowercase: bool,
+    uppercase: bool,
+    digit: bool,
+    symbol: bool,
+}
+
+impl PasswordRule for CharacterClassRule {
+    fn check(&self, password: &str, _: &[&str]) -> Vec<String> {
+        let has = |class: fn(&char) -> bool| password.chars().any(|c| class(&c));
+        let mut messages = Vec::new();
+        if self.lowercase && !has(char::is_ascii_lowercase) {
+            messages.push("Must contain a lowercase letter".to_string());
+        }
+        if self.uppercase && !has(char::is_ascii_uppercase) {
+            messages.push("Must contain an uppercase letter".to_string());
+        }
+        if self.digit && !has(char::is_ascii_digit) {
+            messages.push("Must contain a digit".to_string());
+        }
+        if self.symbol && !has(|c| !c.is_alphanumeric()) {
+            messages.push("Must contain a symbol".to_string());
+        }
+        messages
+    }
+}
+
+struct UserInfoRule;
+
+impl PasswordRule for UserInfoRule {
+    fn check(&self, password: &str, user_inputs: &[&str]) -> Vec<String> {
+        let password = password.to_lowercase();
+        let contains_user_info = user_inputs
+            .iter()
+            // For emails only the local part is meaningful to a guesser.
+            .map(|input| input.split('@').next().unwrap_or(input).to_lowercase())
+            .any(|input| input.chars().count() >= 3 && password.contains(&input));
+        if contains_user_info {
+            vec!["Must not contain your username or email".to_string()]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+struct StrengthRule {
+    min_score: u8,
+}
+
+impl PasswordRule for StrengthRule {
+    fn check(&self, password: &str, _: &[&str]) -> Vec<String> {
+        if strength_score(password) < self.min_score {
+            vec![
+                "Is too easy to guess; use a longer passphrase or mix in unrelated words"
+                    .to_string(),
+            ]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+// Frequent password fragments; each counts as a single guess from a small dictionary.
+const COMMON_WORDS: &[&str] = &[
+    "password",
+    "passwort",
+    "qwerty",
+    "qwertz",
+    "azerty",
+    "asdf",
+    "zxcv",
+    "letmein",
+    "welcome",
+    "admin",
+    "administrator",
+    "login",
+    "master",
+    "secret",
+    "dragon",
+    "monkey",
+    "shadow",
+    "sunshine",
+    "princess",
+    "football",
+    "baseball",
+    "soccer",
+    "hockey",
+    "superman",
+    "batman",
+    "iloveyou",
+    "love",
+    "trustno",
+    "freedom",
+    "whatever",
+    "hello",
+    "charlie",
+    "michael",
+    "jordan",
+    "summer",
+    "winter",
+    "spring",
+    "autumn",
+    "flower",
+    "cheese",
+    "computer",
+    "internet",
+    "google",
+    "starwars",
+    "pokemon",
+    "killer",
+    "hunter",
+    "ranger",
+    "buster",
+    "tigger",
+    "pepper",
+    "ginger",
+    "cookie",
+    "matrix",
+    "access",
+    "changeme",
+    "default",
+    "test",
+    "guest",
+    "root",
+    "user",
+    "pass",
+    "qazwsx",
+    "mustang",
+    "harley",
+];
+
+// zxcvbn-style estimate from 0 (trivially guessable) to 4 (very strong).
+//
+// Walks the password left to right and charges bits per token: a dictionary word (after
+// undoing common l33t substitutions) costs about log2 of the dictionary size, a repeated or
+// sequential character (`aaa`, `abc`, `123`) costs a bit or two, anything else costs
+// log2 of the character pool in use. The total is bucketed like zxcvbn's guess thresholds.
+fn strength_score(password: &str) -> u8 {
+    let chars: Vec<char> = password.chars().collect();
+    let normalized: Vec<char> = chars.iter().map(|c| unleet(*c)).collect();
+    let pool_bits = (pool_size(&chars) as f64).log2();
+    let word_bits = (COMMON_WORDS.len() as f64).log2();
+
+    let mut bits = 0.0;
+    let mut i = 0;
+    while i < chars.len() {
+        if let Some(word) = dictionary_match(&normalized[i..]) {
+            let len = word.chars().count();
+            let original = &chars[i..i + len];
+            // Capitalization or substitutions add a little uncertainty.
+            let varied = original.iter().any(|c| !c.is_lowercase());
+            bits += word_bits + if varied { 1.0 } else { 0.0 };
+            i += len;
+            continue;
+        }
+
+        let step = match i.checked_sub(1).map(|p| chars[p]) {
+            Some(prev) if prev == chars[i] => 1.0,
+            Some(prev) if (chars[i] as i64 - prev as i64).abs() == 1 => 1.5,
+            _ => pool_bits,
+        };
+        bits += step;
+        i += 1;
+    }
+
+    match bits {
+        b if b < 25.0 => 0,
+        b if b < 35.0 => 1,
+        b if b < 45.0 => 2,
+        b if b < 60.0 => 3,
+        _ => 4,
+    }
+}
+
+fn dictionary_match(rest: &[char]) -> Option<&'static str> {
+    COMMON_WORDS
+        .iter()
+        .filter(|word| {
+            let word: Vec<char> = word.chars().collect();
+            rest.len() >= word.len() && rest[..word.len()] == word[..]
+        })
+        .max_by_key(|word| word.len())
+        .copied()
+}
+
+fn unleet(c: char) -> char {
+    match c.to_ascii_lowercase() {
+        '0' => 'o',
+        '1' | '!' => 'i',
+        '3' => 'e',
+        '4' | '@' => 'a',
+        '5' | '$' => 's',
+        '7' => 't',
+        other => other,
+    }
+}
+
+fn pool_size(chars: &[char]) -> u32 {
+    let mut pool = 0;
+    if chars.iter().any(|c| c.is_ascii_lowercase()) {
+        pool += 26;
+    }
+    if chars.iter().any(|c| c.is_ascii_uppercase()) {
+        pool += 26;
+    }
+    if chars.iter().any(|c| c.is_ascii_digit()) {
+        pool += 10;
+    }
+    if chars.iter().any(|c| c.is_ascii_punctuation() || *c == ' ') {
+        pool += 33;
+    }
+    if chars.iter().any(|c| !c.is_ascii()) {
+        pool += 100;
+    }
+    pool.max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> PasswordPolicyConfig {
+        PasswordPolicyConfig {
+            min_length: 8,
+            max_length: 64,
+            require_lowercase: false,
+            require_uppercase: false,
+            require_digit: false,
+            require_symbol: false,
+            min_strength: 0,
+            disallow_user_info: false,
+            breached_passwords_path: None,
+            breached_min_count: 1,
+        }
+    }
+
+    fn messages(policy: &PasswordPolicy, password: &str, user_inputs: &[&str]) -> Vec<String> {
+        match policy.validate(password, user_inputs) {
+            Ok(()) => Vec::new(),
+            Err(AppError::Validation(fields)) => fields["password"].clone(),
+            Err(e) => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn reports_every_broken_rule() {
+        let policy = PasswordPolicy::from_config(&PasswordPolicyConfig {
+            min_length: 12,
+            require_lowercase: true,
+            require_uppercase: true,
+            require_digit: true,
+            require_symbol: true,
+            min_strength: 3,
+            disallow_user_info: true,
+            ..config()
+        })
+        .unwrap();
+
+        let messages = messages(&policy, "alice1234", &["alice", "alice@example.com"]);
+        assert_eq!(
+            messages,
+            [
+                "Must be between 12 and 64 characters",
+                "Must contain an uppercase letter",
+                "Must contain a symbol",
+                "Must not contain your username or email",
+                "Is too easy to guess; use a longer passphrase or mix in unrelated words",
+            ]
+        );
+        assert!(policy
+            .validate("Velvet-Orbit-Canyon-42", &["alice", "alice@example.com"])
+            .is_ok());
+    }
+
+    #[test]
+    fn counts_length_in_characters() {
+        let policy = PasswordPolicy::from_config(&PasswordPolicyConfig {
+            max_length: 10,
+            ..config()
+        })
+        .unwrap();
+        assert!(policy.validate("ééééééééé", &[]).is_ok());
+        assert!(policy.validate("ééééééé", &[]).is_err());
+        assert!(policy.validate("ééééééééééé", &[]).is_err());
+    }
+
+    #[test]
+    fn matches_user_info_case_insensitively_by_email_local_part() {
+        let policy = PasswordPolicy::from_config(&PasswordPolicyConfig {
+            disallow_user_info: true,
+            ..config()
+        })
+        .unwrap();
+        let inputs = ["Jo", "Maria.Lopez@example.com"];
+        assert!(policy.validate("my-MARIA.LOPEZ-pass", &inputs).is_err());
+        // The domain and inputs shorter than three characters are not held against it.
+        assert!(policy.validate("example-jo-pass", &inputs).is_ok());
+    }
+
+    #[test]
+    fn scores_common_words_and_patterns_as_guessable() {
+        for password in [
+            "password",
+            "P@ssw0rd",
+            "qwerty123",
+            "aaaaaaaaaaaa",
+            "1234567890",
+            "abcdefghijkl",
+        ] {
+            assert_eq!(strength_score(password), 0, "{}", password);
+        }
+    }
+
+    #[test]
+    fn scores_long_unpredictable_passwords_as_strong() {
+        assert_eq!(strength_score("Velvet-Orbit-Canyon-42"), 4);
+        assert_eq!(strength_score("correct horse battery staple"), 4);
+        assert!(strength_score("kX9#mQ2v") < strength_score("kX9#mQ2vLp7$wR4z"));
+    }
+}
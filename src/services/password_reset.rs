@@ -0,0 +1,139 @@
+use crate::config::PasswordResetConfig;
+use crate::models::token::TokenUse;
+use crate::repositories::password_reset::{
+    insert_password_reset_token, invalidate_password_reset_tokens, use_password_reset_token,
+};
+use crate::repositories::user::{find_user_by_email, find_user_by_id, update_user_password_hash};
+use crate::services::keys::KeyRing;
+use crate::services::login_throttle::unlock_account;
+use crate::services::mail_templates::MailTemplate;
+use crate::services::mailer::Outbox;
+use crate::services::password_hash::PasswordHasher;
+use crate::services::password_policy::PasswordPolicy;
+use crate::services::session::revoke_all_sessions;
+use crate::services::token::{generate_one_time_token, hash_token_id, validate_one_time_token};
+use crate::services::token_version::TokenVersions;
+use crate::utils::errors::AppError;
+use chrono::Utc;
+use serde_json::json;
+use sqlx::{Pool, Postgres};
+use std::sync::Arc;
+
+const INVALID_RESET_TOKEN: AppError =
+    AppError::InvalidOneTimeToken("Reset token is invalid, has expired or has already been used");
+
+/// Sends and redeems password reset links.
+///
+/// A link carries a signed `password_reset` token whose hashed `jti` is recorded in
+/// `password_reset_tokens`; redeeming it marks the row used, so every link works once.
+pub struct PasswordResetter {
+    config: PasswordResetConfig,
+    public_url: String,
+    outbox: Arc<Outbox>,
+    token_versions: Arc<TokenVersions>,
+}
+
+impl PasswordResetter {
+    pub fn new(
+        config: &PasswordResetConfig,
+        public_url: &str,
+        outbox: Arc<Outbox>,
+        token_versions: Arc<TokenVersions>,
+    ) -> Self {
+        Self {
+            config: config.clone(),
+            public_url: public_url.to_string(),
+            outbox,
+            token_versions,
+        }
+    }
+
+    /// Emails a reset link if the email is registered. The lookup, the token and the email
+    /// are all handled in the background, so neither the response nor how long it takes
+    /// reveals which emails exist; failures are logged.
+    pub fn request(
+        self: &Arc<Self>,
+        pool: &Pool<Postgres>,
+        keyring: &Arc<KeyRing>,
+        email: &str,
+        languages: &[String],
+    ) {
+        let (resetter, pool, keyring) = (self.clone(), pool.clone(), keyring.clone());
+        let (email, languages) = (email.to_string(), languages.to_vec());
+        tokio::spawn(async move {
+            if let Err(e) = resetter
+                .send_link(&pool, &keyring, &email, &languages)
+                .await
+            {
+                eprintln!("❌ Failed to send password reset link: {}", e);
+            }
+        });
+    }
+
+    async fn send_link(
+        &self,
+        pool: &Pool<Postgres>,
+        keyring: &KeyRing,
+        email: &str,
+        languages: &[String],
+    ) -> Result<(), AppError> {
+        let Some(user) = find_user_by_email(pool, email).await? else {
+            return Ok(());
+        };
+
+        let (token, claims) = generate_one_time_token(
+            keyring,
+            user.id,
+            TokenUse::PasswordReset,
+            self.config.token_expiry,
+        )?;
+        insert_password_reset_token(pool, user.id, &hash_token_id(claims.jti), claims.exp).await?;
+
+        let link = format!("{}/reset-password?token={}", self.public_url, token);
+        self.outbox.send(
+            &user.email,
+            MailTemplate::PasswordReset,
+            languages,
+            json!({
+                "username": user.username,
+                "link": link,
+                "expires_in_minutes": self.config.token_expiry.num_minutes(),
+            }),
+        )
+    }
+
+    /// Sets a new password with a reset token, then logs the user out everywhere, access
+    /// tokens included, and retires any other reset links.
+    pub async fn reset(
+        &self,
+        pool: &Pool<Postgres>,
+        keyring: &KeyRing,
+        password_policy: &PasswordPolicy,
+        password_hasher: &PasswordHasher,
+        token: &str,
+        password: &str,
+    ) -> Result<(), AppError> {
+        let claims = validate_one_time_token(keyring, token, TokenUse::PasswordReset)
+            .map_err(|_| AppError::InvalidOneTimeToken("Reset token is invalid or has expired"))?;
+        let user = find_user_by_id(pool, claims.sub)
+            .await?
+            .ok_or(INVALID_RESET_TOKEN)?;
+
+        // Checked before the token is spent, so a rejected password can be retried with the same link.
+        password_policy.validate(password, &[&user.username, &user.email])?;
+        let password_hash = password_hasher.hash(password)?;
+
+        let now = Utc::now().timestamp();
+        use_password_reset_token(pool, &hash_token_id(claims.jti), now)
+            .await?
+            .filter(|user_id| *user_id == user.id)
+            .ok_or(INVALID_RESET_TOKEN)?;
+
+        update_user_password_hash(pool, user.id, &password_hash).await?;
+        invalidate_password_reset_tokens(pool, user.id, now).await?;
+        revoke_all_sessions(pool, &self.token_versions, user.id).await?;
+        // Whoever holds the new password is the owner; earlier failed logins no longer matter.
+        unlock_account(pool, &user.email).await?;
+        Ok(())
+    }
+}
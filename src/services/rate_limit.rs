@@ -0,0 +1,260 @@
+use crate::config::{RateLimitConfig, RateLimitRule, RateLimitStoreKind};
+use crate::repositories::rate_limit::{delete_idle_rate_limit_buckets, take_rate_limit_token};
+use crate::utils::errors::AppError;
+use chrono::Utc;
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Postgres};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Mutex;
+
+// Idle buckets are swept once the store holds this many (memory) or this often (Postgres).
+const MEMORY_SWEEP_THRESHOLD: usize = 10_000;
+const POSTGRES_SWEEP_INTERVAL_MS: i64 = 10 * 60 * 1000;
+
+/// Route groups with their own limit, applied in `routes/`.
+#[derive(Debug, Clone, Copy)]
+pub enum RouteGroup {
+    Auth,
+    Api,
+}
+
+impl RouteGroup {
+    pub fn name(self) -> &'static str {
+        match self {
+            RouteGroup::Auth => "auth",
+            RouteGroup::Api => "api",
+        }
+    }
+}
+
+/// Outcome of taking a token, with what the `RateLimit-*` headers report.
+pub struct RateLimitDecision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    // Seconds until the bucket is full again
+    pub reset: u64,
+    // Seconds until the next token, when not allowed
+    pub retry_after: u64,
+    pub window: u64,
+}
+
+/// Where buckets are kept. Each call refills the bucket for the elapsed time and takes one
+/// token if a whole one is available, returning the tokens left and whether it took one.
+#[axum::async_trait]
+pub trait RateLimitStore: Send + Sync {
+    async fn take(
+        &self,
+        key: &str,
+        capacity: f64,
+        tokens_per_ms: f64,
+        now_ms: i64,
+    ) -> Result<(f64, bool), AppError>;
+}
+
+/// Token-bucket rate limiter shared through `AppState`.
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    store: Box<dyn RateLimitStore>,
+    // Hashes of `RATE_LIMIT_API_KEYS`, as `api_key_id` returns them
+    api_keys: HashSet<String>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig, pool: Pool<Postgres>) -> Self {
+        // A bucket left alone for the longest period is full, so forgetting it changes nothing.
+        let idle_after_ms = config.auth.period.max(config.api.period).num_milliseconds();
+        let store: Box<dyn RateLimitStore> = match config.store {
+            RateLimitStoreKind::Memory => Box::new(MemoryStore {
+                buckets: Mutex::default(),
+                idle_after_ms,
+            }),
+            RateLimitStoreKind::Postgres => Box::new(PostgresStore {
+                pool,
+                idle_after_ms,
+                last_sweep_ms: AtomicI64::new(0),
+            }),
+        };
+        Self {
+            config: config.clone(),
+            store,
+            api_keys: config
+                .api_keys
+                .iter()
+                .map(|key| api_key_id(key.as_bytes()))
+                .collect(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn rule(&self, group: RouteGroup) -> &RateLimitRule {
+        match group {
+            RouteGroup::Auth => &self.config.auth,
+            RouteGroup::Api => &self.config.api,
+        }
+    }
+
+    /// The bucket id of a configured API key, or `None` for keys we do not know, so made-up
+    /// keys cannot each get a fresh bucket.
+    pub fn known_api_key(&self, key: &[u8]) -> Option<String> {
+        let id = api_key_id(key);
+        self.api_keys.contains(&id).then_some(id)
+    }
+
+    /// Takes a token from the bucket `key` under the limit of `group`.
+    pub async fn check(&self, group: RouteGroup, key: &str) -> Result<RateLimitDecision, AppError> {
+        let rule = self.rule(group);
+        let capacity = rule.capacity as f64;
+        let tokens_per_ms = capacity / rule.period.num_milliseconds() as f64;
+        let now_ms = Utc::now().timestamp_millis();
+
+        let (tokens, allowed) = self
+            .store
+            .take(key, capacity, tokens_per_ms, now_ms)
+            .await?;
+
+        let seconds_until =
+            |tokens_needed: f64| (tokens_needed / tokens_per_ms / 1000.0).ceil() as u64;
+        Ok(RateLimitDecision {
+            allowed,
+            limit: rule.capacity,
+            remaining: tokens.floor() as u32,
+            reset: seconds_until(capacity - tokens),
+            retry_after: seconds_until(1.0 - tokens).max(1),
+            window: rule.period.num_seconds() as u64,
+        })
+    }
+}
+
+// Bucket ids carry a hash so the keys themselves never end up in the store.
+fn api_key_id(key: &[u8]) -> String {
+    hex::encode(&Sha256::digest(key)[..16])
+}
+
+struct MemoryStore {
+    // key -> (tokens, updated_at_ms)
+    buckets: Mutex<HashMap<String, (f64, i64)>>,
+    idle_after_ms: i64,
+}
+
+#[axum::async_trait]
+impl RateLimitStore for MemoryStore {
+    async fn take(
+        &self,
+        key: &str,
+        capacity: f64,
+        tokens_per_ms: f64,
+        now_ms: i64,
+    ) -> Result<(f64, bool), AppError> {
+        let mut buckets = self
+            .buckets
+            .lock()
+            .expect("rate limit bucket lock poisoned");
+        if buckets.len() >= MEMORY_SWEEP_THRESHOLD {
+            buckets.retain(|_, (_, updated_at_ms)| now_ms - *updated_at_ms < self.idle_after_ms);
+        }
+
+        let (tokens, updated_at_ms) = buckets.entry(key.to_string()).or_insert((capacity, now_ms));
+        let refilled = (*tokens + (now_ms - *updated_at_ms) as f64 * tokens_per_ms).min(capacity);
+        let allowed = refilled >= 1.0;
+        *tokens = if allowed { refilled - 1.0 } else { refilled };
+        *updated_at_ms = now_ms;
+        Ok((*tokens, allowed))
+    }
+}
+
+// Shares buckets between instances through the `rate_limit_buckets` table.
+struct PostgresStore {
+    pool: Pool<Postgres>,
+    idle_after_ms: i64,
+    last_sweep_ms: AtomicI64,
+}
+
+#[axum::async_trait]
+impl RateLimitStore for PostgresStore {
+    async fn take(
+        &self,
+        key: &str,
+        capacity: f64,
+        tokens_per_ms: f64,
+        now_ms: i64,
+    ) -> Result<(f64, bool), AppError> {
+        let last_sweep_ms = self.last_sweep_ms.load(Ordering::Relaxed);
+        if now_ms - last_sweep_ms >= POSTGRES_SWEEP_INTERVAL_MS
+            && self
+                .last_sweep_ms
+                .compare_exchange(last_sweep_ms, now_ms, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            delete_idle_rate_limit_buckets(&self.pool, now_ms - self.idle_after_ms).await?;
+        }
+
+        Ok(take_rate_limit_token(&self.pool, key, capacity, tokens_per_ms, now_ms).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> MemoryStore {
+        MemoryStore {
+            buckets: Mutex::default(),
+            idle_after_ms: 60_000,
+        }
+    }
+
+    // Three tokens, refilled at one per second.
+    async fn take(store: &MemoryStore, key: &str, now_ms: i64) -> (f64, bool) {
+        store.take(key, 3.0, 0.001, now_ms).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn takes_one_token_per_request_from_a_full_bucket() {
+        let store = store();
+        assert_eq!(take(&store, "a", 0).await, (2.0, true));
+        assert_eq!(take(&store, "a", 0).await, (1.0, true));
+        assert_eq!(take(&store, "a", 0).await, (0.0, true));
+        assert_eq!(take(&store, "a", 0).await, (0.0, false));
+        // Buckets are independent per key.
+        assert_eq!(take(&store, "b", 0).await, (2.0, true));
+    }
+
+    #[tokio::test]
+    async fn refills_for_the_elapsed_time() {
+        let store = store();
+        for _ in 0..3 {
+            take(&store, "a", 0).await;
+        }
+        // Half a token is not enough, and a rejected request costs nothing.
+        assert_eq!(take(&store, "a", 500).await, (0.5, false));
+        assert_eq!(take(&store, "a", 1_000).await, (0.0, true));
+        assert_eq!(take(&store, "a", 2_500).await, (0.5, true));
+    }
+
+    #[tokio::test]
+    async fn refills_no_further_than_capacity() {
+        let store = store();
+        take(&store, "a", 0).await;
+        assert_eq!(take(&store, "a", 3_600_000).await, (2.0, true));
+    }
+
+    #[tokio::test]
+    async fn sweeps_idle_buckets_once_the_store_is_full() {
+        let store = store();
+        store
+            .buckets
+            .lock()
+            .unwrap()
+            .extend((0..MEMORY_SWEEP_THRESHOLD).map(|i| (format!("idle-{}", i), (3.0, 0))));
+        take(&store, "recent", 59_999).await;
+        take(&store, "a", 60_000).await;
+        let buckets = store.buckets.lock().unwrap();
+        assert_eq!(buckets.len(), 2);
+        assert!(buckets.contains_key("recent"));
+    }
+}
@@ -0,0 +1,55 @@
+use crate::models::session::Session;
+use crate::repositories::refresh_token::{
+    revoke_other_refresh_tokens, revoke_user_refresh_token_family, revoke_user_refresh_tokens,
+};
+use crate::repositories::session::list_active_sessions;
+use crate::services::token_version::TokenVersions;
+use crate::utils::errors::AppError;
+use chrono::Utc;
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+// A session is a refresh token family; ending one revokes its refresh tokens. Access tokens
+// already issued for it stay valid until they expire, unless the user's token version is
+// bumped as well, as `revoke_all_sessions` does.
+
+pub async fn list_sessions(pool: &Pool<Postgres>, user_id: Uuid) -> Result<Vec<Session>, AppError> {
+    Ok(list_active_sessions(pool, user_id, Utc::now().timestamp()).await?)
+}
+
+/// Logs one of the user's sessions out, which may be the current one.
+pub async fn revoke_session(
+    pool: &Pool<Postgres>,
+    user_id: Uuid,
+    session_id: Uuid,
+) -> Result<(), AppError> {
+    if !revoke_user_refresh_token_family(pool, user_id, session_id).await? {
+        return Err(AppError::NotFound("Session not found"));
+    }
+    Ok(())
+}
+
+/// Logs out every session of the user except `current_session_id`, or all of them when the
+/// access token predates session ids.
+pub async fn revoke_other_sessions(
+    pool: &Pool<Postgres>,
+    user_id: Uuid,
+    current_session_id: Option<Uuid>,
+) -> Result<(), AppError> {
+    match current_session_id {
+        Some(session_id) => revoke_other_refresh_tokens(pool, user_id, session_id).await?,
+        None => revoke_user_refresh_tokens(pool, user_id).await?,
+    };
+    Ok(())
+}
+
+/// Logs the user out everywhere: revokes every session and bumps the token version, so
+/// access tokens stop working right away too.
+pub async fn revoke_all_sessions(
+    pool: &Pool<Postgres>,
+    token_versions: &TokenVersions,
+    user_id: Uuid,
+) -> Result<(), AppError> {
+    revoke_user_refresh_tokens(pool, user_id).await?;
+    token_versions.bump(pool, user_id).await
+}
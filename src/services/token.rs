@@ -1,95 +1,403 @@
-use crate::models::token::AuthClaims;
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use crate::models::session::SessionClient;
+use crate::models::token::{AuthClaims, RefreshToken, TokenPair, TokenUse};
+use crate::repositories::refresh_token::{
+    find_refresh_token_by_hash, insert_refresh_token, replace_refresh_token,
+    revoke_refresh_token_by_hash, revoke_refresh_token_family,
+};
+use crate::repositories::session::{insert_session, touch_session};
+use crate::services::keys::KeyRing;
+use crate::services::token_version::current_token_version;
+use crate::utils::errors::AppError;
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::{decode, decode_header, encode, Header, Validation};
 // remove unused serde import (derive is on model types)
+use chrono::{Duration, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Postgres};
 use uuid::Uuid;
-use chrono::{Utc, Duration};
-use std::env;
 
 // Uses `AuthClaims` from `crate::models::token`
 
-pub fn generate_access_token(user_id: Uuid) -> Result<String, jsonwebtoken::errors::Error> {
-    let now = Utc::now();
+/// Starts a new session (refresh token family) for the user, recording the client that
+/// logged in, and returns its first token pair.
+pub async fn generate_token_pair(
+    pool: &Pool<Postgres>,
+    keyring: &KeyRing,
+    user_id: Uuid,
+    client: &SessionClient,
+) -> Result<TokenPair, AppError> {
+    let family_id = Uuid::new_v4();
+    insert_session(
+        pool,
+        family_id,
+        user_id,
+        client.user_agent.as_deref(),
+        &client.ip_address.to_string(),
+    )
+    .await?;
+    let version = current_token_version(pool, user_id).await?;
+    let access_token = generate_access_token(keyring, user_id, family_id, version)?;
+    let expires_at = (Utc::now() + keyring.config().refresh_token_expiry).timestamp();
+    let (refresh_token, claims) = sign_refresh_token(keyring, user_id, family_id, expires_at)?;
+    insert_refresh_token(
+        pool,
+        user_id,
+        family_id,
+        &hash_token_id(claims.jti),
+        claims.exp,
+    )
+    .await?;
 
-    let access_token_expiry = env::var("JWT_ACCESS_TOKEN_EXPIRY").expect("JWT_ACCESS_TOKEN_EXPIRY must be set");
+    Ok(TokenPair {
+        access_token,
+        refresh_token,
+    })
+}
 
-    // Parse expiry string to chrono::Duration
-    let duration = parse_duration(&access_token_expiry)
-        .unwrap_or_else(|_| panic!("Invalid JWT_ACCESS_TOKEN_EXPIRY format: {}", access_token_expiry));
-    let expiration = now + duration;
+/// Signs an access token for the session `family_id`, carried in the `sid` claim, at the
+/// user's token `version`, carried in the `ver` claim.
+pub fn generate_access_token(
+    keyring: &KeyRing,
+    user_id: Uuid,
+    family_id: Uuid,
+    version: i32,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    let config = keyring.config();
+    let now = Utc::now();
+    let expiration = now + config.access_token_expiry;
 
     let claims = AuthClaims {
         sub: user_id,
         exp: expiration.timestamp(),
+        iat: now.timestamp(),
+        jti: Uuid::new_v4(),
+        iss: config.issuer.clone(),
+        aud: config.audience.clone(),
+        token_use: TokenUse::Access,
+        sid: Some(family_id),
+        ver: version,
     };
 
-    let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_ref()),
-    )
+    sign_claims(keyring, &claims)
 }
 
-pub fn generate_refresh_token(user_id: Uuid) -> Result<String, jsonwebtoken::errors::Error> {
+// Signs a refresh token in the family; the caller records its hashed `jti` so it can later
+// be revoked.
+fn sign_refresh_token(
+    keyring: &KeyRing,
+    user_id: Uuid,
+    family_id: Uuid,
+    expires_at: i64,
+) -> Result<(String, AuthClaims), jsonwebtoken::errors::Error> {
+    let config = keyring.config();
     let now = Utc::now();
-    
-    let refresh_token_expiry = env::var("JWT_REFRESH_TOKEN_EXPIRY").expect("JWT_REFRESH_TOKEN_EXPIRY must be set");
-    let duration = parse_duration(&refresh_token_expiry)
-        .unwrap_or_else(|_| panic!("Invalid JWT_REFRESH_TOKEN_EXPIRY format: {}", refresh_token_expiry));
 
-    let expiration = now + duration;
+    let claims = AuthClaims {
+        sub: user_id,
+        exp: expires_at,
+        iat: now.timestamp(),
+        jti: Uuid::new_v4(),
+        iss: config.issuer.clone(),
+        aud: config.audience.clone(),
+        token_use: TokenUse::Refresh,
+        sid: Some(family_id),
+        ver: 0,
+    };
+
+    let token = sign_claims(keyring, &claims)?;
+    Ok((token, claims))
+}
+
+pub fn validate_access_token(
+    keyring: &KeyRing,
+    token: &str,
+) -> Result<AuthClaims, jsonwebtoken::errors::Error> {
+    decode_claims(keyring, token, TokenUse::Access)
+}
+
+/// Verifies the signature and checks the token against the `refresh_tokens` store,
+/// rejecting tokens that were never issued by us or have been revoked.
+pub async fn validate_refresh_token(
+    pool: &Pool<Postgres>,
+    keyring: &KeyRing,
+    token: &str,
+) -> Result<AuthClaims, AppError> {
+    let (claims, stored) = find_stored_refresh_token(pool, keyring, token).await?;
+
+    if stored.revoked_at.is_some() {
+        return Err(AppError::InvalidToken("Refresh token has been revoked"));
+    }
+    if stored.expires_at <= Utc::now().timestamp() {
+        return Err(AppError::InvalidToken("Refresh token has expired"));
+    }
+
+    Ok(claims)
+}
+
+/// Exchanges a refresh token for a new `TokenPair` in the same family and revokes the old one.
+/// The new refresh token expires when the old one did, so a session ends
+/// `refresh_token_expiry` after login however often it refreshes. Presenting a token that
+/// was already rotated or revoked is treated as theft and revokes every token in its family.
+pub async fn rotate_refresh_token(
+    pool: &Pool<Postgres>,
+    keyring: &KeyRing,
+    token: &str,
+) -> Result<TokenPair, AppError> {
+    let (claims, stored) = find_stored_refresh_token(pool, keyring, token).await?;
+
+    if stored.expires_at <= Utc::now().timestamp() {
+        return Err(AppError::InvalidToken("Refresh token has expired"));
+    }
+
+    let (refresh_token, new_claims) =
+        sign_refresh_token(keyring, claims.sub, stored.family_id, stored.expires_at)?;
+
+    // The conditional update also catches two concurrent refreshes with the same token.
+    let rotated = stored.revoked_at.is_none()
+        && replace_refresh_token(
+            pool,
+            &hash_token_id(claims.jti),
+            claims.sub,
+            stored.family_id,
+            &hash_token_id(new_claims.jti),
+            new_claims.exp,
+        )
+        .await?;
+    if !rotated {
+        revoke_refresh_token_family(pool, stored.family_id).await?;
+        return Err(AppError::InvalidToken(
+            "Refresh token has already been used",
+        ));
+    }
+
+    // Issued at the current version, so sessions that survived a bump keep working.
+    let version = current_token_version(pool, claims.sub).await?;
+    let access_token = generate_access_token(keyring, claims.sub, stored.family_id, version)?;
+    touch_session(pool, stored.family_id, Utc::now().timestamp()).await?;
+
+    Ok(TokenPair {
+        access_token,
+        refresh_token,
+    })
+}
+
+async fn find_stored_refresh_token(
+    pool: &Pool<Postgres>,
+    keyring: &KeyRing,
+    token: &str,
+) -> Result<(AuthClaims, RefreshToken), AppError> {
+    let claims = decode_claims(keyring, token, TokenUse::Refresh)?;
+
+    let stored = find_refresh_token_by_hash(pool, &hash_token_id(claims.jti))
+        .await?
+        .ok_or(AppError::InvalidToken("Refresh token not recognized"))?;
+
+    if stored.user_id != claims.sub {
+        return Err(AppError::InvalidToken("Refresh token not recognized"));
+    }
+
+    Ok((claims, stored))
+}
+
+/// Signs a single-use token of kind `token_use` for links sent to the user. The caller stores
+/// `hash_token_id(claims.jti)` and marks it used on redemption.
+pub fn generate_one_time_token(
+    keyring: &KeyRing,
+    user_id: Uuid,
+    token_use: TokenUse,
+    expiry: Duration,
+) -> Result<(String, AuthClaims), jsonwebtoken::errors::Error> {
+    let config = keyring.config();
+    let now = Utc::now();
+    let expiration = now + expiry;
 
     let claims = AuthClaims {
         sub: user_id,
         exp: expiration.timestamp(),
+        iat: now.timestamp(),
+        jti: Uuid::new_v4(),
+        iss: config.issuer.clone(),
+        aud: config.audience.clone(),
+        token_use,
+        sid: None,
+        ver: 0,
     };
 
-    let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_ref()),
-    )
+    Ok((sign_claims(keyring, &claims)?, claims))
 }
 
-pub fn validate_access_token(token: &str) -> Result<AuthClaims, jsonwebtoken::errors::Error> {
-    let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-    let token_data = decode::<AuthClaims>(
-        token,
-        &DecodingKey::from_secret(secret.as_ref()),
-        &Validation::default(),
-    )?;
+pub fn validate_one_time_token(
+    keyring: &KeyRing,
+    token: &str,
+    expected: TokenUse,
+) -> Result<AuthClaims, jsonwebtoken::errors::Error> {
+    decode_claims(keyring, token, expected)
+}
 
-    Ok(token_data.claims)
+pub async fn revoke_refresh_token(
+    pool: &Pool<Postgres>,
+    claims: &AuthClaims,
+) -> Result<(), AppError> {
+    revoke_refresh_token_by_hash(pool, &hash_token_id(claims.jti)).await?;
+    Ok(())
 }
 
-pub fn validate_refresh_token(token: &str) -> Result<AuthClaims, jsonwebtoken::errors::Error> {
-    // Currently refresh tokens share the same signing/validation rules.
-    // Split into different validation if you change headers/claims later.
-    let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-    let token_data = decode::<AuthClaims>(
-        token,
-        &DecodingKey::from_secret(secret.as_ref()),
-        &Validation::default(),
-    )?;
-
-    Ok(token_data.claims)
+fn sign_claims(
+    keyring: &KeyRing,
+    claims: &AuthClaims,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    let keys = keyring.keys();
+    let mut header = Header::new(keys.algorithm);
+    header.kid = keys.kid.clone();
+    encode(&header, claims, &keys.encoding)
 }
 
-fn parse_duration(s: &str) -> Result<Duration, &'static str> {
-    let s = s.trim().to_lowercase();
-    if let Some(stripped) = s.strip_suffix('m') {
-        let minutes: i64 = stripped.parse().map_err(|_| "Invalid minutes")?;
-        Ok(Duration::minutes(minutes))
-    } else if let Some(stripped) = s.strip_suffix('h') {
-        let hours: i64 = stripped.parse().map_err(|_| "Invalid hours")?;
-        Ok(Duration::hours(hours))
-    } else if let Some(stripped) = s.strip_suffix('d') {
-        let days: i64 = stripped.parse().map_err(|_| "Invalid days")?;
-        Ok(Duration::days(days))
-    } else {
-        Err("Unknown duration format")
+// Verifies signature, expiry, issuer and audience, then rejects tokens of the wrong kind
+// so an access token cannot be used as a refresh token and vice versa.
+fn decode_claims(
+    keyring: &KeyRing,
+    token: &str,
+    expected: TokenUse,
+) -> Result<AuthClaims, jsonwebtoken::errors::Error> {
+    let config = keyring.config();
+    let keys = keyring.keys();
+
+    let mut validation = Validation::new(keys.algorithm);
+    validation.set_issuer(&[&config.issuer]);
+    validation.set_audience(&[&config.audience]);
+    validation.set_required_spec_claims(&["exp", "iat", "iss", "aud", "sub"]);
+
+    // Pick the verification key by `kid` so tokens signed before a key rotation stay valid.
+    let header = decode_header(token)?;
+    let decoding_key = keys
+        .decoding_key(header.kid.as_deref())
+        .ok_or(ErrorKind::InvalidToken)?;
+    let claims = decode::<AuthClaims>(token, decoding_key, &validation)?.claims;
+
+    if claims.token_use != expected {
+        return Err(ErrorKind::InvalidToken.into());
     }
+
+    Ok(claims)
 }
 
-// Uses `TokenPair` from `crate::models::token`
\ No newline at end of file
+// Only the SHA-256 of the token id is persisted.
+pub fn hash_token_id(jti: Uuid) -> String {
+    hex::encode(Sha256::digest(jti.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::JwtConfig;
+    use jsonwebtoken::Algorithm;
+    use serde_json::json;
+
+    fn keyring() -> KeyRing {
+        KeyRing::load(&JwtConfig {
+            algorithm: Algorithm::HS256,
+            secret: Some("test-secret-with-enough-entropy-0123456789".to_string()),
+            previous_secrets: Vec::new(),
+            private_key_path: None,
+            public_key_path: None,
+            keys_dir: None,
+            key_id: None,
+            access_token_expiry: Duration::minutes(15),
+            refresh_token_expiry: Duration::days(7),
+            issuer: "test-issuer".to_string(),
+            audience: "test-audience".to_string(),
+        })
+        .unwrap()
+    }
+
+    fn access_claims(keyring: &KeyRing) -> AuthClaims {
+        let token = generate_access_token(keyring, Uuid::new_v4(), Uuid::new_v4(), 3).unwrap();
+        validate_access_token(keyring, &token).unwrap()
+    }
+
+    #[test]
+    fn accepts_tokens_of_the_expected_kind() {
+        let keyring = keyring();
+        let claims = access_claims(&keyring);
+        assert_eq!(claims.token_use, TokenUse::Access);
+        assert_eq!(claims.ver, 3);
+
+        let expires_at = (Utc::now() + Duration::days(1)).timestamp();
+        let (token, _) =
+            sign_refresh_token(&keyring, claims.sub, Uuid::new_v4(), expires_at).unwrap();
+        let refresh = decode_claims(&keyring, &token, TokenUse::Refresh).unwrap();
+        assert_eq!(refresh.sub, claims.sub);
+        assert_eq!(refresh.exp, expires_at);
+    }
+
+    #[test]
+    fn rejects_a_refresh_token_as_an_access_token() {
+        let keyring = keyring();
+        let expires_at = (Utc::now() + Duration::days(1)).timestamp();
+        let (token, _) =
+            sign_refresh_token(&keyring, Uuid::new_v4(), Uuid::new_v4(), expires_at).unwrap();
+        assert!(validate_access_token(&keyring, &token).is_err());
+    }
+
+    #[test]
+    fn rejects_an_access_token_as_a_refresh_or_one_time_token() {
+        let keyring = keyring();
+        let token = generate_access_token(&keyring, Uuid::new_v4(), Uuid::new_v4(), 0).unwrap();
+        assert!(decode_claims(&keyring, &token, TokenUse::Refresh).is_err());
+        assert!(validate_one_time_token(&keyring, &token, TokenUse::PasswordReset).is_err());
+    }
+
+    #[test]
+    fn rejects_a_wrong_issuer_or_audience() {
+        let keyring = keyring();
+        let claims = access_claims(&keyring);
+
+        let wrong_issuer = AuthClaims {
+            iss: "someone-else".to_string(),
+            ..access_claims(&keyring)
+        };
+        let token = sign_claims(&keyring, &wrong_issuer).unwrap();
+        assert!(validate_access_token(&keyring, &token).is_err());
+
+        let wrong_audience = AuthClaims {
+            aud: "another-app".to_string(),
+            ..claims
+        };
+        let token = sign_claims(&keyring, &wrong_audience).unwrap();
+        assert!(validate_access_token(&keyring, &token).is_err());
+    }
+
+    #[test]
+    fn rejects_a_token_missing_required_claims() {
+        let keyring = keyring();
+        let claims = serde_json::to_value(access_claims(&keyring)).unwrap();
+        let keys = keyring.keys();
+        let mut header = Header::new(keys.algorithm);
+        header.kid = keys.kid.clone();
+
+        for missing in ["exp", "iat", "iss", "aud", "sub"] {
+            let mut partial = claims.clone();
+            partial.as_object_mut().unwrap().remove(missing);
+            let token = encode(&header, &partial, &keys.encoding).unwrap();
+            assert!(
+                validate_access_token(&keyring, &token).is_err(),
+                "accepted a token without {}",
+                missing
+            );
+        }
+
+        let token = encode(&header, &json!({}), &keys.encoding).unwrap();
+        assert!(validate_access_token(&keyring, &token).is_err());
+    }
+
+    #[test]
+    fn rejects_a_token_signed_with_another_secret() {
+        let token = generate_access_token(&keyring(), Uuid::new_v4(), Uuid::new_v4(), 0).unwrap();
+        let other = KeyRing::load(&JwtConfig {
+            secret: Some("a-different-secret-0123456789abcdef".to_string()),
+            ..keyring().config().clone()
+        })
+        .unwrap();
+        assert!(validate_access_token(&other, &token).is_err());
+    }
+}
@@ -0,0 +1,98 @@
+use crate::repositories::user::{bump_user_token_version, find_user_token_version};
+use crate::utils::errors::AppError;
+use chrono::Utc;
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+// How long a user's token version is trusted before the database is asked again, which is
+// also how long another process's bump (e.g. `log-out-user`) may take to be noticed here.
+const CACHE_TTL_SECS: i64 = 30;
+
+const REVOKED: AppError = AppError::InvalidToken("Token has been revoked");
+
+/// Per-user token versions, the global kill switch for access tokens.
+///
+/// Every access token carries the user's `token_version` from when it was issued. Bumping
+/// the version rejects all of them at once; refresh tokens are unaffected, so sessions that
+/// were not revoked along with the bump simply refresh into the new version. Versions are
+/// cached in memory so checking a token does not cost a query on every request.
+#[derive(Default)]
+pub struct TokenVersions {
+    // user_id -> (token_version, cached_until)
+    cache: Mutex<HashMap<Uuid, (i32, i64)>>,
+}
+
+impl TokenVersions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rejects an access token issued with a version older than the user's current one.
+    pub async fn check(
+        &self,
+        pool: &Pool<Postgres>,
+        user_id: Uuid,
+        version: i32,
+    ) -> Result<(), AppError> {
+        let now = Utc::now().timestamp();
+        let current = match self.cached(user_id, now) {
+            Some(current) => current,
+            None => {
+                let current = find_user_token_version(pool, user_id)
+                    .await?
+                    .ok_or(REVOKED)?;
+                self.remember(user_id, current, now);
+                current
+            }
+        };
+
+        // A newer version than cached means another process bumped it; the token is fine.
+        if version < current {
+            return Err(REVOKED);
+        }
+        Ok(())
+    }
+
+    /// Invalidates every access token issued to the user so far. Takes effect at once in
+    /// this process and within `CACHE_TTL_SECS` in others.
+    pub async fn bump(&self, pool: &Pool<Postgres>, user_id: Uuid) -> Result<(), AppError> {
+        let version = bump_user_token_version(pool, user_id)
+            .await?
+            .ok_or(AppError::NotFound("User not found"))?;
+        self.remember(user_id, version, Utc::now().timestamp());
+        Ok(())
+    }
+
+    fn cached(&self, user_id: Uuid, now: i64) -> Option<i32> {
+        let cache = self
+            .cache
+            .lock()
+            .expect("token version cache lock poisoned");
+        cache
+            .get(&user_id)
+            .filter(|(_, cached_until)| *cached_until > now)
+            .map(|(version, _)| *version)
+    }
+
+    fn remember(&self, user_id: Uuid, version: i32, now: i64) {
+        let mut cache = self
+            .cache
+            .lock()
+            .expect("token version cache lock poisoned");
+        cache.retain(|_, (_, cached_until)| *cached_until > now);
+        // Versions only go up; a lookup that raced with a bump must not cache the old one.
+        let version = cache
+            .get(&user_id)
+            .map_or(version, |(cached, _)| version.max(*cached));
+        cache.insert(user_id, (version, now + CACHE_TTL_SECS));
+    }
+}
+
+/// The version to embed in a new access token for the user.
+pub async fn current_token_version(pool: &Pool<Postgres>, user_id: Uuid) -> Result<i32, AppError> {
+    find_user_token_version(pool, user_id)
+        .await?
+        .ok_or(AppError::NotFound("User not found"))
+}
@@ -1,16 +1,25 @@
-use crate::models::user::UserProfile;
-use crate::repositories::user::find_user_by_id;
+use crate::dtos::user::ChangePasswordRequest;
+use crate::models::user::{User, UserProfile};
+use crate::repositories::password_reset::invalidate_password_reset_tokens;
+use crate::repositories::user::{find_user_by_id, update_user_password_hash};
+use crate::services::login_throttle::LoginThrottle;
+use crate::services::password_hash::{PasswordHasher, Verification};
+use crate::services::password_policy::PasswordPolicy;
+use crate::services::session;
+use crate::services::token_version::TokenVersions;
+use crate::utils::errors::AppError;
+use chrono::Utc;
 use sqlx::{Pool, Postgres};
+use std::net::IpAddr;
 use uuid::Uuid;
 
 pub async fn service_get_profile(
     pool: &Pool<Postgres>,
     user_id: Uuid,
-) -> Result<UserProfile, String> {
+) -> Result<UserProfile, AppError> {
     let user = find_user_by_id(pool, user_id)
-        .await
-        .map_err(|e| e.to_string())?
-        .ok_or_else(|| "User not found".to_string())?;
+        .await?
+        .ok_or(AppError::NotFound("User not found"))?;
 
     Ok(UserProfile {
         id: user.id,
@@ -18,5 +27,71 @@ pub async fn service_get_profile(
         email: user.email,
         created_at: user.created_at,
         updated_at: user.updated_at,
+        email_verified_at: user.email_verified_at,
     })
 }
+
+/// Checks the logged-in user's current password, sent as `field`, and returns the user. Wrong
+/// passwords count as failed logins for the user's email and the client's IP, so a stolen
+/// access token cannot be used to guess the password behind it. A right one does not clear
+/// the failures; only a completed login does, so it cannot reset the guessing of a code
+/// checked after it.
+pub async fn verify_current_password(
+    pool: &Pool<Postgres>,
+    password_hasher: &PasswordHasher,
+    login_throttle: &LoginThrottle,
+    user_id: Uuid,
+    field: &'static str,
+    current_password: &str,
+    client_ip: IpAddr,
+) -> Result<User, AppError> {
+    let user = find_user_by_id(pool, user_id)
+        .await?
+        .ok_or(AppError::NotFound("User not found"))?;
+    login_throttle.check(pool, &user.email, client_ip).await?;
+
+    if let Verification::Mismatch = password_hasher.verify(current_password, &user.password_hash)? {
+        login_throttle
+            .record_failure(pool, &user.email, client_ip)
+            .await?;
+        return Err(AppError::invalid_field(field, "Is incorrect"));
+    }
+    Ok(user)
+}
+
+/// Replaces the password of a user whose current one was checked by `verify_current_password`.
+/// Outstanding reset links are retired and the token version is bumped, so every access token
+/// must be refreshed; with `revoke_other_sessions`, every session except `session_id` is
+/// logged out (all of them when the access token predates session ids).
+pub async fn change_password(
+    pool: &Pool<Postgres>,
+    token_versions: &TokenVersions,
+    password_policy: &PasswordPolicy,
+    password_hasher: &PasswordHasher,
+    user: &User,
+    session_id: Option<Uuid>,
+    request: &ChangePasswordRequest,
+) -> Result<(), AppError> {
+    let ChangePasswordRequest {
+        current_password,
+        password,
+        revoke_other_sessions,
+    } = request;
+
+    if password == current_password {
+        return Err(AppError::invalid_field(
+            "password",
+            "Must differ from the current password",
+        ));
+    }
+    password_policy.validate(password, &[&user.username, &user.email])?;
+
+    let password_hash = password_hasher.hash(password)?;
+    update_user_password_hash(pool, user.id, &password_hash).await?;
+    invalidate_password_reset_tokens(pool, user.id, Utc::now().timestamp()).await?;
+
+    if *revoke_other_sessions {
+        session::revoke_other_sessions(pool, user.id, session_id).await?;
+    }
+    token_versions.bump(pool, user.id).await
+}
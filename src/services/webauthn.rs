@@ -0,0 +1,813 @@
+use crate::config::WebauthnConfig;
+use crate::dtos::webauthn::{
+    AuthenticationCredential, AuthenticatorSelection, CredentialDescriptor, CredentialParameters,
+    PasskeyCreationOptions, PasskeyRequestOptions, PasskeyUser, RegistrationCredential,
+    RelyingParty,
+};
+use crate::models::webauthn::{Ceremony, WebauthnCredential};
+use crate::repositories::user::find_user_by_id;
+use crate::repositories::webauthn::{
+    delete_expired_webauthn_challenges, delete_webauthn_credential, find_webauthn_credential,
+    insert_webauthn_challenge, insert_webauthn_credential, list_webauthn_credentials,
+    record_webauthn_credential_use, use_webauthn_challenge,
+};
+use crate::utils::errors::AppError;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::Utc;
+use ciborium::Value;
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::signature;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Postgres};
+use std::sync::atomic::{AtomicI64, Ordering};
+use uuid::Uuid;
+
+// COSE algorithm identifiers we accept, in order of preference.
+const COSE_ES256: i64 = -7;
+const COSE_EDDSA: i64 = -8;
+const COSE_RS256: i64 = -257;
+
+// Authenticator data flags.
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL: u8 = 0x40;
+
+// Challenges that expired unanswered are swept this often, when the next ceremony starts.
+const SWEEP_INTERVAL_SECS: i64 = 10 * 60;
+
+const INVALID_CHALLENGE: AppError = AppError::InvalidOneTimeToken(
+    "Passkey challenge is invalid, has expired or has already been used",
+);
+
+/// Passkey (WebAuthn) registration and passwordless login.
+///
+/// Both ceremonies start with a random challenge recorded by hash in `webauthn_challenges`
+/// and finish by checking the browser's response against it, the configured origins and the
+/// relying party id. Credentials must be discoverable and user-verified, so a passkey alone
+/// is enough to log in. Attestation is not requested (`"none"`), so any authenticator is
+/// accepted and its attestation statement, if sent, is ignored.
+pub struct Passkeys {
+    config: WebauthnConfig,
+    rp_id_hash: [u8; 32],
+    rng: SystemRandom,
+    last_sweep: AtomicI64,
+}
+
+impl Passkeys {
+    pub fn new(config: &WebauthnConfig) -> Self {
+        Self {
+            config: config.clone(),
+            rp_id_hash: Sha256::digest(config.rp_id.as_bytes()).into(),
+            rng: SystemRandom::new(),
+            last_sweep: AtomicI64::new(0),
+        }
+    }
+
+    /// Options for `navigator.credentials.create()` to add a passkey to the user's account.
+    pub async fn start_registration(
+        &self,
+        pool: &Pool<Postgres>,
+        user_id: Uuid,
+    ) -> Result<PasskeyCreationOptions, AppError> {
+        let user = find_user_by_id(pool, user_id)
+            .await?
+            .ok_or(AppError::NotFound("User not found"))?;
+        let existing = list_webauthn_credentials(pool, user.id).await?;
+        let challenge = self
+            .new_challenge(pool, Ceremony::Registration, Some(user.id))
+            .await?;
+
+        Ok(PasskeyCreationOptions {
+            rp: RelyingParty {
+                id: self.config.rp_id.clone(),
+                name: self.config.rp_name.clone(),
+            },
+            user: PasskeyUser {
+                id: URL_SAFE_NO_PAD.encode(user.id.as_bytes()),
+                name: user.email,
+                display_name: user.username,
+            },
+            challenge,
+            pub_key_cred_params: [COSE_ES256, COSE_EDDSA, COSE_RS256]
+                .into_iter()
+                .map(|alg| CredentialParameters {
+                    credential_type: "public-key",
+                    alg,
+                })
+                .collect(),
+            timeout: self.timeout_ms(),
+            exclude_credentials: existing
+                .into_iter()
+                .map(|credential| CredentialDescriptor {
+                    credential_type: "public-key",
+                    id: URL_SAFE_NO_PAD.encode(&credential.credential_id),
+                    transports: credential.transports,
+                })
+                .collect(),
+            authenticator_selection: AuthenticatorSelection {
+                resident_key: "required",
+                require_resident_key: true,
+                user_verification: "required",
+            },
+            attestation: "none",
+        })
+    }
+
+    /// Verifies the new credential against the user's registration challenge and stores it.
+    pub async fn finish_registration(
+        &self,
+        pool: &Pool<Postgres>,
+        user_id: Uuid,
+        credential: &RegistrationCredential,
+        name: Option<&str>,
+    ) -> Result<WebauthnCredential, AppError> {
+        let (challenge, attested, sign_count) = self.verify_registration(credential)?;
+        self.redeem_challenge(pool, &challenge, Ceremony::Registration)
+            .await?
+            .filter(|owner| *owner == user_id)
+            .ok_or(INVALID_CHALLENGE)?;
+
+        insert_webauthn_credential(
+            pool,
+            user_id,
+            &attested.credential_id,
+            &attested.public_key,
+            i64::from(sign_count),
+            &credential.response.transports,
+            name.unwrap_or("Passkey"),
+        )
+        .await?
+        .ok_or(AppError::InvalidPasskey(
+            "This passkey is already registered",
+        ))
+    }
+
+    // Checks a registration response without touching the database, returning the challenge
+    // it answers, the new credential and its initial signature counter.
+    fn verify_registration(
+        &self,
+        credential: &RegistrationCredential,
+    ) -> Result<(Vec<u8>, AttestedCredential, u32), AppError> {
+        if credential.credential_type != "public-key" {
+            return Err(AppError::InvalidPasskey(
+                "Credential type must be public-key",
+            ));
+        }
+        let client_data = decode(&credential.response.client_data_json)?;
+        let challenge = self.verify_client_data(&client_data, "webauthn.create")?;
+
+        let attestation: Value =
+            ciborium::from_reader(decode(&credential.response.attestation_object)?.as_slice())
+                .map_err(|_| AppError::InvalidPasskey("Malformed attestation object"))?;
+        let auth_data = map_get(&attestation, |key| key.as_text() == Some("authData"))
+            .and_then(Value::as_bytes)
+            .ok_or(AppError::InvalidPasskey(
+                "Attestation object has no authenticator data",
+            ))?;
+        let auth_data = self.authenticator_data(auth_data)?;
+        let attested = auth_data
+            .attested_credential
+            .ok_or(AppError::InvalidPasskey(
+                "Authenticator data has no credential",
+            ))?;
+        if decode(&credential.raw_id)? != attested.credential_id {
+            return Err(AppError::InvalidPasskey(
+                "Credential id does not match the authenticator data",
+            ));
+        }
+        // Rejects unsupported algorithms now rather than at the first login.
+        PublicKey::from_cose(&attested.public_key)?;
+        Ok((challenge, attested, auth_data.sign_count))
+    }
+
+    /// Options for `navigator.credentials.get()`. No user is named: the authenticator offers
+    /// whichever discoverable passkeys it holds for the relying party.
+    pub async fn start_login(
+        &self,
+        pool: &Pool<Postgres>,
+    ) -> Result<PasskeyRequestOptions, AppError> {
+        let challenge = self
+            .new_challenge(pool, Ceremony::Authentication, None)
+            .await?;
+
+        Ok(PasskeyRequestOptions {
+            challenge,
+            timeout: self.timeout_ms(),
+            rp_id: self.config.rp_id.clone(),
+            allow_credentials: Vec::new(),
+            user_verification: "required",
+        })
+    }
+
+    /// Verifies an assertion against a login challenge, returning the user to issue tokens for.
+    pub async fn finish_login(
+        &self,
+        pool: &Pool<Postgres>,
+        credential: &AuthenticationCredential,
+    ) -> Result<Uuid, AppError> {
+        if credential.credential_type != "public-key" {
+            return Err(AppError::InvalidPasskey(
+                "Credential type must be public-key",
+            ));
+        }
+        let client_data = decode(&credential.response.client_data_json)?;
+        let challenge = self.verify_client_data(&client_data, "webauthn.get")?;
+        self.redeem_challenge(pool, &challenge, Ceremony::Authentication)
+            .await?;
+
+        let stored = find_webauthn_credential(pool, &decode(&credential.raw_id)?)
+            .await?
+            .ok_or(AppError::InvalidPasskey("Unknown passkey"))?;
+        if let Some(user_handle) = &credential.response.user_handle {
+            if decode(user_handle)? != stored.user_id.as_bytes() {
+                return Err(AppError::InvalidPasskey(
+                    "User handle does not match the passkey",
+                ));
+            }
+        }
+
+        let sign_count = self.verify_assertion(
+            credential,
+            &client_data,
+            &stored.public_key,
+            stored.sign_count,
+        )?;
+        let now = Utc::now().timestamp();
+        if !record_webauthn_credential_use(pool, stored.id, stored.sign_count, sign_count, now)
+            .await?
+        {
+            return Err(AppError::InvalidPasskey(
+                "Passkey was used concurrently, try again",
+            ));
+        }
+        Ok(stored.user_id)
+    }
+
+    pub async fn list(
+        &self,
+        pool: &Pool<Postgres>,
+        user_id: Uuid,
+    ) -> Result<Vec<WebauthnCredential>, AppError> {
+        Ok(list_webauthn_credentials(pool, user_id).await?)
+    }
+
+    pub async fn delete(
+        &self,
+        pool: &Pool<Postgres>,
+        user_id: Uuid,
+        id: Uuid,
+    ) -> Result<(), AppError> {
+        if !delete_webauthn_credential(pool, user_id, id).await? {
+            return Err(AppError::NotFound("Passkey not found"));
+        }
+        Ok(())
+    }
+
+    async fn new_challenge(
+        &self,
+        pool: &Pool<Postgres>,
+        ceremony: Ceremony,
+        user_id: Option<Uuid>,
+    ) -> Result<String, AppError> {
+        let mut challenge = [0u8; 32];
+        self.rng
+            .fill(&mut challenge)
+            .map_err(|_| AppError::Encryption("failed to generate a WebAuthn challenge"))?;
+        let now = Utc::now();
+        let last_sweep = self.last_sweep.load(Ordering::Relaxed);
+        if now.timestamp() - last_sweep >= SWEEP_INTERVAL_SECS
+            && self
+                .last_sweep
+                .compare_exchange(
+                    last_sweep,
+                    now.timestamp(),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+        {
+            delete_expired_webauthn_challenges(pool, now.timestamp()).await?;
+        }
+
+        let expires_at = (now + self.config.challenge_expiry).timestamp();
+        insert_webauthn_challenge(
+            pool,
+            &hash_challenge(&challenge),
+            ceremony,
+            user_id,
+            expires_at,
+        )
+        .await?;
+        Ok(URL_SAFE_NO_PAD.encode(challenge))
+    }
+
+    // Checks the authenticator data and signature of an assertion made with the stored
+    // credential, returning the new signature counter.
+    fn verify_assertion(
+        &self,
+        credential: &AuthenticationCredential,
+        client_data: &[u8],
+        public_key: &[u8],
+        stored_sign_count: i64,
+    ) -> Result<i64, AppError> {
+        let raw_auth_data = decode(&credential.response.authenticator_data)?;
+        let auth_data = self.authenticator_data(&raw_auth_data)?;
+        let signed = [raw_auth_data.as_slice(), &Sha256::digest(client_data)].concat();
+        PublicKey::from_cose(public_key)?
+            .verify(&signed, &decode(&credential.response.signature)?)?;
+
+        // Authenticators that keep a counter must increase it; synced passkeys report 0.
+        let sign_count = i64::from(auth_data.sign_count);
+        if (sign_count != 0 || stored_sign_count != 0) && sign_count <= stored_sign_count {
+            return Err(AppError::InvalidPasskey(
+                "Signature counter did not increase; the authenticator may have been cloned",
+            ));
+        }
+        Ok(sign_count)
+    }
+
+    // Checks `clientDataJSON` and returns the challenge it answers.
+    fn verify_client_data(
+        &self,
+        client_data: &[u8],
+        expected_type: &str,
+    ) -> Result<Vec<u8>, AppError> {
+        let client_data: ClientData = serde_json::from_slice(client_data)
+            .map_err(|_| AppError::InvalidPasskey("Malformed client data"))?;
+        if client_data.ceremony_type != expected_type {
+            return Err(AppError::InvalidPasskey(
+                "Client data is for a different ceremony",
+            ));
+        }
+        if client_data.cross_origin || !self.config.origins.contains(&client_data.origin) {
+            return Err(AppError::InvalidPasskey("Origin is not allowed"));
+        }
+
+        decode(&client_data.challenge)
+    }
+
+    // Spends the challenge, returning its user (none for login challenges).
+    async fn redeem_challenge(
+        &self,
+        pool: &Pool<Postgres>,
+        challenge: &[u8],
+        ceremony: Ceremony,
+    ) -> Result<Option<Uuid>, AppError> {
+        use_webauthn_challenge(
+            pool,
+            &hash_challenge(challenge),
+            ceremony,
+            Utc::now().timestamp(),
+        )
+        .await?
+        .ok_or(INVALID_CHALLENGE)
+    }
+
+    fn authenticator_data(&self, data: &[u8]) -> Result<AuthenticatorData, AppError> {
+        let auth_data = AuthenticatorData::parse(data)?;
+        if auth_data.rp_id_hash != self.rp_id_hash {
+            return Err(AppError::InvalidPasskey(
+                "Passkey belongs to a different relying party",
+            ));
+        }
+        if auth_data.flags & FLAG_USER_PRESENT == 0 || auth_data.flags & FLAG_USER_VERIFIED == 0 {
+            return Err(AppError::InvalidPasskey(
+                "User presence and verification are required",
+            ));
+        }
+        Ok(auth_data)
+    }
+
+    fn timeout_ms(&self) -> u64 {
+        self.config.challenge_expiry.num_milliseconds().max(0) as u64
+    }
+}
+
+#[derive(Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    ceremony_type: String,
+    challenge: String,
+    origin: String,
+    #[serde(rename = "crossOrigin", default)]
+    cross_origin: bool,
+}
+
+struct AuthenticatorData {
+    rp_id_hash: [u8; 32],
+    flags: u8,
+    sign_count: u32,
+    attested_credential: Option<AttestedCredential>,
+}
+
+struct AttestedCredential {
+    credential_id: Vec<u8>,
+    // The COSE_Key, exactly as the authenticator encoded it
+    public_key: Vec<u8>,
+}
+
+impl AuthenticatorData {
+    // rpIdHash (32) | flags (1) | signCount (4)
+    //   | [aaguid (16) | idLength (2) | id | COSE_Key] | [extensions]
+    fn parse(data: &[u8]) -> Result<Self, AppError> {
+        const MALFORMED: AppError = AppError::InvalidPasskey("Malformed authenticator data");
+        if data.len() < 37 {
+            return Err(MALFORMED);
+        }
+        let flags = data[32];
+        let mut auth_data = AuthenticatorData {
+            rp_id_hash: data[..32].try_into().map_err(|_| MALFORMED)?,
+            flags,
+            sign_count: u32::from_be_bytes(data[33..37].try_into().map_err(|_| MALFORMED)?),
+            attested_credential: None,
+        };
+
+        if flags & FLAG_ATTESTED_CREDENTIAL != 0 {
+            let rest = data
+                .get(37..)
+                .filter(|rest| rest.len() >= 18)
+                .ok_or(MALFORMED)?;
+            let id_length = u16::from_be_bytes([rest[16], rest[17]]) as usize;
+            let credential_id = rest.get(18..18 + id_length).ok_or(MALFORMED)?.to_vec();
+            let key_bytes = &rest[18 + id_length..];
+            // The key's length is only known by decoding it; extensions may follow.
+            let mut reader = key_bytes;
+            let _: Value = ciborium::from_reader(&mut reader).map_err(|_| MALFORMED)?;
+            let public_key = key_bytes[..key_bytes.len() - reader.len()].to_vec();
+            auth_data.attested_credential = Some(AttestedCredential {
+                credential_id,
+                public_key,
+            });
+        }
+        Ok(auth_data)
+    }
+}
+
+enum PublicKey {
+    // SEC1 uncompressed point: 0x04 | x | y
+    Es256(Vec<u8>),
+    Ed25519(Vec<u8>),
+    Rs256 { n: Vec<u8>, e: Vec<u8> },
+}
+
+impl PublicKey {
+    // RFC 9053: kty (1), alg (3), crv (-1), x (-2), y (-3); for RSA n (-1), e (-2).
+    fn from_cose(bytes: &[u8]) -> Result<Self, AppError> {
+        const MALFORMED: AppError = AppError::InvalidPasskey("Malformed or unsupported public key");
+        let key: Value = ciborium::from_reader(bytes).map_err(|_| MALFORMED)?;
+        let int = |label: i64| cose_param(&key, label).and_then(cose_int);
+        let bytes = |label: i64| cose_param(&key, label).and_then(Value::as_bytes).cloned();
+
+        match (int(1), int(3)) {
+            (Some(2), Some(COSE_ES256)) if int(-1) == Some(1) => {
+                let (x, y) = bytes(-2).zip(bytes(-3)).ok_or(MALFORMED)?;
+                if x.len() != 32 || y.len() != 32 {
+                    return Err(MALFORMED);
+                }
+                Ok(PublicKey::Es256([&[0x04][..], &x, &y].concat()))
+            }
+            (Some(1), Some(COSE_EDDSA)) if int(-1) == Some(6) => {
+                let x = bytes(-2).filter(|x| x.len() == 32).ok_or(MALFORMED)?;
+                Ok(PublicKey::Ed25519(x))
+            }
+            (Some(3), Some(COSE_RS256)) => {
+                let (n, e) = bytes(-1).zip(bytes(-2)).ok_or(MALFORMED)?;
+                Ok(PublicKey::Rs256 { n, e })
+            }
+            _ => Err(MALFORMED),
+        }
+    }
+
+    fn verify(&self, message: &[u8], sig: &[u8]) -> Result<(), AppError> {
+        let result = match self {
+            PublicKey::Es256(point) => {
+                signature::UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_ASN1, point)
+                    .verify(message, sig)
+            }
+            PublicKey::Ed25519(key) => {
+                signature::UnparsedPublicKey::new(&signature::ED25519, key).verify(message, sig)
+            }
+            PublicKey::Rs256 { n, e } => signature::RsaPublicKeyComponents { n, e }.verify(
+                &signature::RSA_PKCS1_2048_8192_SHA256,
+                message,
+                sig,
+            ),
+        };
+        result.map_err(|_| AppError::InvalidPasskey("Signature is invalid"))
+    }
+}
+
+fn map_get(map: &Value, matches: impl Fn(&Value) -> bool) -> Option<&Value> {
+    map.as_map()?
+        .iter()
+        .find(|(key, _)| matches(key))
+        .map(|(_, value)| value)
+}
+
+fn cose_param(key: &Value, label: i64) -> Option<&Value> {
+    map_get(key, |k| cose_int(k) == Some(label))
+}
+
+fn cose_int(value: &Value) -> Option<i64> {
+    value.as_integer().and_then(|i| i64::try_from(i).ok())
+}
+
+// Accepts base64url with or without padding, as browsers and libraries differ.
+fn decode(value: &str) -> Result<Vec<u8>, AppError> {
+    URL_SAFE_NO_PAD
+        .decode(value.trim_end_matches('='))
+        .map_err(|_| AppError::InvalidPasskey("Malformed base64url data"))
+}
+
+fn hash_challenge(challenge: &[u8]) -> String {
+    hex::encode(Sha256::digest(challenge))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING};
+    use serde_json::json;
+
+    const RP_ID: &str = "app.example.com";
+    const ORIGIN: &str = "https://app.example.com";
+    const CHALLENGE: [u8; 32] = [42; 32];
+
+    fn passkeys() -> Passkeys {
+        Passkeys::new(&WebauthnConfig {
+            rp_id: RP_ID.to_string(),
+            rp_name: "Example".to_string(),
+            origins: vec![ORIGIN.to_string()],
+            challenge_expiry: chrono::Duration::minutes(5),
+        })
+    }
+
+    fn b64(bytes: &[u8]) -> String {
+        URL_SAFE_NO_PAD.encode(bytes)
+    }
+
+    fn cbor(value: &Value) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(value, &mut bytes).unwrap();
+        bytes
+    }
+
+    fn client_data(ceremony_type: &str, origin: &str) -> Vec<u8> {
+        json!({ "type": ceremony_type, "challenge": b64(&CHALLENGE), "origin": origin })
+            .to_string()
+            .into_bytes()
+    }
+
+    // A software authenticator holding one ES256 credential.
+    struct Authenticator {
+        key: EcdsaKeyPair,
+        credential_id: Vec<u8>,
+        rp_id: &'static str,
+        origin: &'static str,
+        flags: u8,
+        sign_count: u32,
+    }
+
+    impl Authenticator {
+        fn new() -> Self {
+            let rng = SystemRandom::new();
+            let pkcs8 =
+                EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng).unwrap();
+            Self {
+                key: EcdsaKeyPair::from_pkcs8(
+                    &ECDSA_P256_SHA256_ASN1_SIGNING,
+                    pkcs8.as_ref(),
+                    &rng,
+                )
+                .unwrap(),
+                credential_id: vec![7; 16],
+                rp_id: RP_ID,
+                origin: ORIGIN,
+                flags: FLAG_USER_PRESENT | FLAG_USER_VERIFIED,
+                sign_count: 1,
+            }
+        }
+
+        fn cose_key(&self) -> Vec<u8> {
+            // Uncompressed point: 0x04 | x | y
+            let point = self.key.public_key().as_ref();
+            cbor(&Value::Map(vec![
+                (1.into(), 2.into()),
+                (3.into(), COSE_ES256.into()),
+                ((-1).into(), 1.into()),
+                ((-2).into(), Value::Bytes(point[1..33].to_vec())),
+                ((-3).into(), Value::Bytes(point[33..].to_vec())),
+            ]))
+        }
+
+        fn authenticator_data(&self, attested: bool) -> Vec<u8> {
+            let mut data = Sha256::digest(self.rp_id.as_bytes()).to_vec();
+            data.push(if attested {
+                self.flags | FLAG_ATTESTED_CREDENTIAL
+            } else {
+                self.flags
+            });
+            data.extend(self.sign_count.to_be_bytes());
+            if attested {
+                data.extend([0; 16]);
+                data.extend((self.credential_id.len() as u16).to_be_bytes());
+                data.extend(&self.credential_id);
+                data.extend(self.cose_key());
+            }
+            data
+        }
+
+        fn register(&self) -> RegistrationCredential {
+            let attestation = Value::Map(vec![
+                ("fmt".into(), "none".into()),
+                ("attStmt".into(), Value::Map(Vec::new())),
+                (
+                    "authData".into(),
+                    Value::Bytes(self.authenticator_data(true)),
+                ),
+            ]);
+            serde_json::from_value(json!({
+                "rawId": b64(&self.credential_id),
+                "type": "public-key",
+                "response": {
+                    "clientDataJSON": b64(&client_data("webauthn.create", self.origin)),
+                    "attestationObject": b64(&cbor(&attestation)),
+                },
+            }))
+            .unwrap()
+        }
+
+        fn assert(&self) -> AuthenticationCredential {
+            self.assert_with(&self.authenticator_data(false))
+        }
+
+        fn assert_with(&self, authenticator_data: &[u8]) -> AuthenticationCredential {
+            let client_data = client_data("webauthn.get", self.origin);
+            let signed = [authenticator_data, &Sha256::digest(&client_data)].concat();
+            let signature = self.key.sign(&SystemRandom::new(), &signed).unwrap();
+            serde_json::from_value(json!({
+                "rawId": b64(&self.credential_id),
+                "type": "public-key",
+                "response": {
+                    "clientDataJSON": b64(&client_data),
+                    "authenticatorData": b64(authenticator_data),
+                    "signature": b64(signature.as_ref()),
+                },
+            }))
+            .unwrap()
+        }
+    }
+
+    // The database-free part of `finish_login`.
+    fn login(
+        credential: &AuthenticationCredential,
+        public_key: &[u8],
+        stored_sign_count: i64,
+    ) -> Result<i64, AppError> {
+        let passkeys = passkeys();
+        let client_data = decode(&credential.response.client_data_json)?;
+        assert_eq!(
+            passkeys.verify_client_data(&client_data, "webauthn.get")?,
+            CHALLENGE
+        );
+        passkeys.verify_assertion(credential, &client_data, public_key, stored_sign_count)
+    }
+
+    fn rejection<T>(result: Result<T, AppError>) -> &'static str {
+        match result {
+            Err(AppError::InvalidPasskey(message)) => message,
+            Err(e) => panic!("expected InvalidPasskey, got {:?}", e),
+            Ok(_) => panic!("expected InvalidPasskey, got Ok"),
+        }
+    }
+
+    #[test]
+    fn registers_and_logs_in_with_es256_key() {
+        let mut authenticator = Authenticator::new();
+        let (challenge, attested, sign_count) = passkeys()
+            .verify_registration(&authenticator.register())
+            .unwrap();
+        assert_eq!(challenge, CHALLENGE);
+        assert_eq!(attested.credential_id, authenticator.credential_id);
+        assert_eq!(sign_count, 1);
+
+        authenticator.sign_count = 2;
+        let new_count = login(&authenticator.assert(), &attested.public_key, 1).unwrap();
+        assert_eq!(new_count, 2);
+    }
+
+    #[test]
+    fn rejects_tampered_client_data() {
+        let authenticator = Authenticator::new();
+        let mut credential = authenticator.assert();
+        let tampered = json!({
+            "type": "webauthn.get",
+            "challenge": b64(&CHALLENGE),
+            "origin": ORIGIN,
+            "tampered": true,
+        });
+        credential.response.client_data_json = b64(tampered.to_string().as_bytes());
+        assert_eq!(
+            rejection(login(&credential, &authenticator.cose_key(), 0)),
+            "Signature is invalid"
+        );
+
+        credential.response.client_data_json = b64(b"not json");
+        assert_eq!(
+            rejection(login(&credential, &authenticator.cose_key(), 0)),
+            "Malformed client data"
+        );
+    }
+
+    #[test]
+    fn rejects_wrong_origin() {
+        let mut authenticator = Authenticator::new();
+        authenticator.origin = "https://evil.example.com";
+        assert_eq!(
+            rejection(passkeys().verify_registration(&authenticator.register())),
+            "Origin is not allowed"
+        );
+        assert_eq!(
+            rejection(login(&authenticator.assert(), &authenticator.cose_key(), 0)),
+            "Origin is not allowed"
+        );
+    }
+
+    #[test]
+    fn rejects_wrong_rp_id_hash() {
+        let mut authenticator = Authenticator::new();
+        authenticator.rp_id = "evil.example.com";
+        assert_eq!(
+            rejection(passkeys().verify_registration(&authenticator.register())),
+            "Passkey belongs to a different relying party"
+        );
+        assert_eq!(
+            rejection(login(&authenticator.assert(), &authenticator.cose_key(), 0)),
+            "Passkey belongs to a different relying party"
+        );
+    }
+
+    #[test]
+    fn rejects_missing_user_verification() {
+        let mut authenticator = Authenticator::new();
+        authenticator.flags = FLAG_USER_PRESENT;
+        assert_eq!(
+            rejection(passkeys().verify_registration(&authenticator.register())),
+            "User presence and verification are required"
+        );
+        assert_eq!(
+            rejection(login(&authenticator.assert(), &authenticator.cose_key(), 0)),
+            "User presence and verification are required"
+        );
+    }
+
+    #[test]
+    fn rejects_sign_count_regression() {
+        let mut authenticator = Authenticator::new();
+        let public_key = authenticator.cose_key();
+        authenticator.sign_count = 5;
+        const CLONED: &str =
+            "Signature counter did not increase; the authenticator may have been cloned";
+        assert_eq!(
+            rejection(login(&authenticator.assert(), &public_key, 5)),
+            CLONED
+        );
+        assert_eq!(
+            rejection(login(&authenticator.assert(), &public_key, 9)),
+            CLONED
+        );
+        assert_eq!(login(&authenticator.assert(), &public_key, 4).unwrap(), 5);
+
+        // Authenticators without a counter always report 0.
+        authenticator.sign_count = 0;
+        assert_eq!(login(&authenticator.assert(), &public_key, 0).unwrap(), 0);
+        assert_eq!(
+            rejection(login(&authenticator.assert(), &public_key, 3)),
+            CLONED
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_authenticator_data() {
+        let authenticator = Authenticator::new();
+        const MALFORMED: &str = "Malformed authenticator data";
+
+        let data = authenticator.authenticator_data(false);
+        let credential = authenticator.assert_with(&data[..36]);
+        assert_eq!(
+            rejection(login(&credential, &authenticator.cose_key(), 0)),
+            MALFORMED
+        );
+
+        // Cut inside the credential id, then inside the COSE key.
+        let attested = authenticator.authenticator_data(true);
+        for len in [37 + 17, 37 + 18 + 8, attested.len() - 1] {
+            assert_eq!(
+                rejection(AuthenticatorData::parse(&attested[..len])),
+                MALFORMED
+            );
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use crate::config::AppConfig;
+use crate::services::email_verification::EmailVerifier;
+use crate::services::keys::KeyRing;
+use crate::services::login_throttle::LoginThrottle;
+use crate::services::magic_link::MagicLinks;
+use crate::services::mailer::Outbox;
+use crate::services::mfa::Mfa;
+use crate::services::password_hash::PasswordHasher;
+use crate::services::password_policy::PasswordPolicy;
+use crate::services::password_reset::PasswordResetter;
+use crate::services::rate_limit::RateLimiter;
+use crate::services::token_version::TokenVersions;
+use crate::services::webauthn::Passkeys;
+use axum::extract::FromRef;
+use sqlx::{Pool, Postgres};
+use std::sync::Arc;
+
+// Shared application state, cloned into every handler via `State<AppState>`.
+// Everything here is cheap to clone (pool handles and `Arc`s).
+#[derive(Clone)]
+pub struct AppState {
+    pub db: Pool<Postgres>,
+    pub config: Arc<AppConfig>,
+    pub keyring: Arc<KeyRing>,
+    pub password_policy: Arc<PasswordPolicy>,
+    pub password_hasher: Arc<PasswordHasher>,
+    pub login_throttle: Arc<LoginThrottle>,
+    pub rate_limiter: Arc<RateLimiter>,
+    pub outbox: Arc<Outbox>,
+    pub email_verifier: Arc<EmailVerifier>,
+    pub password_resetter: Arc<PasswordResetter>,
+    pub magic_links: Arc<MagicLinks>,
+    pub mfa: Arc<Mfa>,
+    pub passkeys: Arc<Passkeys>,
+    pub token_versions: Arc<TokenVersions>,
+}
+
+// Allow handlers to extract just the part of the state they need, e.g. `State<Pool<Postgres>>`.
+impl FromRef<AppState> for Pool<Postgres> {
+    fn from_ref(state: &AppState) -> Self {
+        state.db.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<AppConfig> {
+    fn from_ref(state: &AppState) -> Self {
+        state.config.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<KeyRing> {
+    fn from_ref(state: &AppState) -> Self {
+        state.keyring.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<PasswordPolicy> {
+    fn from_ref(state: &AppState) -> Self {
+        state.password_policy.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<PasswordHasher> {
+    fn from_ref(state: &AppState) -> Self {
+        state.password_hasher.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<LoginThrottle> {
+    fn from_ref(state: &AppState) -> Self {
+        state.login_throttle.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<RateLimiter> {
+    fn from_ref(state: &AppState) -> Self {
+        state.rate_limiter.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<EmailVerifier> {
+    fn from_ref(state: &AppState) -> Self {
+        state.email_verifier.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<Outbox> {
+    fn from_ref(state: &AppState) -> Self {
+        state.outbox.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<PasswordResetter> {
+    fn from_ref(state: &AppState) -> Self {
+        state.password_resetter.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<MagicLinks> {
+    fn from_ref(state: &AppState) -> Self {
+        state.magic_links.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<Mfa> {
+    fn from_ref(state: &AppState) -> Self {
+        state.mfa.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<Passkeys> {
+    fn from_ref(state: &AppState) -> Self {
+        state.passkeys.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<TokenVersions> {
+    fn from_ref(state: &AppState) -> Self {
+        state.token_versions.clone()
+    }
+}
@@ -1,4 +1,12 @@
+use crate::dtos::common::ApiResponse;
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use jsonwebtoken::errors::ErrorKind;
 use serde::Serialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::fmt;
 
 #[derive(Debug, Clone, Serialize)]
 pub struct ApiError {
@@ -13,19 +21,18 @@ impl ApiError {
 }
 
 // Common error constants
-pub const INVALID_CREDENTIALS: ApiError = ApiError::new(
-    "INVALID_CREDENTIALS",
-    "Email or password is incorrect",
-);
+pub const INVALID_CREDENTIALS: ApiError =
+    ApiError::new("INVALID_CREDENTIALS", "Email or password is incorrect");
 
-pub const UNAUTHORIZED: ApiError = ApiError::new(
-    "UNAUTHORIZED",
-    "Authorization token is missing or invalid",
-);
+pub const UNAUTHORIZED: ApiError =
+    ApiError::new("UNAUTHORIZED", "Authorization token is missing or invalid");
 
-pub const VALIDATION_FAILED: ApiError = ApiError::new(
-    "VALIDATION_FAILED",
-    "One or more fields failed validation",
+pub const VALIDATION_FAILED: ApiError =
+    ApiError::new("VALIDATION_FAILED", "One or more fields failed validation");
+
+pub const INVALID_REQUEST_BODY: ApiError = ApiError::new(
+    "INVALID_REQUEST_BODY",
+    "The request body could not be parsed",
 );
 
 pub const USER_ALREADY_EXISTS: ApiError = ApiError::new(
@@ -33,9 +40,297 @@ pub const USER_ALREADY_EXISTS: ApiError = ApiError::new(
     "A user with these credentials already exists",
 );
 
-pub const INTERNAL_SERVER_ERROR: ApiError = ApiError::new(
-    "INTERNAL_SERVER_ERROR",
-    "An unexpected error occurred",
+pub const ACCOUNT_LOCKED: ApiError = ApiError::new(
+    "ACCOUNT_LOCKED",
+    "Too many failed login attempts, try again later",
+);
+
+pub const RATE_LIMITED: ApiError = ApiError::new(
+    "RATE_LIMITED",
+    "Too many requests, slow down and retry later",
+);
+
+pub const EMAIL_NOT_VERIFIED: ApiError = ApiError::new(
+    "EMAIL_NOT_VERIFIED",
+    "The email address has not been verified yet",
+);
+
+pub const INVALID_OR_EXPIRED_TOKEN: ApiError = ApiError::new(
+    "INVALID_OR_EXPIRED_TOKEN",
+    "The token is invalid, has expired or has already been used",
+);
+
+pub const INVALID_MFA_CODE: ApiError =
+    ApiError::new("INVALID_MFA_CODE", "The authentication code is incorrect");
+
+pub const MFA_ALREADY_ENABLED: ApiError = ApiError::new(
+    "MFA_ALREADY_ENABLED",
+    "Two-factor authentication is already enabled",
+);
+
+pub const MFA_UNAVAILABLE: ApiError = ApiError::new(
+    "MFA_UNAVAILABLE",
+    "Two-factor authentication is not configured on this server",
+);
+
+pub const INVALID_PASSKEY: ApiError =
+    ApiError::new("INVALID_PASSKEY", "The passkey could not be verified");
+
+pub const INTERNAL_SERVER_ERROR: ApiError =
+    ApiError::new("INTERNAL_SERVER_ERROR", "An unexpected error occurred");
+
+pub const SERVICE_UNAVAILABLE: ApiError = ApiError::new(
+    "SERVICE_UNAVAILABLE",
+    "The service is temporarily unavailable, please retry later",
 );
 
+pub const NOT_FOUND: ApiError = ApiError::new("NOT_FOUND", "The requested resource was not found");
+
+/// Error type returned by services and handlers.
+///
+/// Renders as the `ApiResponse` error envelope with the HTTP status in `code`
+/// and the machine-readable `ApiError` code in `error_code`.
+#[derive(Debug)]
+pub enum AppError {
+    InvalidCredentials,
+    /// Missing or malformed `Authorization` header.
+    Unauthorized,
+    /// A token that parsed but must not be accepted (revoked, reused, unknown).
+    InvalidToken(&'static str),
+    /// A single-use token from an emailed link that is unknown, expired or already used.
+    InvalidOneTimeToken(&'static str),
+    /// Correct credentials, but the deployment requires a verified email to log in.
+    EmailNotVerified,
+    /// Wrong TOTP or recovery code for a login challenge.
+    InvalidMfaCode,
+    MfaAlreadyEnabled,
+    /// `MFA_ENCRYPTION_KEY` is not set, so TOTP secrets can be neither stored nor read.
+    MfaUnavailable,
+    /// A passkey response that failed verification (origin, relying party, signature, counter).
+    InvalidPasskey(&'static str),
+    /// Request body that is not valid JSON or does not match the expected shape.
+    InvalidBody(StatusCode, String),
+    /// Request body that failed the DTO's validation rules, as messages per field.
+    Validation(HashMap<String, Vec<String>>),
+    /// Unique constraint violation on `users`, with the colliding field when known.
+    UserAlreadyExists(Option<&'static str>),
+    NotFound(&'static str),
+    /// Too many failed logins for the account or source IP; retry after this many seconds.
+    AccountLocked {
+        retry_after: u64,
+    },
+    /// Over the rate limit of the route group; retry after this many seconds.
+    RateLimited {
+        retry_after: u64,
+    },
+    Database(sqlx::Error),
+    Jwt(jsonwebtoken::errors::Error),
+    PasswordHash(argon2::password_hash::Error),
+    /// Rendering or delivering an email failed.
+    Mail(String),
+    /// Encrypting or decrypting data at rest failed, e.g. after changing the key.
+    Encryption(&'static str),
+}
+
+impl AppError {
+    /// A `Validation` error with a single message on one field.
+    pub fn invalid_field(field: &str, message: &str) -> Self {
+        AppError::Validation(HashMap::from([(
+            field.to_string(),
+            vec![message.to_string()],
+        )]))
+    }
 
+    fn status_and_error(&self) -> (StatusCode, ApiError) {
+        match self {
+            AppError::InvalidCredentials => (StatusCode::UNAUTHORIZED, INVALID_CREDENTIALS),
+            AppError::Unauthorized | AppError::InvalidToken(_) => {
+                (StatusCode::UNAUTHORIZED, UNAUTHORIZED)
+            }
+            AppError::InvalidOneTimeToken(_) => (StatusCode::BAD_REQUEST, INVALID_OR_EXPIRED_TOKEN),
+            AppError::EmailNotVerified => (StatusCode::FORBIDDEN, EMAIL_NOT_VERIFIED),
+            AppError::InvalidMfaCode => (StatusCode::UNAUTHORIZED, INVALID_MFA_CODE),
+            AppError::MfaAlreadyEnabled => (StatusCode::CONFLICT, MFA_ALREADY_ENABLED),
+            AppError::MfaUnavailable => (StatusCode::SERVICE_UNAVAILABLE, MFA_UNAVAILABLE),
+            AppError::InvalidPasskey(_) => (StatusCode::BAD_REQUEST, INVALID_PASSKEY),
+            AppError::InvalidBody(status, _) => (*status, INVALID_REQUEST_BODY),
+            AppError::Validation(_) => (StatusCode::UNPROCESSABLE_ENTITY, VALIDATION_FAILED),
+            AppError::UserAlreadyExists(_) => (StatusCode::CONFLICT, USER_ALREADY_EXISTS),
+            AppError::NotFound(_) => (StatusCode::NOT_FOUND, NOT_FOUND),
+            AppError::AccountLocked { .. } => (StatusCode::TOO_MANY_REQUESTS, ACCOUNT_LOCKED),
+            AppError::RateLimited { .. } => (StatusCode::TOO_MANY_REQUESTS, RATE_LIMITED),
+            AppError::Jwt(e) if is_token_rejection(e) => (StatusCode::UNAUTHORIZED, UNAUTHORIZED),
+            AppError::Database(e) if is_database_unavailable(e) => {
+                (StatusCode::SERVICE_UNAVAILABLE, SERVICE_UNAVAILABLE)
+            }
+            AppError::Database(_)
+            | AppError::Jwt(_)
+            | AppError::PasswordHash(_)
+            | AppError::Mail(_)
+            | AppError::Encryption(_) => (StatusCode::INTERNAL_SERVER_ERROR, INTERNAL_SERVER_ERROR),
+        }
+    }
+
+    // Message shown to the client. Internal details are never exposed.
+    fn message(&self, error: &ApiError) -> String {
+        match self {
+            AppError::InvalidToken(message)
+            | AppError::InvalidOneTimeToken(message)
+            | AppError::InvalidPasskey(message)
+            | AppError::NotFound(message) => message.to_string(),
+            AppError::InvalidBody(_, message) => message.clone(),
+            AppError::AccountLocked { retry_after } => format!(
+                "Too many failed login attempts, try again in {} seconds",
+                retry_after
+            ),
+            _ => error.description.to_string(),
+        }
+    }
+
+    // Per-field messages rendered under `errors`, if any.
+    fn field_errors(&self) -> Option<serde_json::Value> {
+        match self {
+            AppError::UserAlreadyExists(Some(field)) => Some(json!({
+                *field: [format!("This {} is already registered", field)]
+            })),
+            AppError::Validation(errors) => Some(json!(errors)),
+            _ => None,
+        }
+    }
+}
+
+// Problems with the token the client sent, as opposed to failures on our side
+// (bad key material, signing errors).
+fn is_token_rejection(error: &jsonwebtoken::errors::Error) -> bool {
+    !matches!(
+        error.kind(),
+        ErrorKind::InvalidEcdsaKey
+            | ErrorKind::InvalidRsaKey(_)
+            | ErrorKind::RsaFailedSigning
+            | ErrorKind::InvalidKeyFormat
+            | ErrorKind::Crypto(_)
+    )
+}
+
+// The database could not be reached at all, as opposed to a failing query.
+fn is_database_unavailable(error: &sqlx::Error) -> bool {
+    matches!(
+        error,
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut | sqlx::Error::PoolClosed
+    )
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Database(e) => write!(f, "database error: {}", e),
+            AppError::Jwt(e) => write!(f, "jwt error: {}", e),
+            AppError::PasswordHash(e) => write!(f, "password hash error: {}", e),
+            AppError::Mail(e) => write!(f, "mail error: {}", e),
+            AppError::Encryption(e) => write!(f, "encryption error: {}", e),
+            other => {
+                let (_, error) = other.status_and_error();
+                write!(f, "{}", other.message(&error))
+            }
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let (status, error) = self.status_and_error();
+        if status.is_server_error() {
+            eprintln!("❌ {}", self);
+        }
+
+        let message = self.message(&error);
+        let body = match self.field_errors() {
+            Some(errors) => {
+                ApiResponse::error_with_details(status.as_u16(), &error, message, errors)
+            }
+            None => ApiResponse::error_with(status.as_u16(), &error, message),
+        };
+        let mut response = (status, Json(body)).into_response();
+        if let AppError::AccountLocked { retry_after } | AppError::RateLimited { retry_after } =
+            self
+        {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+        }
+        response
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(e: sqlx::Error) -> Self {
+        AppError::Database(e)
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for AppError {
+    fn from(e: jsonwebtoken::errors::Error) -> Self {
+        AppError::Jwt(e)
+    }
+}
+
+impl From<argon2::password_hash::Error> for AppError {
+    fn from(e: argon2::password_hash::Error) -> Self {
+        AppError::PasswordHash(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn render(error: AppError) -> (StatusCode, serde_json::Value) {
+        let response = error.into_response();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn renders_the_documented_error_envelope() {
+        let (status, body) = render(AppError::InvalidCredentials).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            body,
+            json!({
+                "status": "error",
+                "code": 401,
+                "message": "Email or password is incorrect",
+                "error_code": "INVALID_CREDENTIALS",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn lists_field_errors_under_errors() {
+        let fields = HashMap::from([(
+            "email".to_string(),
+            vec!["Must be a valid email address".to_string()],
+        )]);
+        let (status, body) = render(AppError::Validation(fields)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["code"], 422);
+        assert_eq!(body["error_code"], "VALIDATION_FAILED");
+        assert_eq!(
+            body["errors"],
+            json!({"email": ["Must be a valid email address"]})
+        );
+    }
+
+    #[tokio::test]
+    async fn hides_internal_details() {
+        let (status, body) = render(AppError::Mail("smtp.internal refused".to_string())).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body["error_code"], "INTERNAL_SERVER_ERROR");
+        assert_eq!(body["message"], "An unexpected error occurred");
+    }
+}
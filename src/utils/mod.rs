@@ -1,3 +1 @@
 pub mod errors;
-
-